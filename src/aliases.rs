@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::lexer::{self, Operator, SyntaxError, Token, WordPart};
+use crate::parser;
+
+#[derive(Debug, Default, Clone)]
+pub struct Aliases {
+    values: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.values.insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Removes an alias, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.values.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// All the aliases, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.values.iter()
+    }
+
+    /// Replaces the aliases used as command names with the tokens of their values.
+    pub fn expand(&self, tokens: Vec<Token>) -> Result<Vec<Token>, SyntaxError> {
+        self.expand_tokens(tokens, &mut HashSet::new())
+    }
+
+    /// Expands the aliases of some tokens, skipping the ones in `expanding` so an
+    /// alias that refers to itself (directly or through others) doesn't loop forever.
+    fn expand_tokens(&self, tokens: Vec<Token>, expanding: &mut HashSet<String>) -> Result<Vec<Token>, SyntaxError> {
+        let mut result = Vec::with_capacity(tokens.len());
+        let mut command_position = true;
+        let mut tokens = tokens.into_iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            let word = match token {
+                Token::Operator(operator, column) => {
+                    result.push(Token::Operator(operator, column));
+
+                    // Redirections can come before the command, along with their file name
+                    match operator {
+                        Operator::Redirect(..) => result.extend(tokens.next_if(|token| matches!(token, Token::Word(_)))),
+                        _ => command_position = true
+                    }
+                    continue;
+                },
+                Token::Word(word) => word
+            };
+
+            if !command_position || parser::assignment(&word).is_some() {
+                result.push(Token::Word(word));
+                continue;
+            }
+
+            let name = match word.parts.as_slice() {
+                [WordPart::Literal(name)] => name,
+                _ => {
+                    command_position = false;
+                    result.push(Token::Word(word));
+                    continue;
+                }
+            };
+
+            command_position = parser::OPENING_WORDS.contains(&name.as_str());
+
+            let Some(value) = self.get(name).filter(|_| !expanding.contains(name)) else {
+                result.push(Token::Word(word));
+                continue;
+            };
+
+            // Errors in the value of an alias are shown at the column where it was used
+            let column = word.column;
+            let value_tokens = lexer::tokenize(value).map_err(|error| SyntaxError { column, ..error })?;
+
+            expanding.insert(name.clone());
+            let expanded = self.expand_tokens(value_tokens, expanding);
+            expanding.remove(name);
+
+            let start = result.len();
+            for token in expanded? {
+                result.push(match token {
+                    Token::Word(mut word) => {
+                        word.column = column;
+                        Token::Word(word)
+                    },
+                    Token::Operator(operator, _) => Token::Operator(operator, column)
+                });
+            }
+
+            // A value ending in a space makes the next word an alias candidate too,
+            // and so does one that ends with an operator or is empty
+            command_position = value.ends_with(' ') || match result.last() {
+                _ if result.len() == start => true,
+                Some(Token::Operator(operator, _)) => !matches!(operator, Operator::Redirect(..)),
+                _ => false
+            };
+        }
+
+        Ok(result)
+    }
+}
+
+/// Checks that a name can be used for an alias: it can't be empty or contain
+/// spaces, quotes, slashes, expansions or operators.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(|c| c.is_whitespace() || "/\\'\"`$=|&;<>()".contains(c))
+}
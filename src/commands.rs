@@ -1,136 +1,237 @@
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{self, Error, ErrorKind, Write};
-use std::path::{Component, PathBuf, Prefix};
-use std::process::Command;
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf, Prefix, MAIN_SEPARATOR};
+use std::process::{Child, Command};
+use std::rc::Rc;
 use std::{env, fs};
 
-pub fn execute_local_file(path: &mut PathBuf, command: &str, args: Vec<&str>) -> Result<(), ()> {
-    //println!("Trying to locate a file to execute.");
+use crate::aliases::{self, Aliases};
+use crate::completion::{Arguments, Spec, Specs};
+use crate::history::History;
+use crate::jobs::Jobs;
+use crate::parser::Function;
+use crate::shell::Options;
+use crate::signals;
+use crate::variables::{self, Variables};
+use crate::streams::Streams;
+use crate::traps::{Condition, Traps};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Looks for a program in the directories of the search path first, and then in
+/// the current directory.
+pub fn find_program(path: &Path, command: &str, search_path: Option<&str>) -> Option<PathBuf> {
+    if command.contains('/') || command.contains(MAIN_SEPARATOR) {
+        let program = path.join(command);
+        return program.is_file().then_some(program);
+    }
 
-    let executable = path.join(PathBuf::from(command));
-    if !executable.is_file() {
-        return Err(())
+    if let Some(directories) = search_path {
+        for directory in env::split_paths(directories) {
+            for name in program_names(command) {
+                let program = directory.join(name);
+                if is_executable(&program) {
+                    return Some(program);
+                }
+            }
+        }
     }
 
-    let child = Command::new(executable)
-        .args(args)
-        .spawn();
+    let program = path.join(command);
+    program.is_file().then_some(program)
+}
 
-    if let Err(error) = child {
-        let os_error = error.raw_os_error().unwrap();
-        if os_error == 193 || os_error == 8 {
-            println!("{:?} is not an executable file.", command);
-            return Ok(())
-        }
-        
-        println!("Error invoking {:?}: {}", command, error);
-        println!("Type: {:?}", error.kind());
+#[cfg(windows)]
+fn program_names(command: &str) -> Vec<String> {
+    let extensions = env::var("PATHEXT").unwrap_or(String::from(".COM;.EXE;.BAT;.CMD"));
+    let mut names = vec![command.to_owned()];
+    for extension in extensions.split(';').filter(|extension| !extension.is_empty()) {
+        names.push(format!("{command}{}", extension.to_lowercase()));
+    }
+
+    names
+}
+
+#[cfg(not(windows))]
+fn program_names(command: &str) -> Vec<String> {
+    vec![command.to_owned()]
+}
+
+#[cfg(unix)]
+fn is_executable(program: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match program.metadata() {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false
+    }
+}
+
+#[cfg(not(unix))]
+fn is_executable(program: &Path) -> bool {
+    program.is_file()
+}
+
+/// Starts a program without waiting for it. With job control, it's put in a
+/// process group: a new one for the first program of a pipeline (`Some(0)`), and
+/// the one of the first program for the rest. On failure, the error is reported
+/// and the exit status the command should have is returned instead.
+pub fn execute_command(path: &Path, command: &str, args: &[&str], environment: &[(String, String)], group: Option<u32>, io: &mut Streams) -> Result<Child, i32> {
+    let search_path = environment.iter().rev().find(|(name, _)| name == "PATH" || (cfg!(windows) && name.eq_ignore_ascii_case("PATH"))).map(|(_, value)| value.as_str());
+    let Some(executable) = find_program(path, command, search_path) else {
+        errln!(io, "Command '{command}' not found. Type 'help' to show available commands.");
+        return Err(127);
+    };
 
-        return Ok(())
+    let mut child = spawn(&executable, args, path, environment, group, io);
+
+    // Files the system can't run, like scripts without a shebang, are run by
+    // this shell, unless they aren't text
+    if cfg!(unix) && matches!(&child, Err(error) if error.raw_os_error() == Some(8)) && is_text_file(&executable) {
+        let script = executable.to_string_lossy();
+        let script_args: Vec<&str> = [script.as_ref()].into_iter().chain(args.iter().copied()).collect();
+        child = env::current_exe().and_then(|shell| spawn(&shell, &script_args, path, environment, group, io));
     }
 
-    let _exit_status = child.unwrap().wait().unwrap();
-    //println!("Exit status: {}", exit_status);
+    match child {
+        Ok(child) => Ok(child),
+        Err(error) => {
+            if let Some(193 | 8) = error.raw_os_error() {
+                errln!(io, "{:?} is not an executable file.", command);
+                return Err(126);
+            }
 
-    Ok(())
+            errln!(io, "Error invoking {:?}: {}", command, error);
+            Err(126)
+        }
+    }
 }
 
-pub fn execute_command(_path: &mut PathBuf, command: &str, args: Vec<&str>) -> Result<(), ()> {
-    //println!("Trying to execute command.");
-    let child = Command::new(command)
+/// Starts a program with the given environment and streams, in a process group
+/// when one is given.
+pub fn spawn(program: &Path, args: &[&str], path: &Path, environment: &[(String, String)], group: Option<u32>, io: &Streams) -> io::Result<Child> {
+    let mut command = Command::new(program);
+    command
         .args(args)
-        .spawn();
-
-    if let Err(error) = child {
-        if error.kind() == ErrorKind::NotFound {
-            return Err(());
+        .env_clear()
+        .envs(environment.iter().map(|(name, value)| (name, value)))
+        .current_dir(path)
+        .stdin(io.stdin.stdio()?)
+        .stdout(io.stdout.stdio()?)
+        .stderr(io.stderr.stdio()?);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        if let Some(group) = group {
+            command.process_group(group as i32);
         }
 
-        println!("Error invoking {}: {}", command, error);
-        //println!("Type: {:?}", error.kind());
-        return Ok(())
+        // Programs would keep ignoring the signals the shell ignores
+        unsafe {
+            command.pre_exec(|| {
+                crate::signals::restore_defaults();
+                Ok(())
+            });
+        }
     }
-    
-    let _exit_status = child.unwrap().wait().unwrap();
-    //println!("Exit status: {}", exit_status);
 
-    Ok(())
+    #[cfg(not(unix))]
+    let _ = group;
+
+    command.spawn()
+}
+
+/// Checks whether the start of a file looks like text, so it can be run as a script.
+fn is_text_file(file: &Path) -> bool {
+    let mut buffer = [0; 512];
+    match File::open(file).and_then(|mut file| file.read(&mut buffer)) {
+        Ok(read) => !buffer[..read].contains(&0),
+        Err(_) => false
+    }
 }
 
-pub fn make_directory(path: &mut PathBuf, args: Vec<&str>) {
-    if args.len() < 1 {
-        println!("md: There's no name parameter.");
-        return;
+pub fn make_directory(path: &mut Path, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        errln!(io, "md: There's no name parameter.");
+        return 1;
     }
     
     let name = args.join(" ");
 
     let new_path = PathBuf::from(name.clone());
     if new_path.components().count() > 1 {
-        println!("md: Only a single directory can be created at a time.");
-        return;
+        errln!(io, "md: Only a single directory can be created at a time.");
+        return 1;
     }
     
     if new_path.file_name().is_none() || !new_path.starts_with(new_path.file_name().unwrap()) {
-        println!("md: Invalid directory.");
-        return;
+        errln!(io, "md: Invalid directory.");
+        return 1;
     }
 
     let new_path = path.join(new_path);
 
     match fs::create_dir(new_path) {
-        Ok(_) => (),
-        Err(e) => println!("md: There was an error creating the directory: {}", e)
+        Ok(_) => 0,
+        Err(e) => {
+            errln!(io, "md: There was an error creating the directory: {}", e);
+            1
+        }
     }
 }
 
-pub fn create_file(path: &mut PathBuf, args: Vec<&str>) {
-    if args.len() < 1 {
-        println!("touch: There's no name parameter.");
-        return;
+pub fn create_file(path: &mut Path, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        errln!(io, "touch: There's no name parameter.");
+        return 1;
     }
     
     let name = args.join(" ");
 
     let new_path = PathBuf::from(name.clone());
     if new_path.components().count() > 1 {
-        println!("touch: The file name must not contain paths.");
-        return;
+        errln!(io, "touch: The file name must not contain paths.");
+        return 1;
     }
     
     let file_name = new_path.file_name();
     if file_name.is_none() || !new_path.starts_with(file_name.unwrap()) {
-        println!("touch: Invalid file name.");
-        return;
+        errln!(io, "touch: Invalid file name.");
+        return 1;
     }
 
     let new_path = path.join(new_path);
     if new_path.exists() {
-        return;
+        return 0;
     }
 
     match File::create(new_path) {
-        Ok(_) => (),
-        Err(e) => println!("touch: An error ocurred while creating the file: {}", e)
+        Ok(_) => 0,
+        Err(e) => {
+            errln!(io, "touch: An error ocurred while creating the file: {}", e);
+            1
+        }
     }
 }
 
-pub fn move_files(path: &mut PathBuf, args: Vec<&str>) {
+pub fn move_files(path: &mut Path, args: Vec<&str>, io: &mut Streams) -> i32 {
     if args.len() < 2 {
-        if args.len() == 0 {
-            println!("mv: There are no arguments.");
+        if args.is_empty() {
+            errln!(io, "mv: There are no arguments.");
         }
         else {
-            println!("mv: There are not enough arguments.");
+            errln!(io, "mv: There are not enough arguments.");
         }
-        return;
+        return 1;
     }
 
     let mut yes_flag = false;
     let mut no_flag = false;
     let mut rename_flag = false;
-    let mut source_path = PathBuf::new();
-    let mut destination_path = PathBuf::new();
+    let mut paths: Vec<PathBuf> = vec![];
 
     let mut flags = true;
     let mut mandatory_args = 0;
@@ -150,83 +251,113 @@ pub fn move_files(path: &mut PathBuf, args: Vec<&str>) {
             continue;
         }
 
-        if mandatory_args == 0 {
-            source_path = PathBuf::from(arg);
-        }
-        else if mandatory_args == 1 {
-            destination_path = PathBuf::from(arg);
-        }
-
+        paths.push(PathBuf::from(arg));
         mandatory_args += 1;
     }
 
     if mandatory_args < 2 {
         if mandatory_args == 0 {
-            println!("mv: Source and destination arguments weren't provided.");
+            errln!(io, "mv: Source and destination arguments weren't provided.");
         }
         else  {
-            println!("mv: Destination argument wasn't provided.");
+            errln!(io, "mv: Destination argument wasn't provided.");
         }
-        return;
+        return 1;
     }
 
     if yes_flag && no_flag {
-        println!("cp: Opposite arguments -y and -n were provided.");
-        return;
-    }
-
-    if source_path.is_relative() {
-        source_path = path.join(source_path);
+        errln!(io, "mv: Opposite arguments -y and -n were provided.");
+        return 1;
     }
 
+    let mut destination_path = paths.pop().unwrap();
     if destination_path.is_relative() {
         destination_path = path.join(destination_path);
     }
-    
-    if !source_path.exists() {
-        println!("mv: The source doesn't exist.");
-        return;
-    }
 
     if !destination_path.is_dir() {
         if destination_path.exists() {
-            println!("mv: The destination path must be a directory.");
+            errln!(io, "mv: The destination path must be a directory.");
         }
         else {
-            println!("mv: The destination path doesn't exist.");
+            errln!(io, "mv: The destination path doesn't exist.");
         }
-        return;
+        return 1;
     }
 
+    let mut status = 0;
+    for mut source_path in paths {
+        if source_path.is_relative() {
+            source_path = path.join(source_path);
+        }
+
+        if !source_path.exists() {
+            errln!(io, "mv: The source '{}' doesn't exist.", source_path.display());
+            status = 1;
+            continue;
+        }
+
+        if move_source(&source_path, &destination_path, (yes_flag, no_flag, rename_flag), io) != 0 {
+            status = 1;
+        }
+    }
+
+    status
+}
+
+fn move_source(source_path: &PathBuf, destination_path: &Path, flags: (bool, bool, bool), io: &mut Streams) -> i32 {
     if source_path.is_file() {
-        match move_element(&source_path, &destination_path, (yes_flag, no_flag, rename_flag)) {
+        match move_element(source_path, destination_path, flags, io) {
             Ok(amount) => {
                 if amount == 1 {
-                    println!("File moved successfully")
+                    outln!(io, "File moved successfully");
                 }
                 else {
-                    println!("File was not moved")
+                    outln!(io, "File was not moved");
                 }
+
+                0
+            },
+            Err(error) if error.kind() == ErrorKind::Interrupted => {
+                errln!(io, "mv: Interrupted.");
+                130
             },
-            Err(error) => println!("There was an error moving the file: {error}")
+            Err(error) => {
+                errln!(io, "There was an error moving the file: {error}");
+                1
+            }
         }
     }
     else {
-        match move_element(&source_path, &destination_path, (yes_flag, no_flag, rename_flag)) {
+        match move_element(source_path, destination_path, flags, io) {
             Ok(amount) => {
                 if amount == 1 {
-                    println!("{amount} element was moved successfully")
+                    outln!(io, "{amount} element was moved successfully");
                 }
                 else {
-                    println!("{amount} elements were moved successfully")
+                    outln!(io, "{amount} elements were moved successfully");
                 }
+
+                0
+            },
+            Err(error) if error.kind() == ErrorKind::Interrupted => {
+                errln!(io, "mv: Interrupted.");
+                130
             },
-            Err(error) => println!("mv: There was an error: {error}")
+            Err(error) => {
+                errln!(io, "mv: There was an error: {error}");
+                1
+            }
         }
     }
 }
 
-fn move_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, bool)) -> io::Result<i32> {
+fn move_element(source: &PathBuf, destination: &Path, flags: (bool, bool, bool), io: &mut Streams) -> io::Result<i32> {
+    // Control-C stops before the next file, so none is left half done
+    if signals::is_interrupted() {
+        return Err(interrupted());
+    }
+
     let (yes_flag, no_flag, rename_flag) = flags;
 
     let source_name = source.file_name().unwrap();
@@ -236,7 +367,7 @@ fn move_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
     if source.is_file() {
         if *source == destination_name {
             let err = format!("{}: Move to the same location", source.display());
-            return Err(Error::new(ErrorKind::Other, err));
+            return Err(Error::other(err));
         }
 
         if destination_name.exists() {
@@ -248,14 +379,12 @@ fn move_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
             let mut rename_phase = false;
 
             if !yes_flag {
-                println!("The file '{}' exists on the destination.", source.to_str().unwrap());
+                errln!(io, "The file '{}' exists on the destination.", source.to_str().unwrap());
                 loop {
-                    print!("Do you want to replace [y], cancel [n] or rename [r]? ");
-                    io::stdout().flush().unwrap();
-                
-                    let mut value = String::new();
-                    io::stdin().read_line(&mut value).unwrap();
-    
+                    let Some(value) = ask_replace(io)? else {
+                        return Ok(0);
+                    };
+
                     match value.trim() {
                         "y" => break,
                         "n" => return Ok(0),
@@ -286,7 +415,7 @@ fn move_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
 
                     if counter > 10000 {
                         let err = format!("{}: Infinite loop while looking for a valid numbered filename.", destination_name.display());
-                        return Err(Error::new(ErrorKind::Other, err));
+                        return Err(Error::other(err));
                     }
                 }
 
@@ -306,7 +435,7 @@ fn move_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
 
         for element in source.read_dir()? {
             let element = element?;
-            count += move_element(&element.path(), &destination_name, flags)?;
+            count += move_element(&element.path(), &destination_name, flags, io)?;
         }
 
         fs::remove_dir(source)?;
@@ -315,22 +444,41 @@ fn move_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
     Ok(count)
 }
 
-pub fn copy_files(path: &mut PathBuf, args: Vec<&str>) {
+/// The error that stops copying or moving files when Control-C is pressed.
+fn interrupted() -> Error {
+    Error::new(ErrorKind::Interrupted, "Interrupted")
+}
+
+/// Asks what to do with a file that exists on the destination, through the
+/// streams of the command. Returns `None` at the end of the input.
+fn ask_replace(io: &mut Streams) -> io::Result<Option<String>> {
+    let _ = write!(io.stderr, "Do you want to replace [y], cancel [n] or rename [r]? ");
+    let _ = io.stderr.flush();
+
+    let answer = io.stdin.read_line();
+    if !matches!(answer, Ok(Some(_))) {
+        // The question is left in its own line
+        errln!(io, "");
+    }
+
+    answer
+}
+
+pub fn copy_files(path: &mut Path, args: Vec<&str>, io: &mut Streams) -> i32 {
     if args.len() < 2 {
-        if args.len() == 0 {
-            println!("cp: There are no arguments.");
+        if args.is_empty() {
+            errln!(io, "cp: There are no arguments.");
         }
         else {
-            println!("cp: There are not enough arguments.");
+            errln!(io, "cp: There are not enough arguments.");
         }
-        return;
+        return 1;
     }
 
     let mut yes_flag = false;
     let mut no_flag = false;
     let mut rename_flag = false;
-    let mut source_path = PathBuf::new();
-    let mut destination_path = PathBuf::new();
+    let mut paths: Vec<PathBuf> = vec![];
 
     let mut flags = true;
     let mut mandatory_args = 0;
@@ -350,83 +498,113 @@ pub fn copy_files(path: &mut PathBuf, args: Vec<&str>) {
             continue;
         }
 
-        if mandatory_args == 0 {
-            source_path = PathBuf::from(arg);
-        }
-        else if mandatory_args == 1 {
-            destination_path = PathBuf::from(arg);
-        }
-
+        paths.push(PathBuf::from(arg));
         mandatory_args += 1;
     }
 
     if mandatory_args < 2 {
         if mandatory_args == 0 {
-            println!("cp: Source and destination arguments weren't provided.");
+            errln!(io, "cp: Source and destination arguments weren't provided.");
         }
         else  {
-            println!("cp: Destination argument wasn't provided.");
+            errln!(io, "cp: Destination argument wasn't provided.");
         }
-        return;
+        return 1;
     }
 
     if yes_flag && no_flag {
-        println!("cp: Opposite arguments -y and -n were provided.");
-        return;
-    }
-
-    if source_path.is_relative() {
-        source_path = path.join(source_path);
+        errln!(io, "cp: Opposite arguments -y and -n were provided.");
+        return 1;
     }
 
+    let mut destination_path = paths.pop().unwrap();
     if destination_path.is_relative() {
         destination_path = path.join(destination_path);
     }
-    
-    if !source_path.exists() {
-        println!("cp: The source doesn't exist.");
-        return;
-    }
 
     if !destination_path.is_dir() {
         if destination_path.exists() {
-            println!("cp: The destination path must be a directory.");
+            errln!(io, "cp: The destination path must be a directory.");
         }
         else {
-            println!("cp: The destination path doesn't exist.");
+            errln!(io, "cp: The destination path doesn't exist.");
         }
-        return;
+        return 1;
     }
 
+    let mut status = 0;
+    for mut source_path in paths {
+        if source_path.is_relative() {
+            source_path = path.join(source_path);
+        }
+
+        if !source_path.exists() {
+            errln!(io, "cp: The source '{}' doesn't exist.", source_path.display());
+            status = 1;
+            continue;
+        }
+
+        if copy_source(&source_path, &destination_path, (yes_flag, no_flag, rename_flag), io) != 0 {
+            status = 1;
+        }
+    }
+
+    status
+}
+
+fn copy_source(source_path: &PathBuf, destination_path: &Path, flags: (bool, bool, bool), io: &mut Streams) -> i32 {
     if source_path.is_file() {
-        match copy_element(&source_path, &destination_path, (yes_flag, no_flag, rename_flag)) {
+        match copy_element(source_path, destination_path, flags, io) {
             Ok(amount) => {
                 if amount == 1 {
-                    println!("File copied successfully")
+                    outln!(io, "File copied successfully");
                 }
                 else {
-                    println!("File was not copied")
+                    outln!(io, "File was not copied");
                 }
+
+                0
+            },
+            Err(error) if error.kind() == ErrorKind::Interrupted => {
+                errln!(io, "cp: Interrupted.");
+                130
             },
-            Err(error) => println!("There was an error copying the file: {error}")
+            Err(error) => {
+                errln!(io, "There was an error copying the file: {error}");
+                1
+            }
         }
     }
     else {
-        match copy_element(&source_path, &destination_path, (yes_flag, no_flag, rename_flag)) {
+        match copy_element(source_path, destination_path, flags, io) {
             Ok(amount) => {
                 if amount == 1 {
-                    println!("{amount} element was copied successfully")
+                    outln!(io, "{amount} element was copied successfully");
                 }
                 else {
-                    println!("{amount} elements were copied successfully")
+                    outln!(io, "{amount} elements were copied successfully");
                 }
+
+                0
+            },
+            Err(error) if error.kind() == ErrorKind::Interrupted => {
+                errln!(io, "cp: Interrupted.");
+                130
             },
-            Err(error) => println!("cp: There was an error: {error}")
+            Err(error) => {
+                errln!(io, "cp: There was an error: {error}");
+                1
+            }
         }
     }
 }
 
-fn copy_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, bool)) -> std::io::Result<i32> {
+fn copy_element(source: &PathBuf, destination: &Path, flags: (bool, bool, bool), io: &mut Streams) -> std::io::Result<i32> {
+    // Control-C stops before the next file, so none is left half done
+    if signals::is_interrupted() {
+        return Err(interrupted());
+    }
+
     let (yes_flag, no_flag, rename_flag) = flags;
 
     let source_name = source.file_name().unwrap();
@@ -436,7 +614,7 @@ fn copy_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
     if source.is_file() {
         if *source == destination_name {
             let err = format!("{}: Copy on the same location", source.display());
-            return Err(Error::new(ErrorKind::Other, err));
+            return Err(Error::other(err));
         }
 
         if destination_name.exists() {
@@ -448,14 +626,12 @@ fn copy_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
             let mut rename_phase = false;
 
             if !yes_flag {
-                println!("The file '{}' exists on the destination.", source.to_str().unwrap());
+                errln!(io, "The file '{}' exists on the destination.", source.to_str().unwrap());
                 loop {
-                    print!("Do you want to replace [y], cancel [n] or rename [r]? ");
-                    io::stdout().flush().unwrap();
-                
-                    let mut value = String::new();
-                    io::stdin().read_line(&mut value).unwrap();
-    
+                    let Some(value) = ask_replace(io)? else {
+                        return Ok(0);
+                    };
+
                     match value.trim() {
                         "y" => break,
                         "n" => return Ok(0),
@@ -486,7 +662,7 @@ fn copy_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
 
                     if counter > 10000 {
                         let err = format!("{}: Infinite loop while looking for a valid numbered filename.", destination_name.display());
-                        return Err(Error::new(ErrorKind::Other, err));
+                        return Err(Error::other(err));
                     }
                 }
 
@@ -505,17 +681,17 @@ fn copy_element(source: &PathBuf, destination: &PathBuf, flags: (bool, bool, boo
 
         for element in source.read_dir()? {
             let element = element?;
-            count += copy_element(&element.path(), &destination_name, flags)?;
+            count += copy_element(&element.path(), &destination_name, flags, io)?;
         }
     }
 
     Ok(count)
 }
 
-pub fn remove_element(path: &mut PathBuf, args: Vec<&str>) {
-    if args.len() < 1 {
-        println!("rm: There are no parameters.");
-        return;
+pub fn remove_element(path: &mut Path, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        errln!(io, "rm: There are no parameters.");
+        return 1;
     }
 
     let mut recursive = false;
@@ -537,55 +713,68 @@ pub fn remove_element(path: &mut PathBuf, args: Vec<&str>) {
 
     // Still in flags mode - never got a path
     if flags {
-        println!("rm: There's no path parameter.");
-        return;
+        errln!(io, "rm: There's no path parameter.");
+        return 1;
+    }
+
+    let mut status = 0;
+    for dest_path in dest_path {
+        if remove_path(path, &path.join(dest_path), recursive, io).is_err() {
+            status = 1;
+        }
     }
 
-    let dest_path = path.join(PathBuf::from(dest_path.join(" ")));
+    status
+}
 
-    if path.starts_with(&dest_path) {
-        println!("rm: The current working directory is inside of the one being removed.");
-        return;
+fn remove_path(path: &Path, dest_path: &Path, recursive: bool, io: &mut Streams) -> Result<(), ()> {
+    if path.starts_with(dest_path) {
+        errln!(io, "rm: The current working directory is inside of the one being removed.");
+        return Err(());
     }
     
-    if !path.exists() {
-        println!("rm: The location doesn't exist.");
-        return;
+    if dest_path.symlink_metadata().is_err() {
+        errln!(io, "rm: The location '{}' doesn't exist.", dest_path.display());
+        return Err(());
     }
 
-    if dest_path.is_file() {
-        match fs::remove_file(dest_path) {
-            Ok(_) => (),
-            Err(e) => println!("rm: The file couldn't be removed: {}", e)
-        }
-        return;
+    let result = if dest_path.is_file() {
+        fs::remove_file(dest_path).map_err(|e| format!("The file couldn't be removed: {e}"))
     }
-
-    if recursive {
-        match fs::remove_dir_all(dest_path) {
-            Ok(_) => (),
-            Err(e) => println!("rm: The tree couldn't be removed: {}", e)
-        }
+    else if recursive {
+        fs::remove_dir_all(dest_path).map_err(|e| format!("The tree couldn't be removed: {e}"))
     }
     else {
-        match fs::remove_dir(dest_path) {
-            Ok(_) => (),
-            Err(e) => println!("rm: The directory couldn't be removed: {}", e)
-        }
-    }
+        fs::remove_dir(dest_path).map_err(|e| format!("The directory couldn't be removed: {e}"))
+    };
+
+    result.map_err(|error| errln!(io, "rm: {error}"))
 }
 
-pub fn change_directory(path: &mut PathBuf, args: Vec<&str>) {
-    if args.len() < 1 {
-        println!("cd: There's no path parameter.");
-        return;
-    }
-    
-    let new_path = args.join(" ");
+/// Changes the current directory, keeping it in `PWD` and the previous one in
+/// `OLDPWD`. `cd -` goes back to the previous one and shows it.
+pub fn change_directory(path: &mut PathBuf, variables: &mut Variables, args: Vec<&str>, home: Option<&str>, io: &mut Streams) -> i32 {
+    let back = args == ["-"];
+    let new_path = match (args.is_empty(), home) {
+        _ if back => match variables.get("OLDPWD") {
+            Some(previous) => previous.to_owned(),
+            None => {
+                errln!(io, "cd: There's no previous directory.");
+                return 1;
+            }
+        },
+        (false, _) => args.join(" "),
+        (true, Some(home)) => home.to_owned(),
+        (true, None) => {
+            errln!(io, "cd: The home directory couldn't be determined.");
+            return 1;
+        }
+    };
+
     let moving_path = path.join(PathBuf::from(new_path));
     if !moving_path.exists() || !moving_path.is_dir() {
-        println!("cd: Directory doesn't exist.");
-        return;
+        errln!(io, "cd: Directory doesn't exist.");
+        return 1;
     }
 
     let canonical = moving_path.canonicalize().unwrap();
@@ -609,18 +798,30 @@ pub fn change_directory(path: &mut PathBuf, args: Vec<&str>) {
     }
 
     match env::set_current_dir(final_path.clone()) {
-        Err(e) => println!("cd: There was an error while changing directories: {}", e),
+        Err(e) => {
+            errln!(io, "cd: There was an error while changing directories: {}", e);
+            1
+        },
         _ => {
+            if back {
+                outln!(io, "{}", final_path.display());
+            }
+
+            variables.set("OLDPWD", &path.to_string_lossy());
+            variables.export("OLDPWD");
+            variables.set("PWD", &final_path.to_string_lossy());
+            variables.export("PWD");
             *path = final_path;
+            0
         }
-    };
+    }
 }
 
-pub fn list_elements(path: &mut PathBuf) {
+pub fn list_elements(path: &mut Path, io: &mut Streams) -> i32 {
     let elements = path.read_dir();
     if let Err(error) = elements {
-        println!("ls: There was an error listing the elements: {}", error);
-        return;
+        errln!(io, "ls: There was an error listing the elements: {}", error);
+        return 1;
     }
 
     let mut files: Vec<fs::DirEntry> = vec![];
@@ -659,7 +860,7 @@ pub fn list_elements(path: &mut PathBuf) {
         _ => format!(" (including {errors} errors)")
     };
 
-    println!("\nContents of {:?}{}:", location_name, error_text);
+    outln!(io, "\nContents of {:?}{}:", location_name, error_text);
 
 
     for directory in directories {
@@ -669,7 +870,7 @@ pub fn list_elements(path: &mut PathBuf) {
             if metadata.is_symlink() {"<dir link>"} 
             else {"<dir>"};
         
-        println!(" {file_type:11} {file_name}");
+        outln!(io, " {file_type:11} {file_name}");
     }
 
     for file in files {
@@ -681,10 +882,12 @@ pub fn list_elements(path: &mut PathBuf) {
 
         let size = format_file_length(metadata.len());
         
-        println!(" {file_type:11} {file_name} - ({size})");
+        outln!(io, " {file_type:11} {file_name} - ({size})");
     }
 
-    println!("");
+    outln!(io);
+
+    if errors > 0 { 1 } else { 0 }
 }
 
 fn format_file_length(length: u64) -> String {
@@ -722,117 +925,1066 @@ fn format_file_length(length: u64) -> String {
     }
 }
 
-pub fn help_command(args: Vec<&str>) {
-    if args.len() == 0 {
-        println!("");
-        println!("General commands:");
-        println!("cd            Changes the current directory to the one specified");
-        println!("cp            Copies an element to another location");
-        println!("help          Shows the available commands");
-        println!("ls            Shows all elements in a directory");
-        println!("md            Creates a directory");
-        println!("mv            Moves an element to another location");
-        println!("touch         Creates a new file");
-        println!("rm            Removes an element");
-        println!("version       Shows the version information");
-        println!("exit          Exits the shell");
-        println!("");
+pub fn set_options(options: &mut Options, variables: &Variables, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        for (name, variable) in variables.iter() {
+            outln!(io, "{name}={}", quote(&variable.value));
+        }
+        return 0;
+    }
+
+    if args == ["-o"] {
+        for (name, value) in options.list() {
+            outln!(io, "{name:13} {}", if value { "on" } else { "off" });
+        }
+        return 0;
+    }
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let enable = match flag {
+            "-o" => true,
+            "+o" => false,
+            _ => {
+                errln!(io, "set: Unknown argument '{flag}'.");
+                return 1;
+            }
+        };
+
+        match args.next() {
+            Some(option) => {
+                if !options.set(option, enable) {
+                    errln!(io, "set: Unknown option '{option}'.");
+                    return 1;
+                }
+            },
+            None => {
+                errln!(io, "set: There's no option name after '{flag}'.");
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+pub fn export_variables(variables: &mut Variables, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        for (name, variable) in variables.iter().filter(|(_, variable)| variable.exported) {
+            outln!(io, "export {name}={}", quote(&variable.value));
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None)
+        };
+
+        if !variables::is_valid_name(name) {
+            errln!(io, "export: '{name}' is not a valid variable name.");
+            status = 1;
+            continue;
+        }
+
+        if let Some(value) = value {
+            variables.set(name, value);
+        }
+
+        variables.export(name);
+    }
+
+    status
+}
+
+pub fn unset_variables(variables: &mut Variables, functions: &mut BTreeMap<String, Rc<Function>>, args: Vec<&str>, io: &mut Streams) -> i32 {
+    let (remove_functions, names) = match args.split_first() {
+        Some((&"-f", names)) => (true, names),
+        Some((&"-v", names)) => (false, names),
+        _ => (false, args.as_slice())
+    };
+
+    if names.is_empty() {
+        errln!(io, "unset: There's no name parameter.");
+        return 1;
+    }
+
+    for name in names {
+        match remove_functions {
+            true => functions.remove(*name),
+            false => {
+                variables.unset(name);
+                None
+            }
+        };
+    }
+
+    0
+}
+
+pub fn declare_locals(variables: &mut Variables, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        errln!(io, "local: There's no name parameter.");
+        return 1;
+    }
+
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None)
+        };
+
+        if !variables::is_valid_name(name) {
+            errln!(io, "local: '{name}' is not a valid variable name.");
+            return 1;
+        }
+
+        if !variables.make_local(name) {
+            errln!(io, "local: Can only be used in a function.");
+            return 1;
+        }
+
+        if let Some(value) = value {
+            variables.set(name, value);
+        }
+    }
+
+    0
+}
+
+pub fn list_functions(functions: &BTreeMap<String, Rc<Function>>, io: &mut Streams) -> i32 {
+    for name in functions.keys() {
+        outln!(io, "{name}");
+    }
+
+    0
+}
+
+pub fn history_command(history: &mut History, args: Vec<&str>, io: &mut Streams) -> i32 {
+    let result = match args.as_slice() {
+        [] => {
+            show_history(history, history.entries().len(), io);
+            return 0;
+        },
+        ["-c"] => history.clear(),
+        ["-d", number] => match number.parse() {
+            Ok(number) => match history.remove(number) {
+                Ok(true) => Ok(()),
+                Ok(false) => {
+                    errln!(io, "history: There's no entry {number}.");
+                    return 1;
+                },
+                Err(error) => Err(error)
+            },
+            Err(_) => {
+                errln!(io, "history: '{number}' is not a valid entry number.");
+                return 1;
+            }
+        },
+        [count] if !count.starts_with('-') => match count.parse() {
+            Ok(count) => {
+                show_history(history, count, io);
+                return 0;
+            },
+            Err(_) => {
+                errln!(io, "history: '{count}' is not a valid number.");
+                return 1;
+            }
+        },
+        ["-d"] => {
+            errln!(io, "history: There's no entry number after '-d'.");
+            return 1;
+        },
+        [flag, ..] if flag.starts_with('-') && !matches!(*flag, "-c" | "-d") => {
+            errln!(io, "history: Unknown argument '{flag}'.");
+            return 1;
+        },
+        _ => {
+            errln!(io, "history: Too many arguments.");
+            return 1;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            errln!(io, "history: Error saving the history: {error}");
+            1
+        }
+    }
+}
+
+/// Shows the jobs, with their process ids with `-l` or only their process groups with `-p`.
+pub fn list_jobs(jobs: &mut Jobs, args: Vec<&str>, io: &mut Streams) -> i32 {
+    let (flags, specs): (Vec<&str>, Vec<&str>) = args.into_iter().partition(|arg| arg.starts_with('-'));
+    let (mut pids, mut groups) = (false, false);
+    for flag in flags {
+        match flag {
+            "-l" => pids = true,
+            "-p" => groups = true,
+            _ => {
+                errln!(io, "jobs: Unknown argument '{flag}'.");
+                return 1;
+            }
+        }
+    }
+
+    // The jobs that finished are shown one last time
+    let finished = jobs.update();
+    if !groups && specs.is_empty() {
+        for line in finished {
+            outln!(io, "{line}");
+        }
+    }
+
+    let ids = match specs.is_empty() {
+        true => jobs.ids(),
+        false => match specs.iter().map(|spec| jobs.find(spec)).collect::<Result<Vec<_>, _>>() {
+            Ok(ids) => ids,
+            Err(error) => {
+                errln!(io, "jobs: {error}");
+                return 1;
+            }
+        }
+    };
+
+    for id in ids {
+        match groups {
+            true => outln!(io, "{}", jobs.group(id).unwrap_or_default()),
+            false => outln!(io, "{}", jobs.describe(id, pids).unwrap_or_default())
+        }
+    }
+
+    0
+}
+
+/// Continues a stopped or background job in the foreground (`fg`), or continues
+/// stopped jobs in the background (`bg`). The current job is used by default.
+pub fn resume_jobs(jobs: &mut Jobs, command: &str, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if !jobs.is_control() {
+        errln!(io, "{command}: There's no job control in this shell.");
+        return 1;
+    }
+
+    let foreground = command == "fg";
+    if foreground && args.len() > 1 {
+        errln!(io, "fg: Too many arguments.");
+        return 1;
+    }
+
+    if jobs.count() == 0 {
+        errln!(io, "{command}: There are no jobs.");
+        return 1;
+    }
+
+    let specs = if args.is_empty() { vec!["%+"] } else { args };
+    let mut status = 0;
+    for spec in specs {
+        status = match jobs.find(spec) {
+            Ok(id) => jobs.resume(id, foreground, io),
+            Err(error) => {
+                errln!(io, "{command}: {error}");
+                1
+            }
+        };
+    }
+
+    status
+}
+
+/// Removes jobs from the table without stopping them: the current one, the
+/// ones given, or all of them with `-a`.
+pub fn disown_jobs(jobs: &mut Jobs, args: Vec<&str>, io: &mut Streams) -> i32 {
+    let ids = match args.as_slice() {
+        ["-a"] => jobs.ids(),
+        [] if jobs.count() == 0 => {
+            errln!(io, "disown: There are no jobs.");
+            return 1;
+        },
+        [] => vec![jobs.find("%+").unwrap_or_default()],
+        specs => match specs.iter().map(|spec| jobs.find(spec)).collect::<Result<Vec<_>, _>>() {
+            Ok(ids) => ids,
+            Err(error) => {
+                errln!(io, "disown: {error}");
+                return 1;
+            }
+        }
+    };
+
+    for id in ids {
+        jobs.disown(id);
+    }
+
+    0
+}
+
+/// Waits until jobs finish, returning the exit status of the last one. Without
+/// arguments, it waits for all of them.
+pub fn wait_jobs(jobs: &mut Jobs, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        for id in jobs.ids() {
+            jobs.wait(id);
+            if let Some(signal) = signals::received() {
+                return 128 + signal;
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let id = match arg.parse::<i32>() {
+            Ok(pid) if !arg.starts_with('%') => jobs.find_process(pid).ok_or_else(|| format!("Process {pid} isn't a job of this shell.")),
+            _ => jobs.find(arg)
+        };
+
+        status = match id {
+            Ok(id) => jobs.wait(id),
+            Err(error) => {
+                errln!(io, "wait: {error}");
+                127
+            }
+        };
 
-        return;
+        if let Some(signal) = signals::received() {
+            return 128 + signal;
+        }
+    }
+
+    status
+}
+
+/// Sets the commands run when a signal arrives, the shell exits (`EXIT`) or a
+/// command fails (`ERR`). `-` as the commands removes the trap and an empty
+/// command ignores the signal. `-p` shows the traps and `-l` the signals.
+pub fn trap_command(traps: &mut Traps, args: Vec<&str>, io: &mut Streams) -> i32 {
+    let operands = match args.as_slice() {
+        [] => return show_traps(traps, &[], io),
+        ["-p", specs @ ..] => return show_traps(traps, specs, io),
+        ["-l", ..] => {
+            let signals: Vec<String> = signals::list().map(|(number, name)| format!("{number:2}) {name}")).collect();
+            for row in signals.chunks(5) {
+                outln!(io, "{}", row.join("\t"));
+            }
+            return 0;
+        },
+        ["--", operands @ ..] => operands,
+        [flag, ..] if flag.starts_with('-') && flag.len() > 1 => {
+            errln!(io, "trap: Unknown argument '{flag}'.");
+            return 1;
+        },
+        operands => operands
+    };
+
+    // A single condition, like `trap INT`, removes its trap
+    let (action, specs) = match operands {
+        [] => return show_traps(traps, &[], io),
+        [spec] => ("-", std::slice::from_ref(spec)),
+        [action, specs @ ..] => (*action, specs)
+    };
+
+    let mut status = 0;
+    for spec in specs {
+        let Some(condition) = Condition::parse(spec) else {
+            errln!(io, "trap: '{spec}' is not a valid signal.");
+            status = 1;
+            continue;
+        };
+
+        if action == "-" {
+            traps.reset(condition);
+        }
+        else if let Err(error) = traps.set(condition, action) {
+            errln!(io, "trap: {error}.");
+            status = 1;
+        }
+    }
+
+    status
+}
+
+/// Shows the traps, or the ones of some conditions, as the commands that set them.
+fn show_traps(traps: &Traps, specs: &[&str], io: &mut Streams) -> i32 {
+    let mut status = 0;
+    let conditions = match specs.is_empty() {
+        true => traps.conditions(),
+        false => specs.iter()
+            .filter_map(|spec| {
+                let condition = Condition::parse(spec);
+                if condition.is_none() {
+                    errln!(io, "trap: '{spec}' is not a valid signal.");
+                    status = 1;
+                }
+                condition
+            })
+            .collect()
+    };
+
+    for condition in conditions {
+        if let Some(action) = traps.get(condition) {
+            outln!(io, "trap -- {} {condition}", quote(action));
+        }
+    }
+
+    status
+}
+
+/// Shows the last entries of the history along with their numbers.
+fn show_history(history: &History, count: usize, io: &mut Streams) {
+    let entries = history.entries();
+    let start = entries.len().saturating_sub(count);
+
+    for (index, entry) in entries.iter().enumerate().skip(start) {
+        outln!(io, "{:5}  {entry}", index + 1);
+    }
+}
+
+pub fn complete_command(specs: &mut Specs, args: Vec<&str>, io: &mut Streams) -> i32 {
+    let mut spec = Spec::default();
+    let mut action = None;
+    let mut words: Vec<String> = vec![];
+
+    let split = |value: &str| value.split_whitespace().map(str::to_owned).collect();
+
+    // The words can have flags too, like `--target` in `cargo build --target`
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-p" | "-r" => action = Some(arg),
+            "-s" | "-f" | "-a" | "-w" | "-x" => {
+                let Some(value) = args.next() else {
+                    errln!(io, "complete: There's no value after '{arg}'.");
+                    return 1;
+                };
+
+                match arg {
+                    "-s" => spec.subcommands = split(value),
+                    "-f" => spec.flags = split(value),
+                    "-w" => spec.arguments = Arguments::Choices(split(value)),
+                    "-x" => spec.arguments = Arguments::Output(value.to_owned()),
+                    _ => match Arguments::parse(value) {
+                        Some(arguments) => spec.arguments = arguments,
+                        None => {
+                            errln!(io, "complete: Unknown kind of arguments '{value}' (it can be file, dir, command or none).");
+                            return 1;
+                        }
+                    }
+                }
+            },
+            _ if arg.starts_with('-') => {
+                errln!(io, "complete: Unknown argument '{arg}'.");
+                return 1;
+            },
+            _ => {
+                words.push(arg.to_owned());
+                words.extend(args.by_ref().map(str::to_owned));
+            }
+        }
+    }
+
+    match action {
+        Some("-r") if words.is_empty() => {
+            errln!(io, "complete: There's no command name parameter.");
+            1
+        },
+        Some("-r") => {
+            if specs.remove(&words) {
+                return 0;
+            }
+
+            errln!(io, "complete: There's no spec for '{}'.", words.join(" "));
+            1
+        },
+        Some(_) => {
+            show_specs(specs, &words, io);
+            0
+        },
+        None if words.is_empty() => {
+            show_specs(specs, &words, io);
+            0
+        },
+        None => {
+            specs.set(words, spec);
+            0
+        }
+    }
+}
+
+/// Shows the completion specs of the commands that start with some words, as
+/// the `complete` commands that define them.
+fn show_specs(specs: &Specs, words: &[String], io: &mut Streams) {
+    for (key, spec) in specs.iter().filter(|(key, _)| key.starts_with(words)) {
+        let mut line = String::from("complete");
+
+        if !spec.subcommands.is_empty() {
+            line += &format!(" -s {}", quote(&spec.subcommands.join(" ")));
+        }
+        if !spec.flags.is_empty() {
+            line += &format!(" -f {}", quote(&spec.flags.join(" ")));
+        }
+
+        match &spec.arguments {
+            Arguments::Files => (),
+            Arguments::Directories => line += " -a dir",
+            Arguments::Commands => line += " -a command",
+            Arguments::None => line += " -a none",
+            Arguments::Choices(choices) => line += &format!(" -w {}", quote(&choices.join(" "))),
+            Arguments::Output(command) => line += &format!(" -x {}", quote(command))
+        }
+
+        for word in key {
+            line += " ";
+            line += &quote(word);
+        }
+
+        outln!(io, "{line}");
+    }
+}
+
+pub fn define_aliases(aliases: &mut Aliases, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        for (name, value) in aliases.iter() {
+            outln!(io, "alias {name}={}", quote(value));
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                if !aliases::is_valid_name(name) {
+                    errln!(io, "alias: '{name}' is not a valid alias name.");
+                    status = 1;
+                    continue;
+                }
+
+                aliases.set(name, value);
+            },
+            None => match aliases.get(arg) {
+                Some(value) => outln!(io, "alias {arg}={}", quote(value)),
+                None => {
+                    errln!(io, "alias: '{arg}' doesn't exist.");
+                    status = 1;
+                }
+            }
+        }
+    }
+
+    status
+}
+
+pub fn remove_aliases(aliases: &mut Aliases, args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        errln!(io, "unalias: There's no name parameter.");
+        return 1;
+    }
+
+    if args == ["-a"] {
+        aliases.clear();
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in args {
+        if !aliases.remove(name) {
+            errln!(io, "unalias: '{name}' doesn't exist.");
+            status = 1;
+        }
+    }
+
+    status
+}
+
+/// Evaluates a condition given as arguments, like `test -f file` or `[ "$a" = b ]`,
+/// succeeding when it's true. The `[` form needs a closing `]` as the last argument.
+/// Invalid conditions exit with 2.
+pub fn test_command(path: &Path, command: &str, mut args: Vec<&str>, io: &mut Streams) -> i32 {
+    if command == "[" {
+        if args.last() != Some(&"]") {
+            errln!(io, "[: Missing ']' at the end of the condition.");
+            return 2;
+        }
+        args.pop();
+    }
+
+    match evaluate_test(path, &args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(error) => {
+            errln!(io, "{command}: {error}.");
+            2
+        }
+    }
+}
+
+const BINARY_TESTS: &[&str] = &["=", "==", "!=", "-eq", "-ne", "-lt", "-le", "-gt", "-ge"];
+
+fn evaluate_test(path: &Path, args: &[&str]) -> Result<bool, String> {
+    match *args {
+        [] => Ok(false),
+        [text] => Ok(!text.is_empty()),
+        // Like `test ! = x`, comparing texts comes before negating
+        [left, operator, right] if BINARY_TESTS.contains(&operator) => test_binary(left, operator, right),
+        ["!", ref rest @ ..] => evaluate_test(path, rest).map(|result| !result),
+        [operator, operand] => test_unary(path, operator, operand),
+        [left, operator, right] => test_binary(left, operator, right),
+        _ => Err(format!("Too many arguments, '{}' was not expected", args[3]))
+    }
+}
+
+fn test_unary(path: &Path, operator: &str, operand: &str) -> Result<bool, String> {
+    if operator == "-n" || operator == "-z" {
+        return Ok(operand.is_empty() == (operator == "-z"));
+    }
+
+    let file = path.join(operand);
+    let metadata = file.metadata();
+    let result = match operator {
+        "-e" => metadata.is_ok(),
+        "-f" => metadata.is_ok_and(|metadata| metadata.is_file()),
+        "-d" => metadata.is_ok_and(|metadata| metadata.is_dir()),
+        "-s" => metadata.is_ok_and(|metadata| metadata.len() > 0),
+        "-r" => File::open(&file).is_ok(),
+        "-w" => metadata.is_ok_and(|metadata| !metadata.permissions().readonly()),
+        "-x" => is_executable(&file) || file.is_dir(),
+        "-L" | "-h" => file.symlink_metadata().is_ok_and(|metadata| metadata.file_type().is_symlink()),
+        _ => return Err(format!("Unknown operator '{operator}'"))
+    };
+
+    Ok(result)
+}
+
+fn test_binary(left: &str, operator: &str, right: &str) -> Result<bool, String> {
+    let number = |text: &str| text.trim().parse::<i64>().map_err(|_| format!("'{text}' is not a valid number"));
+
+    let result = match operator {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "-eq" => number(left)? == number(right)?,
+        "-ne" => number(left)? != number(right)?,
+        "-lt" => number(left)? < number(right)?,
+        "-le" => number(left)? <= number(right)?,
+        "-gt" => number(left)? > number(right)?,
+        "-ge" => number(left)? >= number(right)?,
+        _ => return Err(format!("Unknown operator '{operator}'"))
+    };
+
+    Ok(result)
+}
+
+pub fn show_environment(variables: &Variables, io: &mut Streams) -> i32 {
+    for (name, value) in variables.environment() {
+        outln!(io, "{name}={value}");
+    }
+
+    0
+}
+
+/// Quotes a value so it can be written back as a single shell word.
+pub fn quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./:,+@%=".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        return value.to_owned();
+    }
+
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+pub fn show_version(ansi: bool, io: &mut Streams) {
+    let (ftitle, fversion, fauthor, freset) = match ansi {
+        true => ("\x1B[1;31m", "\x1B[33m", "\x1B[36m", "\x1B[0m"),
+        false => ("", "", "", "")
+    };
+
+    outln!(io, "{ftitle}Rust Shell{freset} {fversion}v{VERSION}{freset}");
+    outln!(io, "Created by {fauthor}@FacuA0{freset}\n");
+}
+
+pub fn help_command(args: Vec<&str>, io: &mut Streams) -> i32 {
+    if args.is_empty() {
+        outln!(io);
+        outln!(io, "General commands:");
+        outln!(io, "cd            Changes the current directory to the one specified");
+        outln!(io, "cp            Copies an element to another location");
+        outln!(io, "complete      Shows or changes how the arguments of commands are completed");
+        outln!(io, "help          Shows the available commands");
+        outln!(io, "history       Shows or changes the history of commands");
+        outln!(io, "ls            Shows all elements in a directory");
+        outln!(io, "md            Creates a directory");
+        outln!(io, "mv            Moves an element to another location");
+        outln!(io, "touch         Creates a new file");
+        outln!(io, "rm            Removes an element");
+        outln!(io, "set           Shows the variables or changes the shell options");
+        outln!(io, "version       Shows the version information");
+        outln!(io, "exit          Exits the shell");
+        outln!(io);
+        outln!(io, "Variables:");
+        outln!(io, "env           Shows the variables passed to programs");
+        outln!(io, "export        Passes variables to the programs started by the shell");
+        outln!(io, "unset         Removes variables");
+        outln!(io);
+        outln!(io, "Aliases:");
+        outln!(io, "alias         Defines or shows aliases");
+        outln!(io, "unalias       Removes aliases");
+        outln!(io);
+        outln!(io, "Jobs:");
+        outln!(io, "jobs          Shows the jobs running in the background or stopped");
+        outln!(io, "fg            Continues a job in the foreground");
+        outln!(io, "bg            Continues a stopped job in the background");
+        outln!(io, "disown        Removes jobs from the list without stopping them");
+        outln!(io, "wait          Waits until jobs finish");
+        outln!(io);
+        outln!(io, "Functions:");
+        outln!(io, "functions     Shows the names of the defined functions");
+        outln!(io, "local         Makes variables local to the running function");
+        outln!(io, "return        Leaves the running function");
+        outln!(io);
+        outln!(io, "Scripts:");
+        outln!(io, "source        Runs the commands of a file in the current shell (also '.')");
+        outln!(io, "shift         Removes the first positional parameters");
+        outln!(io, "trap          Runs commands when signals arrive, the shell exits or a command fails");
+        outln!(io);
+        outln!(io, "Loops:");
+        outln!(io, "break         Leaves the running loop");
+        outln!(io, "continue      Goes on with the next iteration of the running loop");
+        outln!(io);
+        outln!(io, "Conditions:");
+        outln!(io, "test          Checks a condition, like comparing texts or numbers (also '[')");
+        outln!(io, "true          Does nothing, successfully (also ':')");
+        outln!(io, "false         Does nothing, unsuccessfully");
+        outln!(io);
+
+        return 0;
     }
 
     let command = args[0];
     match command {
         "cd" => {
-            println!("Command: cd <directory>");
-            println!("Description: Changes the current working directory to the one specified on the argument.");
-            println!("");
-            println!("Arguments:");
-            println!(" - <directory>      The directory where the shell should change.");
-            println!("");
+            outln!(io, "Command: cd [directory | -]");
+            outln!(io, "Description: Changes the current working directory to the one specified on the argument.");
+            outln!(io, "Without arguments, it changes to the home directory. The new directory is kept in PWD, and the previous one in OLDPWD.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [directory]      The directory where the shell should change.");
+            outln!(io, " - [-]              Goes back to the previous directory, and shows it.");
+            outln!(io);
         },
         "cp" => {
-            println!("Command: cp [-y] [-n] [-r] <source> <destination>");
-            println!("Description: Copies a specific source element (either a file or a directory) into a destination directory.");
-            println!("If a directory is specified as a source, it copies it along with its contents. Any existing directory on destination will receive the contents of the directory being copied.");
-            println!("The default behavior when a file is duplicated is to ask the user if it should be replaced, cancelled or renamed.");
-            println!("When the -y flag is used, the command will replace any destination file by default unless the -r flag is used.");
-            println!("");
-            println!("Arguments:");
-            println!(" - <source>         The source element to be copied.");
-            println!(" - <destination>    The destination directory.");
-            println!(" - [-y]             A flag that makes the operation to continue even if there are duplicate elements.");
-            println!(" - [-n]             A flag that cancels the entire operation if a single element is duplicated.");
-            println!(" - [-r]             A flag that indicates that, if an element is duplicated, it should be numbered to avoid conflicts.");
-            println!("");
+            outln!(io, "Command: cp [-y] [-n] [-r] <source>... <destination>");
+            outln!(io, "Description: Copies one or more source elements (either files or directories) into a destination directory.");
+            outln!(io, "If a directory is specified as a source, it copies it along with its contents. Any existing directory on destination will receive the contents of the directory being copied.");
+            outln!(io, "The default behavior when a file is duplicated is to ask the user if it should be replaced, cancelled or renamed.");
+            outln!(io, "When the -y flag is used, the command will replace any destination file by default unless the -r flag is used.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <source>         The source elements to be copied.");
+            outln!(io, " - <destination>    The destination directory.");
+            outln!(io, " - [-y]             A flag that makes the operation to continue even if there are duplicate elements.");
+            outln!(io, " - [-n]             A flag that cancels the entire operation if a single element is duplicated.");
+            outln!(io, " - [-r]             A flag that indicates that, if an element is duplicated, it should be numbered to avoid conflicts.");
+            outln!(io);
         },
         "help" => {
-            println!("Command: help [command]");
-            println!("Description: Shows the available commands when invoked without arguments.");
-            println!("With an argument, it shows the description of a specific built-in shell command.");
-            println!("");
-            println!("Arguments:");
-            println!(" - [command]        A command to be described.");
-            println!("");
+            outln!(io, "Command: help [command]");
+            outln!(io, "Description: Shows the available commands when invoked without arguments.");
+            outln!(io, "With an argument, it shows the description of a specific built-in shell command.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [command]        A command to be described.");
+            outln!(io);
         },
         "ls" => {
-            println!("Command: ls");
-            println!("Description: Lists the files and directories in the current location.");
-            println!("");
+            outln!(io, "Command: ls");
+            outln!(io, "Description: Lists the files and directories in the current location.");
+            outln!(io);
         },
         "md" => {
-            println!("Command: md <directory>");
-            println!("Description: Creates a new directory with the specified name.");
-            println!("");
-            println!("Arguments:");
-            println!(" - <directory>      The directory name to be used.");
-            println!("");
+            outln!(io, "Command: md <directory>");
+            outln!(io, "Description: Creates a new directory with the specified name.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <directory>      The directory name to be used.");
+            outln!(io);
         },
         "mv" => {
-            println!("Command: mv [-y] [-n] [-r] <source> <destination>");
-            println!("Description: Moves a specific source element (either a file or a directory) into a destination directory, removing any original file in the former location.");
-            println!("If a directory is specified as a source, it moves it along with its contents. Any existing directory on destination will receive the contents of the directory being moved.");
-            println!("The default behavior when a file is duplicated is to ask the user if it should be replaced, cancelled or renamed.");
-            println!("When the -y flag is used, the command will replace any destination file by default unless the -r flag is used.");
-            println!("");
-            println!("Arguments:");
-            println!(" - <source>         The source element to be moved.");
-            println!(" - <destination>    The destination directory.");
-            println!(" - [-y]             A flag that makes the operation to continue even if there are duplicate elements.");
-            println!(" - [-n]             A flag that cancels the entire operation if a single element is duplicated.");
-            println!(" - [-r]             A flag that indicates that, if an element is duplicated, it should be numbered to avoid conflicts.");
-            println!("");
+            outln!(io, "Command: mv [-y] [-n] [-r] <source>... <destination>");
+            outln!(io, "Description: Moves one or more source elements (either files or directories) into a destination directory, removing any original file in the former location.");
+            outln!(io, "If a directory is specified as a source, it moves it along with its contents. Any existing directory on destination will receive the contents of the directory being moved.");
+            outln!(io, "The default behavior when a file is duplicated is to ask the user if it should be replaced, cancelled or renamed.");
+            outln!(io, "When the -y flag is used, the command will replace any destination file by default unless the -r flag is used.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <source>         The source elements to be moved.");
+            outln!(io, " - <destination>    The destination directory.");
+            outln!(io, " - [-y]             A flag that makes the operation to continue even if there are duplicate elements.");
+            outln!(io, " - [-n]             A flag that cancels the entire operation if a single element is duplicated.");
+            outln!(io, " - [-r]             A flag that indicates that, if an element is duplicated, it should be numbered to avoid conflicts.");
+            outln!(io);
         },
         "touch" => {
-            println!("Command: touch <file>");
-            println!("Description: Creates a new empty file with the specified name.");
-            println!("");
-            println!("Arguments:");
-            println!(" - <file>           The file name to be used.");
-            println!("");
+            outln!(io, "Command: touch <file>");
+            outln!(io, "Description: Creates a new empty file with the specified name.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <file>           The file name to be used.");
+            outln!(io);
         },
         "rm" => {
-            println!("Command: rm [-r] <element>");
-            println!("Description: Removes the file or directory at the specified location.");
-            println!("If a directory has inner elements, it won't be removed unless the -r flag was used.");
-            println!("");
-            println!("Arguments:");
-            println!(" - <element>        The file or directory to be removed.");
-            println!(" - [-r]             A flag that removes a directory recursively, which includes any internal files and directories in it.");
-            println!("");
+            outln!(io, "Command: rm [-r] <element>...");
+            outln!(io, "Description: Removes the files or directories at the specified locations.");
+            outln!(io, "If a directory has inner elements, it won't be removed unless the -r flag was used.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <element>        The file or directory to be removed.");
+            outln!(io, " - [-r]             A flag that removes a directory recursively, which includes any internal files and directories in it.");
+            outln!(io);
         }
+        "set" => {
+            outln!(io, "Command: set [-o [option]] [+o <option>]");
+            outln!(io, "Description: Shows all the variables when invoked without arguments.");
+            outln!(io, "With -o and no option name, it shows the shell options. Otherwise, it turns options on (-o) and off (+o).");
+            outln!(io);
+            outln!(io, "Options:");
+            outln!(io, " - emacs            Lines are edited with the keys of Emacs, like Ctrl-A and Ctrl-E (the default).");
+            outln!(io, " - failglob         A wildcard that doesn't match any file is an error.");
+            outln!(io, " - ignoreeof        Ctrl-D in an empty line is ignored 10 times in a row (or as many times as IGNOREEOF says) before closing the shell.");
+            outln!(io, " - noglob           Wildcards are never replaced by file names.");
+            outln!(io, " - nullglob         A wildcard that doesn't match any file is removed, instead of kept as it is.");
+            outln!(io, " - pipefail         A pipeline fails when any of its commands fails, instead of only the last one.");
+            outln!(io, " - vi               Lines are edited with the keys of Vi, with Escape going to its normal mode.");
+            outln!(io);
+        },
+        "env" => {
+            outln!(io, "Command: env");
+            outln!(io, "Description: Shows the exported variables, which are the ones passed to the programs started by the shell.");
+            outln!(io, "When invoked with arguments, the env program is run instead.");
+            outln!(io);
+        },
+        "export" => {
+            outln!(io, "Command: export [name[=value]...]");
+            outln!(io, "Description: Marks variables as exported, so they're passed to the programs started by the shell.");
+            outln!(io, "Without arguments, it shows the exported variables.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [name[=value]]   A variable to be exported, optionally with a new value.");
+            outln!(io);
+        },
+        "unset" => {
+            outln!(io, "Command: unset [-f] [-v] <name>...");
+            outln!(io, "Description: Removes the specified variables, or functions if -f is used.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <name>           A variable or function to be removed.");
+            outln!(io, " - [-f]             A flag that removes functions instead of variables.");
+            outln!(io, " - [-v]             A flag that removes variables, which is the default.");
+            outln!(io);
+        },
+        "alias" => {
+            outln!(io, "Command: alias [name[=value]...]");
+            outln!(io, "Description: Defines aliases, which replace the name of a command with their value when it's run.");
+            outln!(io, "Without arguments, it shows all the aliases. With a name alone, it shows that alias.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [name[=value]]   An alias to be defined or shown.");
+            outln!(io);
+        },
+        "unalias" => {
+            outln!(io, "Command: unalias [-a] <name>...");
+            outln!(io, "Description: Removes the specified aliases.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <name>           An alias to be removed.");
+            outln!(io, " - [-a]             A flag that removes all the aliases.");
+            outln!(io);
+        },
+        "complete" => {
+            outln!(io, "Command: complete [-s <words>] [-f <words>] [-a <kind>] [-w <words>] [-x <command>] <name> [word...]");
+            outln!(io, "        complete [-p] [name...] | complete -r <name> [word...]");
+            outln!(io, "Description: Defines how the words after a command (or one of its subcommands) are completed with Tab.");
+            outln!(io, "Without arguments or with -p, it shows the definitions. Definitions are also read from the files in ~/.config/rust-shell/completions.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [-s <words>]     The subcommands of the command.");
+            outln!(io, " - [-f <words>]     The flags of the command.");
+            outln!(io, " - [-a <kind>]      The kind of the other arguments: file (the default), dir, command or none.");
+            outln!(io, " - [-w <words>]     The other arguments are one of these words.");
+            outln!(io, " - [-x <command>]   The other arguments are one of the words written by a command.");
+            outln!(io, " - [-r]             Removes the definitions of a command and its subcommands.");
+            outln!(io, " - <name> [word...] The command, followed by subcommands or a flag that takes a value.");
+            outln!(io);
+        },
+        "history" => {
+            outln!(io, "Command: history [count] [-c] [-d <number>]");
+            outln!(io, "Description: Shows the commands typed before, or the last ones if there's a count, along with their numbers.");
+            outln!(io, "The history is saved in ~/.local/share/rust-shell/history and shared by all sessions.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [count]          The amount of commands to show.");
+            outln!(io, " - [-c]             Removes all the commands.");
+            outln!(io, " - [-d <number>]    Removes the command with a number.");
+            outln!(io);
+        },
+        "jobs" => {
+            outln!(io, "Command: jobs [-l] [-p] [job]...");
+            outln!(io, "Description: Shows the jobs started with '&' or stopped with Control-Z, with their numbers and states.");
+            outln!(io, "The current job is marked with '+', and the previous one with '-'.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [job]            The jobs to show: '%n' is the job n, '%+' the current one, '%-' the previous one,");
+            outln!(io, "                    '%text' the one starting with the text and '%?text' the one containing it.");
+            outln!(io, " - [-l]             Shows the process ids too.");
+            outln!(io, " - [-p]             Shows only the process groups of the jobs.");
+            outln!(io);
+        },
+        "fg" => {
+            outln!(io, "Command: fg [job]");
+            outln!(io, "Description: Continues a job in the foreground, giving it the terminal. Without arguments, it continues the current job.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [job]            The job to continue, like '%1' (see 'help jobs').");
+            outln!(io);
+        },
+        "bg" => {
+            outln!(io, "Command: bg [job]...");
+            outln!(io, "Description: Continues stopped jobs in the background. Without arguments, it continues the current job.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [job]            The jobs to continue, like '%1' (see 'help jobs').");
+            outln!(io);
+        },
+        "disown" => {
+            outln!(io, "Command: disown [-a] [job]...");
+            outln!(io, "Description: Removes jobs from the list without stopping them, so the shell forgets them. Without arguments, it removes the current job.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [job]            The jobs to remove, like '%1' (see 'help jobs').");
+            outln!(io, " - [-a]             Removes all the jobs.");
+            outln!(io);
+        },
+        "wait" => {
+            outln!(io, "Command: wait [job | pid]...");
+            outln!(io, "Description: Waits until jobs finish, and exits with the status of the last one. Without arguments, it waits for all the jobs.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [job]            A job, like '%1' (see 'help jobs').");
+            outln!(io, " - [pid]            The process id of a job, like the one in '$!'.");
+            outln!(io);
+        },
+        "trap" => {
+            outln!(io, "Command: trap [-l] [-p] [commands] [condition]...");
+            outln!(io, "Description: Sets the commands run when a signal arrives (like 'INT', 'SIGTERM' or 15), when the shell exits ('EXIT') or when a command fails ('ERR').");
+            outln!(io, "Failures in the conditions of 'if', 'while' and 'until', in commands followed by '&&' or '||' and inside functions don't run the ERR trap.");
+            outln!(io, "'-' as the commands (or only a condition) removes the trap, and empty commands make the shell and its programs ignore the signal.");
+            outln!(io, "Without arguments, it shows the traps.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [-l]             Shows the signals along with their numbers.");
+            outln!(io, " - [-p]             Shows the traps of the conditions, or all of them.");
+            outln!(io, " - [commands]       The commands to run, usually quoted.");
+            outln!(io, " - [condition]      EXIT, ERR or a signal.");
+            outln!(io);
+        },
+        "functions" => {
+            outln!(io, "Command: functions");
+            outln!(io, "Description: Shows the names of the functions defined with 'name() {{ ... }}' or 'function name {{ ... }}'.");
+            outln!(io, "Functions are removed with 'unset -f <name>'.");
+            outln!(io);
+        },
+        "local" => {
+            outln!(io, "Command: local <name[=value]>...");
+            outln!(io, "Description: Makes variables local to the running function, so their previous values come back when it returns.");
+            outln!(io, "Local variables start empty unless a value is given.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <name[=value]>   A variable to be made local, optionally with a value.");
+            outln!(io);
+        },
+        "return" => {
+            outln!(io, "Command: return [code]");
+            outln!(io, "Description: Leaves the running function, which finishes with the exit code given, or the one of the last command.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [code]           The exit code of the function, from 0 to 255.");
+            outln!(io);
+        },
+        "break" => {
+            outln!(io, "Command: break [levels]");
+            outln!(io, "Description: Leaves the running for, while or until loop.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [levels]         How many nested loops to leave, 1 by default.");
+            outln!(io);
+        },
+        "continue" => {
+            outln!(io, "Command: continue [levels]");
+            outln!(io, "Description: Skips the rest of the body of the running loop and goes on with its next iteration.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [levels]         Which of the nested loops goes on, 1 (the innermost one) by default.");
+            outln!(io);
+        },
+        "source" | "." => {
+            outln!(io, "Command: source <file> [argument...]");
+            outln!(io, "Description: Runs the commands of a file in the current shell, so the variables, functions and aliases it defines stay.");
+            outln!(io, "It can also be written as '.'. The file can be left early with 'return'.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - <file>           The file to be run.");
+            outln!(io, " - [argument]       Positional parameters to be used while the file runs.");
+            outln!(io);
+        },
+        "shift" => {
+            outln!(io, "Command: shift [count]");
+            outln!(io, "Description: Removes the first positional parameters, so $2 becomes $1 and so on.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [count]          How many parameters to remove, 1 by default.");
+            outln!(io);
+        },
+        "test" | "[" => {
+            outln!(io, "Command: test <condition> (or [ <condition> ])");
+            outln!(io, "Description: Succeeds when the condition is true, so it can be used with 'if', 'while', '&&' and '||'.");
+            outln!(io, "Without a condition it fails, and with a single text it succeeds if the text isn't empty. '!' before a condition negates it.");
+            outln!(io);
+            outln!(io, "Conditions:");
+            outln!(io, " - -n <text>        The text isn't empty ('-z' for empty).");
+            outln!(io, " - -e <file>        The file exists ('-f' if it's a file, '-d' a directory, '-L' a link).");
+            outln!(io, " - -s <file>        The file isn't empty ('-r', '-w' and '-x' check if it can be read, written or run).");
+            outln!(io, " - <a> = <b>        The texts are equal ('!=' if they aren't).");
+            outln!(io, " - <a> -eq <b>      The numbers are equal ('-ne', '-lt', '-le', '-gt' and '-ge' compare them too).");
+            outln!(io);
+        },
+        "true" | ":" => {
+            outln!(io, "Command: true (or :)");
+            outln!(io, "Description: Does nothing and succeeds, like in 'while true; do ...; done'. Its arguments are ignored.");
+            outln!(io);
+        },
+        "false" => {
+            outln!(io, "Command: false");
+            outln!(io, "Description: Does nothing and fails with the exit code 1. Its arguments are ignored.");
+            outln!(io);
+        },
         "version" => {
-            println!("Command: version");
-            println!("Description: Prints the current version and author of the shell.");
-            println!("");
+            outln!(io, "Command: version");
+            outln!(io, "Description: Prints the current version and author of the shell.");
+            outln!(io);
         },
         "exit" => {
-            println!("Command: exit");
-            println!("Description: Exits the shell.");
-            println!("");
+            outln!(io, "Command: exit [code]");
+            outln!(io, "Description: Exits the shell with the specified exit code, or with the one of the last command if there's none.");
+            outln!(io);
+            outln!(io, "Arguments:");
+            outln!(io, " - [code]           The exit code of the shell, from 0 to 255.");
+            outln!(io);
         },
         _ => {
-            println!("help: There's no built-in command named '{command}'. Type 'help' to show available commands.");
+            errln!(io, "help: There's no built-in command named '{command}'. Type 'help' to show available commands.");
+            return 1;
         }
     };
+
+    0
 }
\ No newline at end of file
@@ -0,0 +1,499 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::parser;
+use crate::shell::{Shell, BUILTINS};
+use crate::users;
+
+/// The flags of the commands run by the shell that have them.
+const FLAGS: &[(&str, &[&str])] = &[
+    ("cp", &["-n", "-r", "-y"]),
+    ("mv", &["-n", "-r", "-y"]),
+    ("rm", &["-r"]),
+];
+
+/// How the words after a command (or one of its subcommands) are completed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Spec {
+    pub subcommands: Vec<String>,
+    pub flags: Vec<String>,
+    /// What the other words of the command are.
+    pub arguments: Arguments,
+}
+
+/// The kinds of words taken by a command.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Arguments {
+    /// Names of files and directories.
+    #[default]
+    Files,
+    Directories,
+    /// Names of commands.
+    Commands,
+    /// One of some words.
+    Choices(Vec<String>),
+    /// One of the words written by a command, like the branches listed by `git branch`.
+    Output(String),
+    /// Nothing, for commands that don't take other arguments.
+    None,
+}
+
+impl Arguments {
+    /// Reads a kind of argument as it's written in specs: `file`, `dir`, `command` or `none`.
+    pub fn parse(name: &str) -> Option<Arguments> {
+        match name {
+            "file" => Some(Arguments::Files),
+            "dir" => Some(Arguments::Directories),
+            "command" => Some(Arguments::Commands),
+            "none" => Some(Arguments::None),
+            _ => None
+        }
+    }
+}
+
+/// The completion specs of some commands, by the words that lead to them: the name
+/// of the command, then its subcommands, and maybe a flag that takes a value.
+#[derive(Debug, Default, Clone)]
+pub struct Specs {
+    values: BTreeMap<Vec<String>, Spec>,
+}
+
+impl Specs {
+    pub fn get(&self, words: &[String]) -> Option<&Spec> {
+        self.values.get(words)
+    }
+
+    pub fn set(&mut self, words: Vec<String>, spec: Spec) {
+        self.values.insert(words, spec);
+    }
+
+    /// Removes the spec of some words along with the ones of their subcommands,
+    /// returning whether there was any.
+    pub fn remove(&mut self, words: &[String]) -> bool {
+        let count = self.values.len();
+        self.values.retain(|key, _| !key.starts_with(words));
+        self.values.len() != count
+    }
+
+    /// All the specs, sorted by their words.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<String>, &Spec)> {
+        self.values.iter()
+    }
+
+    /// Reads the specs of a file. They're written in sections, each one starting
+    /// with the words it completes in brackets, like `[cargo build]`, followed by
+    /// settings like `flags = --release --target`. Errors come with their line.
+    pub fn parse(&mut self, contents: &str) -> Result<(), (usize, String)> {
+        let mut section: Option<(Vec<String>, Spec)> = None;
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| (index + 1, message);
+
+            if let Some(words) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                let words: Vec<String> = words.split_whitespace().map(str::to_owned).collect();
+                if words.is_empty() {
+                    return Err(error(String::from("There's no command name in the section.")));
+                }
+
+                if let Some((words, spec)) = section.replace((words, Spec::default())) {
+                    self.set(words, spec);
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("Expected a setting like 'flags = ...' or a section like '[command]', found '{line}'.")));
+            };
+
+            let Some((_, spec)) = section.as_mut() else {
+                return Err(error(String::from("Settings must come after a section like '[command]'.")));
+            };
+
+            let value = value.trim();
+            let words = || value.split_whitespace().map(str::to_owned).collect();
+            match key.trim() {
+                "subcommands" => spec.subcommands = words(),
+                "flags" => spec.flags = words(),
+                "choices" => spec.arguments = Arguments::Choices(words()),
+                "output" => spec.arguments = Arguments::Output(value.to_owned()),
+                "arguments" => match Arguments::parse(value) {
+                    Some(arguments) => spec.arguments = arguments,
+                    None => return Err(error(format!("Unknown kind of arguments '{value}' (it can be file, dir, command or none).")))
+                },
+                key => return Err(error(format!("Unknown setting '{key}'.")))
+            }
+        }
+
+        if let Some((words, spec)) = section {
+            self.set(words, spec);
+        }
+
+        Ok(())
+    }
+}
+
+/// The ways the word before the cursor can be completed.
+pub struct Completion {
+    /// Where the word starts in the line, in characters.
+    pub start: usize,
+    /// The word as it's meant, without its quotes.
+    pub word: String,
+    /// The quote left open in the word, which the completed word keeps using.
+    pub quote: Option<char>,
+    pub candidates: Vec<Candidate>,
+}
+
+pub struct Candidate {
+    /// The complete word, without quotes.
+    pub value: String,
+    /// What the list of candidates shows, like the name of a file without its directory.
+    pub display: String,
+    /// Whether the word is finished, so a space goes after it. Directories aren't,
+    /// so what's inside them can be completed next.
+    pub finished: bool,
+}
+
+impl Candidate {
+    fn word(value: &str) -> Candidate {
+        Candidate { value: value.to_owned(), display: value.to_owned(), finished: true }
+    }
+}
+
+/// The word being typed at the end of a line, along with the words before it in
+/// the same command.
+struct Context {
+    start: usize,
+    word: String,
+    quote: Option<char>,
+    /// The words of the command before the one being completed.
+    command: Vec<String>,
+    /// Whether the word comes after `<` or `>`, so it's a file name.
+    redirection: bool,
+}
+
+/// Finds what the word before the cursor can be completed with: the names of
+/// commands for the first word of a command, what the spec of the command says
+/// for the ones that have one, the flags of a command for words starting with
+/// `-`, and file names for the rest.
+pub fn complete(shell: &mut Shell, line: &str) -> Completion {
+    let context = split_line(line);
+    let word = &context.word;
+
+    // Assignments and opening reserved words, like in `X=1 then cmd`, come before the command name
+    let start = context.command.iter()
+        .position(|word| !word.contains('=') && !parser::OPENING_WORDS.contains(&word.as_str()));
+
+    let candidates = match start {
+        _ if context.redirection => files(shell, word, Kind::All),
+        None if !word.contains('/') && !word.starts_with('~') => commands(shell, word),
+        None => files(shell, word, Kind::Programs),
+        Some(start) if shell.completions.get(&context.command[start..=start]).is_some() => {
+            let (spec, subcommands) = find_spec(&shell.completions, &context.command[start..]);
+            match word.starts_with('-') {
+                true => matching(&spec.flags, word),
+                false if subcommands => {
+                    let mut candidates = matching(&spec.subcommands, word);
+                    candidates.extend(arguments(shell, &spec.arguments, word));
+                    candidates
+                },
+                false => arguments(shell, &spec.arguments, word)
+            }
+        },
+        Some(start) if word.starts_with('-') => {
+            let name = &context.command[start];
+            let flags = FLAGS.iter().find(|(command, _)| command == name).map_or(&[][..], |(_, flags)| flags);
+            flags.iter().filter(|flag| flag.starts_with(word.as_str())).map(|flag| Candidate::word(flag)).collect()
+        },
+        Some(_) => files(shell, word, Kind::All)
+    };
+
+    Completion { start: context.start, word: context.word, quote: context.quote, candidates }
+}
+
+/// Finds the spec for the next word of a command, following its subcommands.
+/// After a flag that has a spec of its own, it's the one of the flag. Also
+/// returns whether a subcommand can come next, which is only before the other
+/// arguments.
+fn find_spec(specs: &Specs, command: &[String]) -> (Spec, bool) {
+    let mut words = vec![command[0].clone()];
+    let mut subcommands = true;
+
+    for word in &command[1..] {
+        let spec = specs.get(&words);
+        if spec.is_some_and(|spec| spec.subcommands.contains(word)) {
+            words.push(word.clone());
+        }
+        else if !word.starts_with('-') {
+            subcommands = false;
+        }
+    }
+
+    if let Some(flag) = command.last().filter(|word| word.starts_with('-')) {
+        words.push(flag.clone());
+        if let Some(spec) = specs.get(&words) {
+            return (spec.clone(), false);
+        }
+        words.pop();
+    }
+
+    (specs.get(&words).cloned().unwrap_or_default(), subcommands)
+}
+
+/// The words of a list that start with some text.
+fn matching(words: &[String], prefix: &str) -> Vec<Candidate> {
+    words.iter().filter(|word| word.starts_with(prefix)).map(|word| Candidate::word(word)).collect()
+}
+
+/// The arguments of some kind that start with some text.
+fn arguments(shell: &mut Shell, arguments: &Arguments, prefix: &str) -> Vec<Candidate> {
+    match arguments {
+        Arguments::Files => files(shell, prefix, Kind::All),
+        Arguments::Directories => files(shell, prefix, Kind::Directories),
+        Arguments::Commands => commands(shell, prefix),
+        Arguments::Choices(choices) => matching(choices, prefix),
+        Arguments::Output(command) => {
+            let status = shell.status;
+            let output = shell.substitute(command).unwrap_or_default();
+            shell.status = status;
+            shell.substitution = None;
+
+            let words: BTreeSet<String> = output.split_whitespace().map(str::to_owned).collect();
+            matching(&words.into_iter().collect::<Vec<_>>(), prefix)
+        },
+        Arguments::None => vec![]
+    }
+}
+
+/// Splits a line into words the way the shell would, enough to know which word
+/// the cursor is on.
+fn split_line(line: &str) -> Context {
+    let mut context = Context { start: 0, word: String::new(), quote: None, command: vec![], redirection: false };
+    let mut in_word = false;
+    let mut chars = line.chars().enumerate();
+
+    while let Some((index, c)) = chars.next() {
+        if let Some(quote) = context.quote {
+            match c {
+                _ if c == quote => context.quote = None,
+                '\\' if quote == '"' => context.word.extend(chars.next().map(|(_, c)| c)),
+                _ => context.word.push(c)
+            }
+            continue;
+        }
+
+        if !in_word && !c.is_whitespace() && !"|&;()<>".contains(c) {
+            in_word = true;
+            context.start = index;
+        }
+
+        match c {
+            '\'' | '"' => context.quote = Some(c),
+            '\\' => context.word.extend(chars.next().map(|(_, c)| c)),
+            _ if c.is_whitespace() || "|&;()<>".contains(c) => {
+                if in_word {
+                    let word = std::mem::take(&mut context.word);
+                    if !context.redirection {
+                        context.command.push(word);
+                    }
+                    context.redirection = false;
+                    in_word = false;
+                }
+
+                match c {
+                    '<' | '>' => context.redirection = true,
+                    '|' | '&' | ';' | '(' | ')' | '\n' => {
+                        context.command.clear();
+                        context.redirection = false;
+                    },
+                    _ => ()
+                }
+            },
+            _ => context.word.push(c)
+        }
+    }
+
+    if !in_word {
+        context.start = line.chars().count();
+    }
+
+    context
+}
+
+/// The builtins, functions, aliases, reserved words and programs in PATH that
+/// start with some text.
+fn commands(shell: &Shell, prefix: &str) -> Vec<Candidate> {
+    let mut names = BTreeSet::new();
+
+    names.extend(BUILTINS.iter().map(|name| name.to_string()));
+    names.extend(parser::RESERVED_WORDS.iter().map(|name| name.to_string()));
+    names.extend(shell.functions.keys().cloned());
+    names.extend(shell.aliases.iter().map(|(name, _)| name.clone()));
+
+    for dir in shell.variables.get("PATH").unwrap_or_default().split(PATH_SEPARATOR) {
+        let Ok(entries) = fs::read_dir(shell.path.join(dir)) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(prefix) && is_executable(&entry.path()) {
+                names.insert(name);
+            }
+        }
+    }
+
+    names.into_iter()
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| Candidate::word(&name))
+        .collect()
+}
+
+#[cfg(unix)]
+const PATH_SEPARATOR: char = ':';
+
+#[cfg(not(unix))]
+const PATH_SEPARATOR: char = ';';
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The files that can be given as arguments. Directories are always there, so
+/// the files inside them can be completed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    All,
+    Programs,
+    Directories,
+}
+
+/// The files and directories whose path starts with some text, relative to the
+/// current directory.
+fn files(shell: &Shell, word: &str, kind: Kind) -> Vec<Candidate> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(index) => word.split_at(index + 1),
+        None => ("", word)
+    };
+
+    // A `~` at the start stays in the word, but it has to be replaced to read the directory
+    let lookup = match dir.strip_prefix('~') {
+        Some(rest) => {
+            let (user, rest) = rest.split_once('/').unwrap_or((rest, ""));
+            let home = match user {
+                "" => shell.home(),
+                user => users::user_home(user)
+            };
+            match home {
+                Some(home) => Path::new(&home).join(rest),
+                None => return vec![]
+            }
+        },
+        None => shell.path.join(dir)
+    };
+
+    let Ok(entries) = fs::read_dir(&lookup) else {
+        return vec![];
+    };
+
+    let mut candidates = vec![];
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            continue;
+        }
+
+        let path = entry.path();
+        let is_dir = path.is_dir();
+        let wanted = match kind {
+            Kind::All => true,
+            Kind::Programs => is_executable(&path),
+            Kind::Directories => false
+        };
+
+        if !is_dir && !wanted {
+            continue;
+        }
+
+        candidates.push(match is_dir {
+            true => Candidate { value: format!("{dir}{name}/"), display: format!("{name}/"), finished: false },
+            false => Candidate { value: format!("{dir}{name}"), display: name, finished: true }
+        });
+    }
+
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates
+}
+
+/// Writes a completed word so the shell reads it back as it is: inside the quote
+/// the word was started with, or with backslashes before spaces and other special
+/// characters. A `~` at the start is left unquoted, so it's still replaced.
+pub fn quote_word(value: &str, quote: Option<char>, finished: bool) -> String {
+    let (home, rest) = match value.starts_with('~') {
+        true => value.split_at(value.find('/').map_or(value.len(), |index| index + 1)),
+        false => ("", value)
+    };
+
+    let mut result = String::from(home);
+    match quote {
+        Some('\'') => {
+            result.push('\'');
+            result.push_str(&rest.replace('\'', "'\\''"));
+        },
+        Some(quote) => {
+            result.push(quote);
+            for c in rest.chars() {
+                if "\"\\$`".contains(c) {
+                    result.push('\\');
+                }
+                result.push(c);
+            }
+        },
+        None => {
+            for c in rest.chars() {
+                if c.is_whitespace() || "\\'\"`$|&;()<>*?[]#!{}".contains(c) {
+                    result.push('\\');
+                }
+                result.push(c);
+            }
+        }
+    }
+
+    if let (Some(quote), true) = (quote, finished) {
+        result.push(quote);
+    }
+
+    result
+}
+
+/// The longest text that all the candidates start with.
+pub fn common_prefix(candidates: &[Candidate]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+
+    let mut length = first.value.len();
+    for candidate in rest {
+        length = first.value.char_indices()
+            .zip(candidate.value.chars())
+            .take_while(|((index, a), b)| *index < length && a == b)
+            .map(|((index, a), _)| index + a.len_utf8())
+            .last()
+            .unwrap_or(0);
+    }
+
+    first.value[..length].to_owned()
+}
@@ -0,0 +1,210 @@
+//! Copies of the shell, which run some of its commands at the same time as it:
+//! the jobs started with `&`, and the commands of a pipeline that the shell runs
+//! by itself. A copy is a new process of the shell, started with `--copy` and the
+//! number of a pipe where it reads what it runs and the state of the shell.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::process::Child;
+
+use crate::commands;
+use crate::lexer;
+use crate::parser::{self, Command, List};
+use crate::shell::Shell;
+use crate::streams::{self, Streams};
+use crate::traps::Condition;
+use crate::variables::Variables;
+
+/// What a copy of the shell runs.
+pub enum Task {
+    /// Commands run in the background.
+    Job(String),
+    /// A command of a pipeline, along with its words when it's a simple command,
+    /// which were already expanded by the shell.
+    Stage(String, Vec<String>),
+}
+
+/// Starts a copy of the shell running a task with the given streams. With job
+/// control, it's put in a process group like a program would be.
+pub fn start(shell: &Shell, task: &Task, group: Option<u32>, io: &Streams) -> io::Result<Child> {
+    let (reader, mut writer) = streams::pipe()?;
+
+    // The end given to the copy must stay open when it starts, unlike the other ones
+    let descriptor = unsafe { libc::dup(reader.as_raw_fd()) };
+    if descriptor == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let inherited = unsafe { File::from_raw_fd(descriptor) };
+
+    let program = env::current_exe()?;
+    let descriptor = descriptor.to_string();
+    let child = commands::spawn(&program, &["--copy", &descriptor], &shell.path, &shell.variables.environment(), group, io)?;
+    drop(inherited);
+    drop(reader);
+
+    // The copy reads everything before running anything, so this doesn't block for long
+    writer.write_all(encode(shell, task).as_bytes())?;
+    Ok(child)
+}
+
+/// Reads the task and the state of the shell that started the copy, setting the
+/// state in the given shell.
+pub fn load(shell: &mut Shell, descriptor: i32) -> io::Result<Task> {
+    let mut state = String::new();
+    unsafe { File::from_raw_fd(descriptor) }.read_to_string(&mut state)?;
+
+    let mut fields = Fields(state.split('\0'));
+    let task = match fields.next()? {
+        "job" => Task::Job(fields.next()?.to_owned()),
+        _ => {
+            let command = fields.next()?.to_owned();
+            let words = (0..fields.count()?).map(|_| fields.next().map(str::to_owned)).collect::<io::Result<_>>()?;
+            Task::Stage(command, words)
+        }
+    };
+
+    shell.path = fields.next()?.into();
+    shell.name = fields.next()?.to_owned();
+    shell.pid = fields.number()?;
+    shell.status = fields.number()?;
+    shell.jobs.last = fields.next()?.parse().ok();
+    shell.interactive = fields.next()? == "1";
+    shell.depth = fields.number()?;
+    shell.sourcing = fields.number()?;
+    shell.loops = fields.number()?;
+
+    for _ in 0..fields.count()? {
+        shell.options.set(fields.next()?, true);
+    }
+
+    shell.positional = (0..fields.count()?).map(|_| fields.next().map(str::to_owned)).collect::<io::Result<_>>()?;
+
+    for _ in 0..fields.count()? {
+        let _ = shell.traps.set(Condition::Signal(fields.number()?), "");
+    }
+
+    // The variables made local by the running functions stay as they are, but
+    // `local` still works in them
+    shell.variables = Variables::default();
+    for _ in 0..shell.depth {
+        shell.variables.push_scope();
+    }
+    for _ in 0..fields.count()? {
+        let name = fields.next()?;
+        shell.variables.set(name, fields.next()?);
+        if fields.next()? == "1" {
+            shell.variables.export(name);
+        }
+    }
+
+    for _ in 0..fields.count()? {
+        if let Command::Function(function) = parse_command(fields.next()?)? {
+            shell.functions.insert(function.name.clone(), function);
+        }
+    }
+
+    // Aliases were already replaced in the commands, so they're only set for the
+    // ones the copy reads by itself, like with `eval`
+    for _ in 0..fields.count()? {
+        let name = fields.next()?;
+        shell.aliases.set(name, fields.next()?);
+    }
+
+    Ok(task)
+}
+
+/// Builds the commands written back by the shell. Their aliases were already replaced.
+pub fn parse(source: &str) -> io::Result<List> {
+    lexer::tokenize(source).and_then(parser::parse).map_err(|error| invalid(&error.to_string()))
+}
+
+/// Builds a single command written back by the shell.
+pub fn parse_command(source: &str) -> io::Result<Command> {
+    parse(source)?.items.into_iter().next()
+        .and_then(|item| item.first.commands.into_iter().next())
+        .ok_or_else(|| invalid("There's no command."))
+}
+
+/// Writes the task and the state of the shell as fields ended by NUL characters,
+/// which can't be part of them. Lists start with their length.
+fn encode(shell: &Shell, task: &Task) -> String {
+    let mut fields: Vec<String> = vec![];
+
+    match task {
+        Task::Job(command) => fields.extend([String::from("job"), command.clone()]),
+        Task::Stage(command, words) => {
+            fields.extend([String::from("stage"), command.clone(), words.len().to_string()]);
+            fields.extend(words.iter().cloned());
+        }
+    }
+
+    fields.extend([
+        shell.path.to_string_lossy().into_owned(),
+        shell.name.clone(),
+        shell.pid.to_string(),
+        shell.status.to_string(),
+        shell.jobs.last.map(|pid| pid.to_string()).unwrap_or_default(),
+        String::from(if shell.interactive { "1" } else { "0" }),
+        shell.depth.to_string(),
+        shell.sourcing.to_string(),
+        shell.loops.to_string(),
+    ]);
+
+    let options: Vec<&str> = shell.options.list().into_iter().filter(|(_, on)| *on).map(|(name, _)| name).collect();
+    fields.push(options.len().to_string());
+    fields.extend(options.into_iter().map(str::to_owned));
+
+    fields.push(shell.positional.len().to_string());
+    fields.extend(shell.positional.iter().cloned());
+
+    // The traps that run commands are left out, but ignored signals stay ignored
+    let ignored: Vec<String> = shell.traps.conditions().into_iter()
+        .filter_map(|condition| match condition {
+            Condition::Signal(signal) if shell.traps.get(condition) == Some("") => Some(signal.to_string()),
+            _ => None
+        })
+        .collect();
+    fields.push(ignored.len().to_string());
+    fields.extend(ignored);
+
+    fields.push(shell.variables.iter().count().to_string());
+    for (name, variable) in shell.variables.iter() {
+        let exported = if variable.exported { "1" } else { "0" };
+        fields.extend([name.clone(), variable.value.clone(), String::from(exported)]);
+    }
+
+    fields.push(shell.functions.len().to_string());
+    fields.extend(shell.functions.values().map(|function| Command::Function(function.clone()).to_string()));
+
+    let aliases: Vec<(&String, &String)> = shell.aliases.iter().collect();
+    fields.push(aliases.len().to_string());
+    for (name, value) in aliases {
+        fields.extend([name.clone(), value.clone()]);
+    }
+
+    let mut state = fields.join("\0");
+    state.push('\0');
+    state
+}
+
+struct Fields<'a>(std::str::Split<'a, char>);
+
+impl<'a> Fields<'a> {
+    fn next(&mut self) -> io::Result<&'a str> {
+        self.0.next().ok_or_else(|| invalid("The state of the shell is incomplete."))
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> io::Result<T> {
+        self.next()?.parse().map_err(|_| invalid("The state of the shell has an invalid number."))
+    }
+
+    fn count(&mut self) -> io::Result<usize> {
+        self.number()
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
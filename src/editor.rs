@@ -0,0 +1,930 @@
+use std::io::{self, Write};
+use std::mem;
+use std::ops::Range;
+
+use crate::completion::{self, Candidate};
+use crate::shell::Shell;
+use crate::streams::Input;
+use crate::terminal::{self, Key, RawMode};
+
+/// How many candidates of a completion are listed without asking.
+const MAX_LISTED: usize = 100;
+
+/// The set of key bindings used to edit lines.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Keymap {
+    /// Control and Alt keys, like in Emacs and most shells.
+    #[default]
+    Emacs,
+    /// An insert mode for typing and a normal mode (entered with Escape) for
+    /// moving around and changing the line with commands, like in Vi.
+    Vi,
+}
+
+/// How reading a line finished.
+pub enum Entry {
+    Line(String),
+    /// Control-C was pressed, discarding the line.
+    Interrupted,
+    /// The input ended, or Control-D was pressed in an empty line.
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Insert,
+    /// The normal mode of the Vi keymap.
+    Normal,
+}
+
+/// What the last key did, so that typing is undone as a whole and consecutive
+/// kills are yanked back together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Insert,
+    Kill,
+    Other,
+}
+
+/// An incremental search through the history, started with Control-R.
+struct Search {
+    query: String,
+    /// The number of the entry found, counted from 0.
+    found: Option<usize>,
+    /// Whether the last search didn't find anything.
+    failed: bool,
+    /// The line and its cursor from before searching, which come back when it's cancelled.
+    original: (Vec<char>, usize),
+}
+
+/// Reads lines from the terminal, letting the user move around and change them
+/// before running them.
+pub struct Editor {
+    keymap: Keymap,
+    mode: Mode,
+    /// The prompt as written, with its colors.
+    prompt: String,
+    buffer: Vec<char>,
+    /// Position of the cursor in the buffer, in characters.
+    cursor: usize,
+    /// Row of the cursor as last drawn, counted from the first row of the prompt.
+    row: usize,
+    /// The text killed last, which yanking inserts back.
+    killed: String,
+    last: Action,
+    previous: Action,
+    /// Earlier states of the line, along with their cursors.
+    undo: Vec<(Vec<char>, usize)>,
+    /// The last search for a character in the line with `f`, `F`, `t` or `T`,
+    /// which `;` and `,` repeat.
+    find: Option<(char, char)>,
+    /// The entry of the history being shown, counted from 0, or `None` for a new line.
+    recall: Option<usize>,
+    /// The new line, kept while going through the history.
+    draft: Vec<char>,
+    search: Option<Search>,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            keymap: Keymap::default(),
+            mode: Mode::Insert,
+            prompt: String::new(),
+            buffer: vec![],
+            cursor: 0,
+            row: 0,
+            killed: String::new(),
+            last: Action::Other,
+            previous: Action::Other,
+            undo: vec![],
+            find: None,
+            recall: None,
+            draft: vec![],
+            search: None,
+        }
+    }
+
+    /// Shows a prompt and reads a line, which can also be taken from the history
+    /// of the shell and completed with Tab. When the terminal can't be put in raw
+    /// mode, the line is read as it's typed, without editing keys.
+    pub fn read_line(&mut self, prompt: &str, shell: &mut Shell) -> io::Result<Entry> {
+        let Ok(raw) = RawMode::enable() else {
+            return read_plain(prompt);
+        };
+
+        self.keymap = shell.options.keymap();
+        self.mode = Mode::Insert;
+        self.prompt = prompt.to_owned();
+        self.buffer.clear();
+        self.cursor = 0;
+        self.row = 0;
+        self.last = Action::Other;
+        self.undo.clear();
+        self.recall = None;
+        self.search = None;
+        self.render()?;
+
+        let entry = loop {
+            let Some(key) = terminal::read_key()? else {
+                break self.finish("\r\n", Entry::End)?;
+            };
+
+            self.previous = mem::replace(&mut self.last, Action::Other);
+            let searching = self.search_key(key, shell.history.entries());
+            let entry = match self.mode {
+                _ if searching => None,
+                Mode::Insert => self.insert_key(key, shell)?,
+                Mode::Normal => self.normal_key(key, shell)?
+            };
+
+            if let Some(entry) = entry {
+                break entry;
+            }
+
+            if self.mode == Mode::Normal {
+                self.cursor = self.cursor.min(self.buffer.len().saturating_sub(1));
+            }
+
+            self.render()?;
+        };
+
+        drop(raw);
+        Ok(entry)
+    }
+
+    /// Handles a key of the Emacs keymap, or of the insert mode of the Vi one.
+    fn insert_key(&mut self, key: Key, shell: &mut Shell) -> io::Result<Option<Entry>> {
+        if self.keymap == Keymap::Vi {
+            match key {
+                Key::Escape => {
+                    self.mode = Mode::Normal;
+                    self.cursor = self.cursor.saturating_sub(1);
+                    return Ok(None);
+                },
+                // Escape followed quickly by a command arrives as Alt
+                Key::Alt(c) => {
+                    self.mode = Mode::Normal;
+                    self.cursor = self.cursor.saturating_sub(1);
+                    return self.normal_key(Key::Char(c), shell);
+                },
+                _ => ()
+            }
+        }
+
+        match key {
+            Key::Char(c) => self.insert(c),
+            Key::Tab => self.complete(shell)?,
+            Key::Enter => return self.finish("\r\n", self.line()).map(Some),
+            Key::Ctrl('c') => return self.finish("^C\r\n", Entry::Interrupted).map(Some),
+            Key::Ctrl('d') if self.buffer.is_empty() => return self.finish("\r\n", Entry::End).map(Some),
+            Key::Ctrl('d') | Key::Delete => self.delete(self.cursor..self.cursor + 1),
+            Key::Backspace => self.delete(self.cursor.saturating_sub(1)..self.cursor),
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Up | Key::Ctrl('p') => self.previous_entry(shell.history.entries()),
+            Key::Down | Key::Ctrl('n') => self.next_entry(shell.history.entries()),
+            Key::Ctrl('r') => self.start_search(),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.buffer.len(),
+            Key::WordLeft | Key::Alt('b') => self.cursor = self.word_back(self.cursor),
+            Key::WordRight | Key::Alt('f') => self.cursor = self.word_forward(self.cursor),
+            Key::Ctrl('k') => self.kill(self.cursor..self.buffer.len()),
+            Key::Ctrl('u') => self.kill(0..self.cursor),
+            Key::Ctrl('w') => self.kill(self.blank_word_back(self.cursor)..self.cursor),
+            Key::Alt('d') => self.kill(self.cursor..self.word_forward(self.cursor)),
+            Key::AltBackspace => self.kill(self.word_back(self.cursor)..self.cursor),
+            Key::Ctrl('y') => self.yank(),
+            Key::Ctrl('t') => self.transpose(),
+            Key::Ctrl('_') => self.restore(),
+            Key::Ctrl('l') => self.clear_screen()?,
+            _ => ()
+        }
+
+        Ok(None)
+    }
+
+    /// Handles a command of the normal mode of the Vi keymap, which can start
+    /// with a count and read more keys, like `2dw`.
+    fn normal_key(&mut self, key: Key, shell: &mut Shell) -> io::Result<Option<Entry>> {
+        let (count, key) = read_count(key)?;
+        let end = self.buffer.len();
+
+        // Each command of the normal mode replaces the killed text
+        self.previous = Action::Other;
+
+        match key {
+            Key::Enter => return self.finish("\r\n", self.line()).map(Some),
+            Key::Ctrl('c') => return self.finish("^C\r\n", Entry::Interrupted).map(Some),
+            Key::Ctrl('d') if self.buffer.is_empty() => return self.finish("\r\n", Entry::End).map(Some),
+            Key::Ctrl('l') => self.clear_screen()?,
+            Key::Char('i') => self.mode = Mode::Insert,
+            Key::Char('a') => {
+                self.cursor = (self.cursor + 1).min(end);
+                self.mode = Mode::Insert;
+            },
+            Key::Char('I') => {
+                self.cursor = self.first_non_blank();
+                self.mode = Mode::Insert;
+            },
+            Key::Char('A') => {
+                self.cursor = end;
+                self.mode = Mode::Insert;
+            },
+            Key::Char('x') | Key::Delete => self.kill(self.cursor..(self.cursor + count).min(end)),
+            Key::Char('X') => self.kill(self.cursor.saturating_sub(count)..self.cursor),
+            Key::Char('D') => self.kill(self.cursor..end),
+            Key::Char('C') => {
+                self.kill(self.cursor..end);
+                self.mode = Mode::Insert;
+            },
+            Key::Char('s') => {
+                self.kill(self.cursor..(self.cursor + count).min(end));
+                self.mode = Mode::Insert;
+            },
+            Key::Char('S') => {
+                self.kill(0..end);
+                self.mode = Mode::Insert;
+            },
+            Key::Char('Y') => self.killed = self.buffer.iter().collect(),
+            Key::Char(operator @ ('d' | 'c' | 'y')) => self.operator(operator, count)?,
+            Key::Char('p') => {
+                if !self.buffer.is_empty() {
+                    self.cursor += 1;
+                }
+                self.paste(count);
+            },
+            Key::Char('P') => self.paste(count),
+            Key::Char('r') => {
+                if let Some(Key::Char(c)) = terminal::read_key()? {
+                    if self.cursor + count <= end {
+                        self.save();
+                        self.buffer[self.cursor..self.cursor + count].fill(c);
+                        self.cursor += count - 1;
+                    }
+                }
+            },
+            Key::Char('~') => {
+                self.save();
+                for _ in 0..count {
+                    let Some(c) = self.buffer.get_mut(self.cursor) else {
+                        break;
+                    };
+
+                    *c = match c.is_uppercase() {
+                        true => c.to_lowercase().next().unwrap_or(*c),
+                        false => c.to_uppercase().next().unwrap_or(*c)
+                    };
+                    self.cursor += 1;
+                }
+            },
+            Key::Char('u') | Key::Ctrl('_') => self.restore(),
+            Key::Char('k') | Key::Up => {
+                for _ in 0..count {
+                    self.previous_entry(shell.history.entries());
+                }
+                self.cursor = 0;
+            },
+            Key::Char('j') | Key::Down => {
+                for _ in 0..count {
+                    self.next_entry(shell.history.entries());
+                }
+                self.cursor = 0;
+            },
+            Key::Ctrl('r') => self.start_search(),
+            key => {
+                if let Some((target, _)) = self.motion(key, count)? {
+                    self.cursor = target;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Runs `d`, `c` or `y` over the text covered by the motion after it, or over
+    /// the whole line when the operator is repeated, like in `dd`.
+    fn operator(&mut self, operator: char, count: usize) -> io::Result<()> {
+        let Some(key) = terminal::read_key()? else {
+            return Ok(());
+        };
+
+        let (more, key) = read_count(key)?;
+        let count = count * more;
+
+        let range = if key == Key::Char(operator) {
+            0..self.buffer.len()
+        }
+        else {
+            // Changing a word leaves the spaces after it, like changing to its end
+            let key = match (operator, key) {
+                ('c', Key::Char('w')) if self.buffer.get(self.cursor).is_some_and(|c| !c.is_whitespace()) => Key::Char('e'),
+                ('c', Key::Char('W')) if self.buffer.get(self.cursor).is_some_and(|c| !c.is_whitespace()) => Key::Char('E'),
+                _ => key
+            };
+
+            match self.motion(key, count)? {
+                Some((target, inclusive)) if target >= self.cursor => {
+                    self.cursor..(target + usize::from(inclusive)).min(self.buffer.len())
+                },
+                Some((target, _)) => target..self.cursor,
+                None => return Ok(())
+            }
+        };
+
+        match operator {
+            'y' => {
+                self.killed = self.buffer[range.clone()].iter().collect();
+                self.cursor = range.start;
+            },
+            _ => {
+                self.kill(range);
+                if operator == 'c' {
+                    self.mode = Mode::Insert;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds where a motion of the Vi keymap moves the cursor, along with whether
+    /// the character there is part of the text it covers.
+    fn motion(&mut self, key: Key, count: usize) -> io::Result<Option<(usize, bool)>> {
+        let end = self.buffer.len();
+        let mut position = self.cursor;
+
+        let inclusive = match key {
+            Key::Char('h') | Key::Left | Key::Backspace => {
+                position = position.saturating_sub(count);
+                false
+            },
+            Key::Char('l' | ' ') | Key::Right => {
+                position = (position + count).min(end);
+                false
+            },
+            Key::Char('0') | Key::Home => {
+                position = 0;
+                false
+            },
+            Key::Char('^') => {
+                position = self.first_non_blank();
+                false
+            },
+            Key::Char('$') | Key::End => {
+                position = end;
+                false
+            },
+            Key::Char(c @ ('w' | 'W' | 'b' | 'B' | 'e' | 'E')) | Key::Alt(c @ ('b' | 'f')) => {
+                let big = c.is_uppercase();
+                for _ in 0..count {
+                    position = match c {
+                        'w' | 'W' | 'f' => self.vi_word_forward(position, big),
+                        'b' | 'B' => self.vi_word_back(position, big),
+                        _ => self.vi_word_end(position, big)
+                    };
+                }
+                matches!(c, 'e' | 'E')
+            },
+            Key::WordLeft => {
+                position = self.word_back(position);
+                false
+            },
+            Key::WordRight => {
+                position = self.word_forward(position);
+                false
+            },
+            Key::Char(kind @ ('f' | 'F' | 't' | 'T')) => {
+                let Some(Key::Char(target)) = terminal::read_key()? else {
+                    return Ok(None);
+                };
+
+                self.find = Some((kind, target));
+                return Ok(self.find_char(kind, target, count).map(|position| (position, kind.is_lowercase())));
+            },
+            Key::Char(repeat @ (';' | ',')) => {
+                let Some((kind, target)) = self.find else {
+                    return Ok(None);
+                };
+
+                // `,` searches the other way
+                let kind = match repeat {
+                    ';' => kind,
+                    _ if kind.is_lowercase() => kind.to_ascii_uppercase(),
+                    _ => kind.to_ascii_lowercase()
+                };
+
+                return Ok(self.find_char(kind, target, count).map(|position| (position, kind.is_lowercase())));
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some((position, inclusive)))
+    }
+
+    /// Finds a character in the line: forward with `f` (on it) and `t` (before
+    /// it), and backward with `F` and `T`.
+    fn find_char(&self, kind: char, target: char, count: usize) -> Option<usize> {
+        let mut position = self.cursor;
+        for _ in 0..count {
+            position = match kind {
+                'f' | 't' => {
+                    // Repeating `t` shouldn't stop at the character right before the same target
+                    let start = position + 1 + usize::from(kind == 't' && self.buffer.get(position + 1) == Some(&target));
+                    start + self.buffer.get(start..)?.iter().position(|c| *c == target)?
+                },
+                _ => {
+                    let end = position.saturating_sub(usize::from(kind == 'T' && position > 0 && self.buffer[position - 1] == target));
+                    self.buffer[..end].iter().rposition(|c| *c == target)?
+                }
+            };
+        }
+
+        Some(match kind {
+            't' => position - 1,
+            'T' => position + 1,
+            _ => position
+        })
+    }
+
+    /// Shows the entry of the history before the one being shown.
+    fn previous_entry(&mut self, history: &[String]) {
+        let index = match self.recall {
+            None if !history.is_empty() => history.len() - 1,
+            Some(index) if index > 0 => index - 1,
+            _ => return
+        };
+
+        self.show_entry(Some(index), history);
+    }
+
+    /// Shows the entry of the history after the one being shown, or the new line after the last one.
+    fn next_entry(&mut self, history: &[String]) {
+        match self.recall {
+            Some(index) if index + 1 < history.len() => self.show_entry(Some(index + 1), history),
+            Some(_) => self.show_entry(None, history),
+            None => ()
+        }
+    }
+
+    fn show_entry(&mut self, index: Option<usize>, history: &[String]) {
+        if self.recall.is_none() {
+            self.draft = self.buffer.clone();
+        }
+
+        self.save();
+        self.buffer = match index {
+            Some(index) => history[index].chars().collect(),
+            None => mem::take(&mut self.draft)
+        };
+        self.cursor = self.buffer.len();
+        self.recall = index;
+    }
+
+    fn start_search(&mut self) {
+        self.search = Some(Search {
+            query: String::new(),
+            found: None,
+            failed: false,
+            original: (self.buffer.clone(), self.cursor),
+        });
+    }
+
+    /// Handles a key while searching the history, returning whether it was used.
+    /// Typing adds to the text searched, Control-R finds an older entry, and
+    /// Control-G or Escape cancel the search. Other keys end the search keeping
+    /// the entry found, and are used as usual.
+    fn search_key(&mut self, key: Key, history: &[String]) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+
+        // Entries are searched from the newest, starting at the one found so far
+        let before = match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                search.found.map_or(history.len(), |index| index + 1)
+            },
+            Key::Backspace => {
+                search.query.pop();
+                history.len()
+            },
+            Key::Ctrl('r') => search.found.unwrap_or(history.len()),
+            Key::Ctrl('g') | Key::Escape => {
+                (self.buffer, self.cursor) = mem::take(&mut search.original);
+                self.search = None;
+                return true;
+            },
+            _ => {
+                self.search = None;
+                return false;
+            }
+        };
+
+        let query: Vec<char> = search.query.chars().collect();
+        let found = history[..before].iter().rposition(|entry| entry.contains(&search.query));
+        search.failed = found.is_none() && !query.is_empty();
+
+        if let Some(index) = found.filter(|_| !query.is_empty()) {
+            search.found = Some(index);
+            self.buffer = history[index].chars().collect();
+            self.cursor = self.buffer.windows(query.len()).position(|window| window == query).unwrap_or(0);
+            self.recall = Some(index);
+        }
+
+        true
+    }
+
+    /// Completes the word before the cursor. When there are several ways to do it,
+    /// it's completed as far as they agree, and then they're listed.
+    fn complete(&mut self, shell: &mut Shell) -> io::Result<()> {
+        let line: String = self.buffer[..self.cursor].iter().collect();
+        let completion = completion::complete(shell, &line);
+        let candidates = &completion.candidates;
+
+        let (value, finished) = match candidates.as_slice() {
+            [] => return io::stdout().write_all(b"\x07"),
+            [candidate] => (candidate.value.clone(), candidate.finished),
+            _ => (completion::common_prefix(candidates), false)
+        };
+
+        if candidates.len() > 1 && value.len() <= completion.word.len() {
+            return self.list(candidates);
+        }
+
+        let mut text = completion::quote_word(&value, completion.quote, finished);
+        if finished {
+            text.push(' ');
+        }
+
+        self.save();
+        self.buffer.splice(completion.start..self.cursor, text.chars());
+        self.cursor = completion.start + text.chars().count();
+        Ok(())
+    }
+
+    /// Shows the candidates of a completion in columns under the line, asking
+    /// first when there are many of them. The line is drawn again after them.
+    fn list(&mut self, candidates: &[Candidate]) -> io::Result<()> {
+        let cursor = self.cursor;
+        self.cursor = self.buffer.len();
+        self.render()?;
+        self.cursor = cursor;
+        self.row = 0;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\r\n")?;
+
+        if candidates.len() > MAX_LISTED {
+            write!(stdout, "Show all {} possibilities? (y/n) ", candidates.len())?;
+            stdout.flush()?;
+
+            let answer = terminal::read_key()?;
+            stdout.write_all(b"\r\n")?;
+            if !matches!(answer, Some(Key::Char('y' | 'Y'))) {
+                return Ok(());
+            }
+        }
+
+        let widths: Vec<usize> = candidates.iter()
+            .map(|candidate| candidate.display.chars().map(terminal::char_width).sum())
+            .collect();
+        let column_width = widths.iter().max().copied().unwrap_or(0) + 2;
+        let columns = (terminal::width() / column_width).max(1);
+        let rows = candidates.len().div_ceil(columns);
+
+        // Candidates go down each column, like in `ls`
+        let mut output = String::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let index = column * rows + row;
+                let Some(candidate) = candidates.get(index) else {
+                    break;
+                };
+
+                output += &candidate.display;
+                if (column + 1) * rows + row < candidates.len() {
+                    output.extend(std::iter::repeat(' ').take(column_width - widths[index]));
+                }
+            }
+            output += "\r\n";
+        }
+
+        stdout.write_all(output.as_bytes())
+    }
+
+    fn line(&self) -> Entry {
+        Entry::Line(self.buffer.iter().collect())
+    }
+
+    /// Moves the cursor after the line and writes something there, ending the line.
+    fn finish(&mut self, text: &str, entry: Entry) -> io::Result<Entry> {
+        self.cursor = self.buffer.len();
+        self.render()?;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+        Ok(entry)
+    }
+
+    /// Saves the line before changing it, for undoing the change later.
+    fn save(&mut self) {
+        if self.undo.last().map_or(true, |(buffer, _)| *buffer != self.buffer) {
+            self.undo.push((self.buffer.clone(), self.cursor));
+        }
+    }
+
+    /// Goes back to the line as it was before the last change.
+    fn restore(&mut self) {
+        if let Some((buffer, cursor)) = self.undo.pop() {
+            self.buffer = buffer;
+            self.cursor = cursor.min(self.buffer.len());
+        }
+    }
+
+    fn insert(&mut self, c: char) {
+        if self.previous != Action::Insert {
+            self.save();
+        }
+
+        self.buffer.insert(self.cursor, c);
+        self.cursor += 1;
+        self.last = Action::Insert;
+    }
+
+    fn delete(&mut self, range: Range<usize>) {
+        if range.end <= self.buffer.len() && !range.is_empty() {
+            self.save();
+            self.buffer.drain(range.clone());
+            self.cursor = range.start;
+        }
+    }
+
+    /// Removes some text, keeping it for yanking. Text killed by consecutive keys
+    /// is kept together, in the order it had in the line.
+    fn kill(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.save();
+        let text: String = self.buffer.drain(range.clone()).collect();
+
+        match self.previous {
+            Action::Kill if range.start < self.cursor => self.killed.insert_str(0, &text),
+            Action::Kill => self.killed.push_str(&text),
+            _ => self.killed = text
+        }
+
+        self.cursor = range.start;
+        self.last = Action::Kill;
+    }
+
+    /// Inserts the killed text at the cursor, leaving the cursor after it.
+    fn yank(&mut self) {
+        if self.killed.is_empty() {
+            return;
+        }
+
+        self.save();
+        let text: Vec<char> = self.killed.chars().collect();
+        self.cursor = self.cursor.min(self.buffer.len());
+        self.buffer.splice(self.cursor..self.cursor, text.iter().copied());
+        self.cursor += text.len();
+    }
+
+    /// Pastes the killed text in the normal mode of Vi, leaving the cursor on its
+    /// last character.
+    fn paste(&mut self, count: usize) {
+        for _ in 0..count {
+            self.yank();
+        }
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    /// Swaps the character before the cursor with the one under it, or the last
+    /// two at the end of the line.
+    fn transpose(&mut self) {
+        if self.buffer.len() < 2 || self.cursor == 0 {
+            return;
+        }
+
+        self.save();
+        let position = self.cursor.min(self.buffer.len() - 1);
+        self.buffer.swap(position - 1, position);
+        self.cursor = position + 1;
+    }
+
+    fn first_non_blank(&self) -> usize {
+        self.buffer.iter().position(|c| !c.is_whitespace()).unwrap_or(self.buffer.len())
+    }
+
+    /// The start of the word before a position, where words are made of letters and digits.
+    fn word_back(&self, mut position: usize) -> usize {
+        while position > 0 && !self.buffer[position - 1].is_alphanumeric() {
+            position -= 1;
+        }
+        while position > 0 && self.buffer[position - 1].is_alphanumeric() {
+            position -= 1;
+        }
+        position
+    }
+
+    /// The end of the word after a position, where words are made of letters and digits.
+    fn word_forward(&self, mut position: usize) -> usize {
+        let end = self.buffer.len();
+        while position < end && !self.buffer[position].is_alphanumeric() {
+            position += 1;
+        }
+        while position < end && self.buffer[position].is_alphanumeric() {
+            position += 1;
+        }
+        position
+    }
+
+    /// The start of the word before a position, where words are separated by spaces.
+    fn blank_word_back(&self, mut position: usize) -> usize {
+        while position > 0 && self.buffer[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        while position > 0 && !self.buffer[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        position
+    }
+
+    /// The start of the next word, like the `w` motion of Vi (or `W` when `big`).
+    fn vi_word_forward(&self, mut position: usize, big: bool) -> usize {
+        let end = self.buffer.len();
+        if let Some(&c) = self.buffer.get(position) {
+            let class = char_class(c, big);
+            while position < end && class != 0 && char_class(self.buffer[position], big) == class {
+                position += 1;
+            }
+        }
+        while position < end && self.buffer[position].is_whitespace() {
+            position += 1;
+        }
+        position
+    }
+
+    /// The start of the word before a position, like the `b` motion of Vi.
+    fn vi_word_back(&self, mut position: usize, big: bool) -> usize {
+        while position > 0 && self.buffer[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        if position > 0 {
+            let class = char_class(self.buffer[position - 1], big);
+            while position > 0 && char_class(self.buffer[position - 1], big) == class {
+                position -= 1;
+            }
+        }
+        position
+    }
+
+    /// The last character of the word after a position, like the `e` motion of Vi.
+    fn vi_word_end(&self, position: usize, big: bool) -> usize {
+        let end = self.buffer.len();
+        let mut position = position + 1;
+        while position < end && self.buffer[position].is_whitespace() {
+            position += 1;
+        }
+        if position >= end {
+            return end.saturating_sub(1);
+        }
+
+        let class = char_class(self.buffer[position], big);
+        while position + 1 < end && char_class(self.buffer[position + 1], big) == class {
+            position += 1;
+        }
+        position
+    }
+
+    fn clear_screen(&mut self) -> io::Result<()> {
+        io::stdout().write_all(b"\x1B[H\x1B[2J")?;
+        self.row = 0;
+        Ok(())
+    }
+
+    /// Draws the prompt and the line again, leaving the terminal cursor where the
+    /// one of the line is. Long lines wrap over several rows.
+    fn render(&mut self) -> io::Result<()> {
+        let width = terminal::width();
+        let mut output = String::new();
+
+        if self.row > 0 {
+            output += &format!("\x1B[{}A", self.row);
+        }
+        let prompt = match &self.search {
+            Some(search) if search.failed => format!("(failed reverse-i-search)'{}': ", search.query),
+            Some(search) => format!("(reverse-i-search)'{}': ", search.query),
+            None => self.prompt.clone()
+        };
+
+        output += "\r\x1B[J";
+        output += &prompt;
+        output.extend(self.buffer.iter());
+
+        // Find where each character ends up, wrapping the ones that don't fit in a row
+        let (mut row, mut column) = (0, 0);
+        let mut cursor = (0, 0);
+        let prompt_chars = terminal::visible_chars(&prompt);
+        let characters = prompt_chars.iter().chain(self.buffer.iter());
+
+        for (index, &c) in characters.enumerate() {
+            let size = terminal::char_width(c);
+            if c != '\n' && column + size > width {
+                row += 1;
+                column = 0;
+            }
+            if index == prompt_chars.len() + self.cursor {
+                cursor = (row, column);
+            }
+
+            match c {
+                '\n' => {
+                    row += 1;
+                    column = 0;
+                },
+                _ => column += size
+            }
+        }
+
+        // A full last row leaves the terminal cursor on it, so it has to be moved down
+        if column >= width {
+            output += "\r\n";
+            row += 1;
+            column = 0;
+        }
+        if self.cursor >= self.buffer.len() {
+            cursor = (row, column);
+        }
+
+        if row > cursor.0 {
+            output += &format!("\x1B[{}A", row - cursor.0);
+        }
+        output += "\r";
+        if cursor.1 > 0 {
+            output += &format!("\x1B[{}C", cursor.1);
+        }
+
+        self.row = cursor.0;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// Reads the count typed before a command of the Vi keymap, returning it along
+/// with the key of the command.
+fn read_count(mut key: Key) -> io::Result<(usize, Key)> {
+    let mut count: Option<usize> = None;
+    loop {
+        match key {
+            Key::Char(digit @ '0'..='9') if digit != '0' || count.is_some() => {
+                let value = digit.to_digit(10).unwrap_or(0) as usize;
+                count = Some((count.unwrap_or(0) * 10 + value).min(10_000));
+            },
+            _ => return Ok((count.unwrap_or(1), key))
+        }
+
+        match terminal::read_key()? {
+            Some(next) => key = next,
+            None => return Ok((count.unwrap_or(1), Key::Unknown))
+        }
+    }
+}
+
+/// Groups characters for the word motions of Vi: spaces (0), letters, digits
+/// and underscores (1), and the rest (2). Big words only separate spaces.
+fn char_class(c: char, big: bool) -> u8 {
+    match c {
+        _ if c.is_whitespace() => 0,
+        _ if big || c.is_alphanumeric() || c == '_' => 1,
+        _ => 2
+    }
+}
+
+/// Reads a line without editing it, for when the terminal can't be put in raw
+/// mode. Control-C discards what was typed.
+pub fn read_plain(prompt: &str) -> io::Result<Entry> {
+    let mut stdout = io::stdout();
+    stdout.write_all(prompt.as_bytes())?;
+    stdout.flush()?;
+
+    match Input::Stdin.read_line() {
+        Ok(Some(line)) => Ok(Entry::Line(line)),
+        // The prompt is left in its own line
+        Ok(None) => {
+            stdout.write_all(b"\n")?;
+            Ok(Entry::End)
+        },
+        Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+            stdout.write_all(b"\n")?;
+            Ok(Entry::Interrupted)
+        },
+        Err(error) => Err(error)
+    }
+}
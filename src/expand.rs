@@ -0,0 +1,433 @@
+use std::{env, mem};
+
+use crate::glob;
+use crate::lexer::{Modifier, Parameter, Word, WordPart};
+use crate::shell::Shell;
+use crate::streams::{Capture, Input, Output, Streams};
+use crate::users;
+use crate::variables;
+
+/// Characters that separate the fields of an unquoted expansion.
+fn is_separator(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n'
+}
+
+/// An argument being built, along with the pattern used to look for files.
+#[derive(Default)]
+struct Field {
+    text: String,
+    /// The text with its quoted parts escaped, so only unquoted wildcards work.
+    pattern: String,
+    has_wildcards: bool,
+}
+
+impl Field {
+    fn push_quoted(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(&glob::escape(text));
+    }
+
+    fn push_unquoted(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(text);
+        self.has_wildcards |= glob::has_wildcards(text);
+    }
+}
+
+impl Shell {
+    /// Expands the words of a command into its final arguments. The results of
+    /// unquoted expansions are split into several arguments on whitespace, and
+    /// then the ones with wildcards are replaced by the files they match.
+    pub fn expand_words(&mut self, words: &[Word]) -> Result<Vec<String>, String> {
+        let mut fields = vec![];
+        for word in words {
+            self.expand_fields(word, &mut fields)?;
+        }
+
+        let mut args = vec![];
+        for field in fields {
+            if !field.has_wildcards || self.options.noglob {
+                args.push(field.text);
+                continue;
+            }
+
+            let matches = glob::expand(&self.path, &field.pattern);
+            if !matches.is_empty() {
+                args.extend(matches);
+            }
+            else if self.options.failglob {
+                return Err(format!("No matches found for '{}'", field.text));
+            }
+            else if !self.options.nullglob {
+                args.push(field.text);
+            }
+        }
+
+        Ok(args)
+    }
+
+    fn expand_fields(&mut self, word: &Word, fields: &mut Vec<Field>) -> Result<(), String> {
+        let mut current = Field::default();
+        // Quoted parts create a field even if they're empty, unquoted expansions don't
+        let mut started = false;
+
+        let mut parts = word.parts.iter();
+        if let Some((home, rest)) = self.tilde(word) {
+            parts.next();
+            current.push_quoted(&home);
+            current.push_unquoted(rest);
+            started = true;
+        }
+
+        for part in parts {
+            match part {
+                WordPart::Literal(value) => {
+                    current.push_unquoted(value);
+                    started = true;
+                },
+                WordPart::Quoted(value) => {
+                    current.push_quoted(value);
+                    started = true;
+                },
+                // "$@" keeps every positional parameter as a separate argument
+                WordPart::Parameter(Parameter { name, modifier: None }, true) if name == "@" => {
+                    for (index, arg) in self.positional.iter().enumerate() {
+                        if index > 0 {
+                            fields.push(mem::take(&mut current));
+                        }
+
+                        current.push_quoted(arg);
+                        started = true;
+                    }
+                },
+                WordPart::Parameter(parameter, true) => {
+                    current.push_quoted(&self.parameter(parameter)?);
+                    started = true;
+                },
+                WordPart::Command(source, true) => {
+                    current.push_quoted(&self.substitute(source)?);
+                    started = true;
+                },
+                WordPart::Parameter(_, false) | WordPart::Command(_, false) => {
+                    let value = match part {
+                        WordPart::Parameter(parameter, _) => self.parameter(parameter)?,
+                        WordPart::Command(source, _) => self.substitute(source)?,
+                        _ => unreachable!()
+                    };
+
+                    let pieces: Vec<&str> = value.split(is_separator).filter(|piece| !piece.is_empty()).collect();
+
+                    if started && (pieces.is_empty() || value.starts_with(is_separator)) && !value.is_empty() {
+                        fields.push(mem::take(&mut current));
+                        started = false;
+                    }
+
+                    for (index, piece) in pieces.iter().enumerate() {
+                        if index > 0 {
+                            fields.push(mem::take(&mut current));
+                        }
+
+                        current.push_unquoted(piece);
+                        started = true;
+                    }
+
+                    if started && !pieces.is_empty() && value.ends_with(is_separator) {
+                        fields.push(mem::take(&mut current));
+                        started = false;
+                    }
+                }
+            }
+        }
+
+        if started {
+            fields.push(current);
+        }
+
+        Ok(())
+    }
+
+    /// Expands a word that must result in a single string, like a redirection target.
+    pub fn expand_word(&mut self, word: &Word) -> Result<String, String> {
+        Ok(self.expand_single(word)?.text)
+    }
+
+    /// Expands a word to be used as a pattern, like the ones of a `case` command,
+    /// so only its unquoted wildcards work.
+    pub fn expand_pattern(&mut self, word: &Word) -> Result<String, String> {
+        Ok(self.expand_single(word)?.pattern)
+    }
+
+    /// Expands a word into a single field, without splitting it.
+    fn expand_single(&mut self, word: &Word) -> Result<Field, String> {
+        let mut field = Field::default();
+        let mut parts = word.parts.iter();
+        if let Some((home, rest)) = self.tilde(word) {
+            parts.next();
+            field.push_quoted(&home);
+            field.push_unquoted(rest);
+        }
+
+        for part in parts {
+            match part {
+                WordPart::Literal(value) => field.push_unquoted(value),
+                WordPart::Quoted(value) => field.push_quoted(value),
+                WordPart::Parameter(parameter, true) => field.push_quoted(&self.parameter(parameter)?),
+                WordPart::Parameter(parameter, false) => field.push_unquoted(&self.parameter(parameter)?),
+                WordPart::Command(source, true) => field.push_quoted(&self.substitute(source)?),
+                WordPart::Command(source, false) => field.push_unquoted(&self.substitute(source)?)
+            }
+        }
+
+        Ok(field)
+    }
+
+    /// Runs the source of a command substitution and returns its output, without
+    /// the newlines at its end. Like in a subshell, the changes it makes to the
+    /// state of the shell (like variables, functions, traps, completions or the
+    /// current directory) are undone.
+    pub fn substitute(&mut self, source: &str) -> Result<String, String> {
+        let list = self.parse(source)
+            .map_err(|error| format!("Invalid syntax in command substitution: {error}"))?;
+
+        let (output, capture) = Capture::new()
+            .map_err(|error| format!("Error running the command substitution: {error}"))?;
+
+        let mut io = Streams { stdin: Input::Stdin, stdout: output, stderr: Output::Stderr };
+        let path = self.path.clone();
+        let options = self.options.clone();
+        let variables = self.variables.clone();
+        let aliases = self.aliases.clone();
+        let functions = self.functions.clone();
+        let completions = self.completions.clone();
+        let positional = self.positional.clone();
+        let traps = self.traps.clone();
+        let detached = self.history.detached();
+        let history = mem::replace(&mut self.history, detached);
+        let exiting = self.exiting;
+        // The commands get their own jobs, without job control since their output is waited for
+        let jobs = mem::take(&mut self.jobs);
+        self.jobs.last = jobs.last;
+
+        self.run_list(&list, &mut io);
+        // The capture only ends once its output has been closed
+        drop(io);
+
+        if self.path != path {
+            let _ = env::set_current_dir(&path);
+            self.path = path;
+        }
+
+        self.options = options;
+        self.variables = variables;
+        self.aliases = aliases;
+        self.functions = functions;
+        self.completions = completions;
+        self.positional = positional;
+        self.traps.restore(traps);
+        self.history = history;
+        self.jobs = jobs;
+        self.flow = None;
+        self.exiting = exiting;
+        self.substitution = Some(self.status);
+
+        let output = String::from_utf8_lossy(&capture.finish()).into_owned();
+        Ok(output.trim_end_matches(['\n', '\r']).to_owned())
+    }
+
+    /// Replaces the unquoted `~` or `~user` at the start of a word with a home
+    /// directory, returning it along with the rest of the first part of the word.
+    /// The prefix is left as it is if the home directory can't be found.
+    fn tilde<'a>(&self, word: &'a Word) -> Option<(String, &'a str)> {
+        let Some(WordPart::Literal(text)) = word.parts.first() else {
+            return None;
+        };
+
+        let rest = text.strip_prefix('~')?;
+        let (user, rest) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None if word.parts.len() == 1 => (rest, ""),
+            None => return None
+        };
+
+        let home = match user {
+            "" => self.home()?,
+            _ => users::user_home(user)?
+        };
+
+        Some((home, rest))
+    }
+
+    fn parameter(&mut self, parameter: &Parameter) -> Result<String, String> {
+        let name = &parameter.name;
+        let value = self.parameter_value(name);
+
+        let Some((modifier, colon, word)) = &parameter.modifier else {
+            return Ok(value.unwrap_or_default());
+        };
+
+        let is_set = match &value {
+            Some(value) => !colon || !value.is_empty(),
+            None => false
+        };
+
+        match modifier {
+            Modifier::Default if !is_set => self.expand_word(word),
+            Modifier::Assign if !is_set => {
+                if !variables::is_valid_name(name) {
+                    return Err(format!("{name}: A value can't be assigned this way"));
+                }
+
+                let value = self.expand_word(word)?;
+                self.variables.set(name, &value);
+                Ok(value)
+            },
+            Modifier::Error if !is_set => {
+                // Like in other shells, the error ends a script
+                if !self.interactive {
+                    self.exiting = true;
+                }
+
+                let message = self.expand_word(word)?;
+                match message.is_empty() {
+                    true => Err(format!("{name}: Parameter not set")),
+                    false => Err(format!("{name}: {message}"))
+                }
+            },
+            Modifier::Alternative if is_set => self.expand_word(word),
+            Modifier::Alternative => Ok(String::new()),
+            _ => Ok(value.unwrap_or_default())
+        }
+    }
+
+    /// Gets the value of a variable or special parameter, or `None` if it isn't set.
+    fn parameter_value(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.jobs.last.map(|pid| pid.to_string()),
+            "0" => Some(self.name.clone()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")),
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let index: usize = name.parse().ok()?;
+                self.positional.get(index.checked_sub(1)?).cloned()
+            },
+            _ => self.variables.get(name).map(str::to_owned)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::lexer::{self, Token};
+    use crate::traps::Condition;
+
+    fn shell() -> Shell {
+        let mut shell = Shell::new(PathBuf::from("."), false);
+        shell.variables.set("X", "x");
+        shell.variables.set("EMPTY", "");
+        shell.variables.set("SPACED", " a  b ");
+        shell.variables.unset("UNSET");
+        shell
+    }
+
+    /// Expands the words of a line like the ones of a command.
+    fn expand(shell: &mut Shell, line: &str) -> Result<Vec<String>, String> {
+        let words: Vec<Word> = lexer::tokenize(line).unwrap().into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word),
+                Token::Operator(..) => None
+            })
+            .collect();
+
+        shell.expand_words(&words)
+    }
+
+    #[test]
+    fn default_values() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "${X:-d} ${UNSET:-d} ${EMPTY:-d}").unwrap(), ["x", "d", "d"]);
+        // Without the colon, only missing variables get the default
+        assert_eq!(expand(&mut shell, "\"${EMPTY-d}\" ${UNSET-d}").unwrap(), ["", "d"]);
+        assert_eq!(shell.variables.get("UNSET"), None);
+    }
+
+    #[test]
+    fn assigned_defaults_stay() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "${UNSET:=v} ${X:=v}").unwrap(), ["v", "x"]);
+        assert_eq!(shell.variables.get("UNSET"), Some("v"));
+        assert!(expand(&mut shell, "${1:=v}").is_err());
+    }
+
+    #[test]
+    fn errors_for_missing_values() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "${UNSET:?is gone}"), Err(String::from("UNSET: is gone")));
+        assert_eq!(expand(&mut shell, "${EMPTY:?}"), Err(String::from("EMPTY: Parameter not set")));
+        assert_eq!(expand(&mut shell, "\"${EMPTY?}\" ${X:?no}").unwrap(), ["", "x"]);
+    }
+
+    #[test]
+    fn alternative_values() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "${X:+alt} ${UNSET:+alt} ${EMPTY:+alt}").unwrap(), ["alt"]);
+        assert_eq!(expand(&mut shell, "${EMPTY+alt}").unwrap(), ["alt"]);
+    }
+
+    #[test]
+    fn unquoted_values_are_split() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "$SPACED").unwrap(), ["a", "b"]);
+        assert_eq!(expand(&mut shell, "\"$SPACED\"").unwrap(), [" a  b "]);
+        assert_eq!(expand(&mut shell, "x${SPACED}y").unwrap(), ["x", "a", "b", "y"]);
+        assert!(expand(&mut shell, "$EMPTY $UNSET").unwrap().is_empty());
+    }
+
+    #[test]
+    fn tildes_at_the_start_are_home() {
+        let mut shell = shell();
+        shell.variables.set("HOME", "/home/me");
+        assert_eq!(expand(&mut shell, "~ ~/a a~ '~' \"~\"/a").unwrap(), ["/home/me", "/home/me/a", "a~", "~", "~/a"]);
+        // The home directory isn't split or matched against files
+        shell.variables.set("HOME", "/a b/*");
+        assert_eq!(expand(&mut shell, "~/c").unwrap(), ["/a b/*/c"]);
+    }
+
+    #[test]
+    fn command_substitutions_are_split_unless_quoted() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "$(echo 'a  b') \"$(echo 'a  b')\" `echo c`").unwrap(), ["a", "b", "a  b", "c"]);
+        // The line breaks at the end are removed
+        assert_eq!(expand(&mut shell, "\"$(printf 'x\\n\\n')\"").unwrap(), ["x"]);
+    }
+
+    #[test]
+    fn command_substitutions_change_nothing() {
+        let mut shell = shell();
+        assert_eq!(expand(&mut shell, "$(X=changed; set -o pipefail; echo $X; exit; echo after)").unwrap(), ["changed"]);
+        assert_eq!(shell.variables.get("X"), Some("x"));
+        assert!(!shell.options.pipefail);
+        assert!(!shell.exiting);
+
+        assert!(expand(&mut shell, "$(false)").unwrap().is_empty());
+        assert_eq!(shell.substitution, Some(1));
+    }
+
+    #[test]
+    fn command_substitutions_keep_arguments_traps_history_and_completions() {
+        let mut shell = shell();
+        shell.positional = vec![String::from("first")];
+        shell.history.add("echo before").unwrap();
+        shell.jobs.last = Some(42);
+
+        let words = expand(&mut shell, "$(shift; trap : ERR; history -c; complete -s run cargo; echo $# $!)").unwrap();
+        assert_eq!(words, ["0", "42"]);
+        assert_eq!(shell.positional, ["first"]);
+        assert!(shell.traps.get(Condition::Err).is_none());
+        assert_eq!(shell.history.entries(), ["echo before"]);
+        assert_eq!(shell.completions.iter().count(), 0);
+    }
+}
@@ -0,0 +1,931 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// How many commits are looked at when counting the ones ahead and behind the
+/// upstream branch, so branches that diverged long ago don't slow the prompt down.
+const MAX_WALKED: usize = 10_000;
+
+/// How long the prompt waits for a new check of the changes before showing the
+/// result of the previous one.
+const CHANGES_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// The name of an object, the SHA-1 of its contents.
+type Id = [u8; 20];
+
+/// How many commits a branch has that another one doesn't, and the other way around.
+type Divergence = (usize, usize);
+
+/// A git repository, read directly from its files instead of running `git`,
+/// which is too slow to do before each prompt in large repositories.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    /// Where `HEAD` and the index are.
+    git_dir: PathBuf,
+    /// Where the refs and objects are, which is different in linked worktrees.
+    common_dir: PathBuf,
+    /// The directory with the files checked out.
+    work_tree: PathBuf,
+}
+
+/// What changed in the checked out files since the last commit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Changes {
+    /// Whether there are changes added to the index.
+    pub staged: bool,
+    /// Whether there are changes in the files that aren't in the index yet.
+    /// Untracked files aren't counted.
+    pub dirty: bool,
+}
+
+enum Head {
+    Branch(String),
+    Detached(Id),
+}
+
+impl Repository {
+    /// Finds the repository a directory is in, looking at its parents too.
+    pub fn find(start: &Path) -> Option<Repository> {
+        for dir in start.ancestors() {
+            let git = dir.join(".git");
+            // In worktrees and submodules `.git` is a file pointing to the git directory
+            let git_dir = match git.is_file() {
+                true => {
+                    let contents = fs::read_to_string(&git).ok()?;
+                    dir.join(contents.trim().strip_prefix("gitdir:")?.trim())
+                },
+                false if git.is_dir() => git,
+                false => continue
+            };
+
+            let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common) => git_dir.join(common.trim()),
+                Err(_) => git_dir.clone()
+            };
+
+            return Some(Repository { git_dir, common_dir, work_tree: dir.to_owned() });
+        }
+
+        None
+    }
+
+    /// The branch checked out, or the start of the commit when no branch is (a detached HEAD).
+    pub fn branch(&self) -> Option<String> {
+        match self.head()? {
+            Head::Branch(name) => Some(name.strip_prefix("refs/heads/").unwrap_or(&name).to_owned()),
+            Head::Detached(id) => Some(hex(&id)[..7].to_owned())
+        }
+    }
+
+    /// The commits the branch checked out and its upstream branch point to.
+    /// `None` when it has no upstream branch.
+    fn tracked(&self) -> Option<(Id, Id)> {
+        let Head::Branch(branch) = self.head()? else {
+            return None;
+        };
+
+        let local = self.resolve(&branch)?;
+        let upstream = self.resolve(&self.upstream(branch.strip_prefix("refs/heads/")?)?)?;
+        Some((local, upstream))
+    }
+
+    /// Compares the index with the last commit and the checked out files. It can
+    /// take a while in large repositories, so the prompt uses a [`Watcher`] to run it.
+    pub fn changes(&self) -> Option<Changes> {
+        let index = Index::read(&self.git_dir.join("index"))?;
+        let objects = Objects::new(&self.common_dir);
+
+        let staged = match self.resolve("HEAD") {
+            Some(head) => {
+                let tree = objects.commit(head)?.tree;
+                // The index remembers the tree it was written from, which avoids reading them all
+                match index.tree {
+                    Some(cached) if cached == tree => false,
+                    _ => {
+                        let mut files = HashMap::new();
+                        objects.list_tree(tree, "", &mut files)?;
+                        index.entries.len() != files.len()
+                            || index.entries.iter().any(|entry| files.get(&entry.path) != Some(&(entry.mode, entry.id)))
+                    }
+                }
+            },
+            None => !index.entries.is_empty()
+        };
+
+        let dirty = index.entries.iter().any(|entry| entry.is_modified(&self.work_tree));
+        Some(Changes { staged, dirty })
+    }
+
+    fn head(&self) -> Option<Head> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        match head.trim().strip_prefix("ref:") {
+            Some(name) => Some(Head::Branch(name.trim().to_owned())),
+            None => parse_hex(head.trim()).map(Head::Detached)
+        }
+    }
+
+    /// The object a ref points to, following the symbolic refs like `HEAD`.
+    fn resolve(&self, name: &str) -> Option<Id> {
+        let mut name = name.to_owned();
+
+        // Symbolic refs could point to each other forever
+        for _ in 0..5 {
+            // `HEAD` and the refs of the worktree are in its own directory, and the rest are shared
+            let dir = match name.starts_with("refs/") && !name.starts_with("refs/worktree/") {
+                true => &self.common_dir,
+                false => &self.git_dir
+            };
+
+            let Ok(contents) = fs::read_to_string(dir.join(&name)) else {
+                return self.packed_ref(&name);
+            };
+
+            match contents.trim().strip_prefix("ref:") {
+                Some(target) => name = target.trim().to_owned(),
+                None => return parse_hex(contents.trim())
+            }
+        }
+
+        None
+    }
+
+    /// Looks for a ref in `packed-refs`, where git moves them from their own files.
+    fn packed_ref(&self, name: &str) -> Option<Id> {
+        let packed = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        packed.lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .find_map(|line| match line.split_once(' ') {
+                Some((id, reference)) if reference == name => parse_hex(id),
+                _ => None
+            })
+    }
+
+    /// The ref of the branch a local branch is set to track, from the configuration.
+    fn upstream(&self, branch: &str) -> Option<String> {
+        let config = fs::read_to_string(self.common_dir.join("config")).ok()?;
+        let header = format!("[branch \"{branch}\"]");
+        let (mut remote, mut merge) = (None, None);
+        let mut inside = false;
+
+        for line in config.lines().map(str::trim) {
+            if line.starts_with('[') {
+                inside = line == header;
+                continue;
+            }
+
+            if let (true, Some((key, value))) = (inside, line.split_once('=')) {
+                match key.trim() {
+                    "remote" => remote = Some(value.trim().to_owned()),
+                    "merge" => merge = Some(value.trim().to_owned()),
+                    _ => ()
+                }
+            }
+        }
+
+        let merge = merge?;
+        match remote?.as_str() {
+            // A branch can also track another local branch
+            "." => Some(merge),
+            remote => Some(format!("refs/remotes/{remote}/{}", merge.strip_prefix("refs/heads/")?))
+        }
+    }
+}
+
+/// Checks the changes of repositories in the background, so the prompt can show
+/// them without waiting in large repositories. A check is started each time the
+/// prompt is shown, and its result is shown then if it's quick enough, or the
+/// next time otherwise.
+#[derive(Debug, Default)]
+pub struct Watcher {
+    running: Option<(PathBuf, Receiver<Option<Changes>>)>,
+    /// The last changes found, with the directory of the repository.
+    last: Option<(PathBuf, Changes)>,
+    /// The last commits ahead and behind counted, with the commits of both branches.
+    divergence: Option<((Id, Id), Option<Divergence>)>,
+}
+
+impl Watcher {
+    /// How many commits the branch checked out has that its upstream branch
+    /// doesn't, and the other way around. `None` when it has no upstream branch.
+    /// Counting them takes a while, so it's only done again once a branch moves.
+    pub fn divergence(&mut self, repository: &Repository) -> Option<Divergence> {
+        let tracked = repository.tracked()?;
+        // Commits are named by their contents, so the count is the same in any repository
+        if let Some((counted, divergence)) = self.divergence {
+            if counted == tracked {
+                return divergence;
+            }
+        }
+
+        let divergence = Objects::new(&repository.common_dir).divergence(tracked.0, tracked.1);
+        self.divergence = Some((tracked, divergence));
+        divergence
+    }
+
+    pub fn changes(&mut self, repository: &Repository) -> Option<Changes> {
+        // The check started the last time may have finished since then
+        if let Some((_, receiver)) = &self.running {
+            match receiver.try_recv() {
+                Ok(changes) => self.finish(changes),
+                Err(TryRecvError::Disconnected) => self.running = None,
+                Err(TryRecvError::Empty) => ()
+            }
+        }
+
+        if self.running.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            let checked = repository.clone();
+            thread::spawn(move || sender.send(checked.changes()));
+
+            match receiver.recv_timeout(CHANGES_TIMEOUT) {
+                Ok(changes) => self.last = changes.map(|changes| (repository.work_tree.clone(), changes)),
+                Err(RecvTimeoutError::Timeout) => self.running = Some((repository.work_tree.clone(), receiver)),
+                Err(RecvTimeoutError::Disconnected) => ()
+            }
+        }
+
+        match &self.last {
+            Some((dir, changes)) if *dir == repository.work_tree => Some(*changes),
+            _ => None
+        }
+    }
+
+    fn finish(&mut self, changes: Option<Changes>) {
+        if let Some((dir, _)) = self.running.take() {
+            self.last = changes.map(|changes| (dir, changes));
+        }
+    }
+}
+
+/// The files in the index, which is what the next commit will have.
+struct Index {
+    entries: Vec<Entry>,
+    /// The tree of all the entries, when the index has it cached.
+    tree: Option<Id>,
+}
+
+struct Entry {
+    path: String,
+    mode: u32,
+    id: Id,
+    size: u32,
+    modified: (u32, u32),
+    /// Set for conflicts, which count as changes.
+    stage: u16,
+    /// Set for the files that aren't checked out, like in sparse checkouts.
+    skip: bool,
+}
+
+impl Index {
+    fn read(path: &Path) -> Option<Index> {
+        let data = fs::read(path).ok()?;
+        if data.get(..4)? != b"DIRC" {
+            return None;
+        }
+
+        let version = be32(&data, 4)?;
+        let count = be32(&data, 8)? as usize;
+        let mut entries: Vec<Entry> = Vec::with_capacity(count);
+        let mut position = 12;
+
+        for _ in 0..count {
+            let start = position;
+            let field = |index: usize| be32(&data, start + index * 4);
+            let flags = be16(&data, start + 60)?;
+            let mut extended = 0;
+            position += 62;
+            if version >= 3 && flags & 0x4000 != 0 {
+                extended = be16(&data, position)?;
+                position += 2;
+            }
+
+            // Version 4 writes each path as the end of the previous one, after removing some characters
+            let mut path = match version {
+                4 => {
+                    let (removed, length) = varint(&data[position..])?;
+                    position += length;
+                    let previous = entries.last().map_or("", |entry| entry.path.as_str());
+                    previous.get(..previous.len().checked_sub(removed)?)?.to_owned()
+                },
+                _ => String::new()
+            };
+
+            let end = position + data.get(position..)?.iter().position(|byte| *byte == 0)?;
+            path += std::str::from_utf8(&data[position..end]).ok()?;
+            position = match version {
+                4 => end + 1,
+                // Entries are padded with at least a zero to a multiple of 8 bytes
+                _ => start + (end - start + 8) / 8 * 8
+            };
+
+            entries.push(Entry {
+                path,
+                mode: field(6)?,
+                id: data.get(start + 40..start + 60)?.try_into().ok()?,
+                size: field(9)?,
+                modified: (field(2)?, field(3)?),
+                stage: (flags >> 12) & 3,
+                skip: extended & 0x4000 != 0,
+            });
+        }
+
+        let mut tree = None;
+        // The extensions are followed by the checksum of the file
+        while position + 8 <= data.len().saturating_sub(20) {
+            let size = be32(&data, position + 4)? as usize;
+            let contents = data.get(position + 8..position + 8 + size)?;
+            if &data[position..position + 4] == b"TREE" {
+                tree = cached_tree(contents);
+            }
+            position += 8 + size;
+        }
+
+        Some(Index { entries, tree })
+    }
+}
+
+/// Gets the cached tree of the root directory from the `TREE` extension of the
+/// index. It starts with its path (empty), the number of entries (-1 when it's
+/// out of date), the number of subtrees and its id.
+fn cached_tree(contents: &[u8]) -> Option<Id> {
+    let line_end = contents.iter().position(|byte| *byte == b'\n')?;
+    let line = std::str::from_utf8(&contents[..line_end]).ok()?;
+    let (path, counts) = line.split_once('\0')?;
+    let entries: i64 = counts.split(' ').next()?.parse().ok()?;
+
+    match path.is_empty() && entries >= 0 {
+        true => contents.get(line_end + 1..line_end + 21)?.try_into().ok(),
+        false => None
+    }
+}
+
+impl Entry {
+    /// Whether the checked out file is different from the one in the index.
+    fn is_modified(&self, work_tree: &Path) -> bool {
+        const SYMLINK: u32 = 0o120000;
+        const SUBMODULE: u32 = 0o160000;
+
+        if self.stage != 0 {
+            return true;
+        }
+
+        if self.skip || self.mode == SUBMODULE {
+            return false;
+        }
+
+        let path = work_tree.join(&self.path);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return true;
+        };
+
+        if metadata.is_symlink() != (self.mode == SYMLINK) || is_executable(&metadata) != (self.mode == 0o100755) {
+            return true;
+        }
+
+        // Files whose size and time didn't change are considered the same, like git does
+        if metadata.len() as u32 != self.size {
+            return true;
+        }
+        if modified(&metadata) == Some(self.modified) {
+            return false;
+        }
+
+        let contents = match metadata.is_symlink() {
+            true => fs::read_link(&path).ok().and_then(|target| target.to_str().map(|target| target.as_bytes().to_vec())),
+            false => fs::read(&path).ok()
+        };
+
+        let Some(contents) = contents else {
+            return true;
+        };
+
+        let mut hash = sha1_smol::Sha1::new();
+        hash.update(format!("blob {}\0", contents.len()).as_bytes());
+        hash.update(&contents);
+        hash.digest().bytes() != self.id
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+/// Other systems don't have the executable bit, so the one in the index is trusted.
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// The time a file was modified, in seconds and nanoseconds like in the index.
+#[cfg(unix)]
+fn modified(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32))
+}
+
+#[cfg(not(unix))]
+fn modified(_metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+struct Commit {
+    tree: Id,
+    parents: Vec<Id>,
+    time: i64,
+}
+
+/// Reads the objects of a repository, both the loose ones and the ones in packs.
+struct Objects {
+    dir: PathBuf,
+    /// The indexes of the packs.
+    packs: Vec<PathBuf>,
+}
+
+impl Objects {
+    fn new(common_dir: &Path) -> Objects {
+        let dir = common_dir.join("objects");
+        let packs = fs::read_dir(dir.join("pack")).into_iter().flatten().flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "idx"))
+            .collect();
+
+        Objects { dir, packs }
+    }
+
+    /// Reads an object, returning its type and contents.
+    fn read(&self, id: Id) -> Option<(u8, Vec<u8>)> {
+        let name = hex(&id);
+        if let Ok(compressed) = fs::read(self.dir.join(&name[..2]).join(&name[2..])) {
+            let data = miniz_oxide::inflate::decompress_to_vec_zlib(&compressed).ok()?;
+            let header_end = data.iter().position(|byte| *byte == 0)?;
+            let kind = match data[..header_end].split(|byte| *byte == b' ').next()? {
+                b"commit" => COMMIT,
+                b"tree" => TREE,
+                b"blob" => BLOB,
+                b"tag" => TAG,
+                _ => return None
+            };
+            return Some((kind, data[header_end + 1..].to_vec()));
+        }
+
+        self.packs.iter().find_map(|index| {
+            let offset = pack_offset(index, &id)?;
+            let mut pack = File::open(index.with_extension("pack")).ok()?;
+            self.read_packed(&mut pack, offset, 0)
+        })
+    }
+
+    /// Reads an object at an offset of a pack. Objects can be stored as the
+    /// changes to another one, so `depth` keeps a broken pack from looping forever.
+    fn read_packed(&self, pack: &mut File, offset: u64, depth: usize) -> Option<(u8, Vec<u8>)> {
+        const OFFSET_DELTA: u8 = 6;
+        const ID_DELTA: u8 = 7;
+
+        if depth > 100 {
+            return None;
+        }
+
+        let mut header = [0; 32];
+        pack.seek(SeekFrom::Start(offset)).ok()?;
+        let read = read_up_to(pack, &mut header)?;
+        let header = &header[..read];
+
+        let kind = (header.first()? >> 4) & 7;
+        let mut size = (header[0] & 15) as usize;
+        let mut position = 1;
+        let mut shift = 4;
+        while header[position - 1] & 0x80 != 0 {
+            size |= ((*header.get(position)? & 0x7F) as usize) << shift;
+            shift += 7;
+            position += 1;
+        }
+
+        let base = match kind {
+            OFFSET_DELTA => {
+                let mut byte = *header.get(position)?;
+                let mut distance = (byte & 0x7F) as u64;
+                position += 1;
+                while byte & 0x80 != 0 {
+                    byte = *header.get(position)?;
+                    distance = ((distance + 1) << 7) | (byte & 0x7F) as u64;
+                    position += 1;
+                }
+                Some(self.read_packed(pack, offset.checked_sub(distance)?, depth + 1)?)
+            },
+            ID_DELTA => {
+                let id = header.get(position..position + 20)?.try_into().ok()?;
+                position += 20;
+                Some(self.read(id)?)
+            },
+            _ => None
+        };
+
+        // Compressed data is at most a bit larger than the original
+        let mut compressed = vec![0; size + size / 8 + 64];
+        pack.seek(SeekFrom::Start(offset + position as u64)).ok()?;
+        let read = read_up_to(pack, &mut compressed)?;
+        let data = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&compressed[..read], size).ok()?;
+
+        match base {
+            Some((kind, base)) => Some((kind, apply_delta(&base, &data)?)),
+            None => Some((kind, data))
+        }
+    }
+
+    fn commit(&self, id: Id) -> Option<Commit> {
+        let (kind, data) = self.read(id)?;
+        if kind != COMMIT {
+            return None;
+        }
+
+        let text = String::from_utf8_lossy(&data);
+        let mut commit = Commit { tree: [0; 20], parents: vec![], time: 0 };
+        // The headers end with an empty line, before the message
+        for line in text.lines().take_while(|line| !line.is_empty()) {
+            match line.split_once(' ') {
+                Some(("tree", id)) => commit.tree = parse_hex(id)?,
+                Some(("parent", id)) => commit.parents.push(parse_hex(id)?),
+                Some(("committer", rest)) => commit.time = rest.rsplit(' ').nth(1)?.parse().ok()?,
+                _ => ()
+            }
+        }
+
+        Some(commit)
+    }
+
+    /// Adds the files in a tree and its subtrees, with their modes and ids.
+    fn list_tree(&self, id: Id, prefix: &str, files: &mut HashMap<String, (u32, Id)>) -> Option<()> {
+        let (kind, data) = self.read(id)?;
+        if kind != TREE {
+            return None;
+        }
+
+        let mut position = 0;
+        while position < data.len() {
+            let space = position + data[position..].iter().position(|byte| *byte == b' ')?;
+            let nul = space + data[space..].iter().position(|byte| *byte == 0)?;
+            let mode = u32::from_str_radix(std::str::from_utf8(&data[position..space]).ok()?, 8).ok()?;
+            let name = String::from_utf8_lossy(&data[space + 1..nul]);
+            let id: Id = data.get(nul + 1..nul + 21)?.try_into().ok()?;
+            position = nul + 21;
+
+            let path = format!("{prefix}{name}");
+            match mode {
+                0o40000 => self.list_tree(id, &format!("{path}/"), files)?,
+                _ => {
+                    files.insert(path, (mode, id));
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    /// Counts the commits reachable from each of two commits but not the other.
+    /// Commits are walked from the newest, marking which side reaches them, until
+    /// only the ones reachable from both are left.
+    fn divergence(&self, local: Id, upstream: Id) -> Option<Divergence> {
+        const LOCAL: u8 = 1;
+        const UPSTREAM: u8 = 2;
+        const BOTH: u8 = LOCAL | UPSTREAM;
+
+        let mut reached: HashMap<Id, u8> = HashMap::new();
+        // Commits made in the same second are walked in the order they were found, like git does
+        let mut queue = BinaryHeap::new();
+        let mut found = 0;
+        for (id, side) in [(local, LOCAL), (upstream, UPSTREAM)] {
+            *reached.entry(id).or_default() |= side;
+            queue.push((self.commit(id)?.time, Reverse(found), id));
+            found += 1;
+        }
+
+        while queue.iter().any(|(_, _, id)| reached[id] != BOTH) {
+            let Some((_, _, id)) = queue.pop() else {
+                break;
+            };
+
+            if found > MAX_WALKED {
+                return None;
+            }
+
+            let side = reached[&id];
+            for parent in self.commit(id)?.parents {
+                let marks = reached.entry(parent).or_default();
+                if *marks | side != *marks {
+                    *marks |= side;
+                    queue.push((self.commit(parent)?.time, Reverse(found), parent));
+                    found += 1;
+                }
+            }
+        }
+
+        let count = |side| reached.values().filter(|marks| **marks == side).count();
+        Some((count(LOCAL), count(UPSTREAM)))
+    }
+}
+
+const COMMIT: u8 = 1;
+const TREE: u8 = 2;
+const BLOB: u8 = 3;
+const TAG: u8 = 4;
+
+/// Finds where an object is in a pack, using the binary search its index (version 2) is made for.
+fn pack_offset(index: &Path, id: &Id) -> Option<u64> {
+    let mut file = File::open(index).ok()?;
+    let mut header = vec![0; 8 + 256 * 4];
+    file.read_exact(&mut header).ok()?;
+    if header[..8] != [0xFF, b't', b'O', b'c', 0, 0, 0, 2] {
+        return None;
+    }
+
+    // The number of objects with a first byte up to each value
+    let fanout = |byte: usize| be32(&header, 8 + byte * 4).map(|count| count as u64);
+    let total = fanout(255)?;
+    let mut low = match id[0] {
+        0 => 0,
+        byte => fanout(byte as usize - 1)?
+    };
+    let mut high = fanout(id[0] as usize)?;
+
+    let ids_start = header.len() as u64;
+    let mut found = [0; 20];
+    while low < high {
+        let middle = (low + high) / 2;
+        file.seek(SeekFrom::Start(ids_start + middle * 20)).ok()?;
+        file.read_exact(&mut found).ok()?;
+
+        match found.cmp(id) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => {
+                // The ids are followed by their checksums and then by their offsets
+                let offsets_start = ids_start + total * 24;
+                let mut offset = [0; 4];
+                file.seek(SeekFrom::Start(offsets_start + middle * 4)).ok()?;
+                file.read_exact(&mut offset).ok()?;
+                let offset = u32::from_be_bytes(offset);
+
+                // Offsets too large for 31 bits are in another table after that
+                if offset & 0x8000_0000 == 0 {
+                    return Some(offset as u64);
+                }
+
+                let mut large = [0; 8];
+                file.seek(SeekFrom::Start(offsets_start + total * 4 + (offset & 0x7FFF_FFFF) as u64 * 8)).ok()?;
+                file.read_exact(&mut large).ok()?;
+                return Some(u64::from_be_bytes(large));
+            }
+        }
+    }
+
+    None
+}
+
+/// Rebuilds an object stored as the changes to another, which are instructions
+/// to copy parts of the other one and to insert new data.
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let (base_size, read) = delta_size(delta)?;
+    let mut position = read;
+    let (size, read) = delta_size(&delta[position..])?;
+    position += read;
+
+    if base_size != base.len() {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(size);
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+
+        if instruction & 0x80 == 0 {
+            let length = instruction as usize;
+            result.extend_from_slice(delta.get(position..position + length)?);
+            position += length;
+            continue;
+        }
+
+        // Each bit says whether a byte of the offset or the length is there
+        let mut values = [0usize; 2];
+        for (bit, shift, value) in (0..7).map(|bit| (bit, bit % 4 * 8, bit / 4)) {
+            if instruction & (1 << bit) != 0 {
+                values[value] |= (*delta.get(position)? as usize) << shift;
+                position += 1;
+            }
+        }
+
+        let [offset, length] = values;
+        let length = if length == 0 { 0x10000 } else { length };
+        result.extend_from_slice(base.get(offset..offset + length)?);
+    }
+
+    (result.len() == size).then_some(result)
+}
+
+/// Reads the sizes at the start of a delta, 7 bits per byte starting with the lowest.
+fn delta_size(data: &[u8]) -> Option<(usize, usize)> {
+    let mut size = 0;
+    for (index, byte) in data.iter().enumerate().take(10) {
+        size |= ((byte & 0x7F) as usize) << (index * 7);
+        if byte & 0x80 == 0 {
+            return Some((size, index + 1));
+        }
+    }
+
+    None
+}
+
+/// Reads the numbers of the paths in version 4 indexes, returning it and how many bytes it took.
+fn varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (index, byte) in data.iter().enumerate().take(10) {
+        value = (value << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+        value += 1;
+    }
+
+    None
+}
+
+/// Fills as much of a buffer as possible, stopping at the end of the file.
+fn read_up_to(file: &mut File, buffer: &mut [u8]) -> Option<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]).ok()? {
+            0 => break,
+            read => filled += read
+        }
+    }
+
+    Some(filled)
+}
+
+fn be32(data: &[u8], position: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(position..position + 4)?.try_into().ok()?))
+}
+
+fn be16(data: &[u8], position: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(position..position + 2)?.try_into().ok()?))
+}
+
+fn parse_hex(text: &str) -> Option<Id> {
+    if text.len() != 40 {
+        return None;
+    }
+
+    let mut id = [0; 20];
+    for (index, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(id)
+}
+
+fn hex(id: &Id) -> String {
+    id.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bare repository made with git, with the commits of `main` split between a pack
+    /// whose deltas point to their base by offset, one whose deltas name it by id,
+    /// and a loose object. `main` is 4 commits ahead of `origin/main` and 2 behind.
+    fn fixture() -> Repository {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/packed.git");
+        Repository { git_dir: dir.clone(), common_dir: dir.clone(), work_tree: dir }
+    }
+
+    /// The ids in the index of a pack, in order.
+    fn pack_ids(index: &Path) -> Vec<Id> {
+        let data = fs::read(index).unwrap();
+        let total = be32(&data, 8 + 255 * 4).unwrap() as usize;
+        let ids = &data[8 + 256 * 4..][..total * 20];
+        ids.chunks(20).map(|id| id.try_into().unwrap()).collect()
+    }
+
+    fn hash(kind: u8, data: &[u8]) -> Id {
+        let name = match kind {
+            COMMIT => "commit",
+            TREE => "tree",
+            BLOB => "blob",
+            TAG => "tag",
+            _ => panic!("unknown object type {kind}")
+        };
+
+        let mut hash = sha1_smol::Sha1::new();
+        hash.update(format!("{name} {}\0", data.len()).as_bytes());
+        hash.update(data);
+        hash.digest().bytes()
+    }
+
+    #[test]
+    fn reads_every_packed_object() {
+        let objects = Objects::new(&fixture().common_dir);
+        assert_eq!(objects.packs.len(), 2);
+
+        let mut stored = vec![];
+        for index in &objects.packs {
+            let mut pack = File::open(index.with_extension("pack")).unwrap();
+            for id in pack_ids(index) {
+                let offset = pack_offset(index, &id).unwrap();
+                let mut header = [0];
+                pack.seek(SeekFrom::Start(offset)).unwrap();
+                pack.read_exact(&mut header).unwrap();
+                stored.push((header[0] >> 4) & 7);
+
+                // Rebuilding a delta wrongly would give other contents, with another id
+                let (kind, data) = objects.read(id).unwrap();
+                assert_eq!(hex(&hash(kind, &data)), hex(&id));
+            }
+        }
+
+        // The fixture has to keep both kinds of deltas to test them
+        assert!(stored.contains(&6), "no deltas by offset");
+        assert!(stored.contains(&7), "no deltas by id");
+    }
+
+    #[test]
+    fn reads_commits_and_trees() {
+        let repository = fixture();
+        let objects = Objects::new(&repository.common_dir);
+        let head = repository.resolve("HEAD").unwrap();
+        assert_eq!(hex(&head), "70668c703385bcbe4aa884d633443b170f2f8a61");
+
+        // The last commit is a loose object, and its parent is in a pack
+        let commit = objects.commit(head).unwrap();
+        assert_eq!(commit.parents.len(), 1);
+        assert!(objects.commit(commit.parents[0]).is_some());
+
+        let mut files = HashMap::new();
+        objects.list_tree(commit.tree, "", &mut files).unwrap();
+        let (mode, id) = files["file.txt"];
+        assert_eq!(mode, 0o100644);
+
+        let (kind, data) = objects.read(id).unwrap();
+        assert_eq!(kind, BLOB);
+        let text = String::from_utf8(data).unwrap();
+        assert_eq!(text.lines().count(), 200);
+        assert_eq!(text.lines().nth(89), Some("line 90 changed in version 9"));
+    }
+
+    #[test]
+    fn resolves_loose_and_packed_refs() {
+        let repository = fixture();
+        assert_eq!(repository.branch().as_deref(), Some("main"));
+        assert_eq!(repository.upstream("main").as_deref(), Some("refs/remotes/origin/main"));
+
+        let upstream = repository.resolve("refs/remotes/origin/main").unwrap();
+        assert_eq!(hex(&upstream), "49da424fd805a5c5a44326568c5639280f0bf624");
+        assert!(repository.resolve("refs/heads/missing").is_none());
+    }
+
+    #[test]
+    fn counts_commits_ahead_and_behind() {
+        let repository = fixture();
+        let mut watcher = Watcher::default();
+        assert_eq!(watcher.divergence(&repository), Some((4, 2)));
+        // The second time it comes from the cache
+        assert_eq!(watcher.divergence(&repository), Some((4, 2)));
+
+        let (local, upstream) = repository.tracked().unwrap();
+        let objects = Objects::new(&repository.common_dir);
+        assert_eq!(objects.divergence(upstream, local), Some((2, 4)));
+        assert_eq!(objects.divergence(local, local), Some((0, 0)));
+    }
+
+    #[test]
+    fn applies_deltas() {
+        let base = b"hello world";
+        // The sizes, a copy of 5 bytes from offset 6 and an insertion of 6 bytes
+        let mut delta = vec![11, 11, 0x80 | 0x01 | 0x10, 6, 5, 6];
+        delta.extend_from_slice(b" there");
+        assert_eq!(apply_delta(base, &delta).as_deref(), Some(&b"world there"[..]));
+
+        // A delta made for another base, or giving another size, is rejected
+        delta[0] = 12;
+        assert_eq!(apply_delta(base, &delta), None);
+        delta[0] = 11;
+        delta[1] = 12;
+        assert_eq!(apply_delta(base, &delta), None);
+    }
+
+    #[test]
+    fn reads_delta_sizes() {
+        assert_eq!(delta_size(&[0x05]), Some((5, 1)));
+        assert_eq!(delta_size(&[0x80 | 0x10, 0x01]), Some((0x90, 2)));
+        assert_eq!(delta_size(&[0x80]), None);
+    }
+}
@@ -0,0 +1,386 @@
+use std::path::Path;
+
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`, with whether it was negated with `!` or `^` and its ranges.
+    Class(bool, Vec<(char, char)>),
+}
+
+/// Checks whether a pattern has any unescaped wildcard.
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            },
+            '*' | '?' | '[' => return true,
+            _ => ()
+        }
+    }
+
+    false
+}
+
+/// Escapes the wildcards of a text, so it only matches itself.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Removes the escaping backslashes of a pattern.
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c)
+        }
+    }
+
+    text
+}
+
+fn parse(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = vec![];
+    let mut index = 0;
+
+    while index < chars.len() {
+        let token = match chars[index] {
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                Token::Char(chars[index])
+            },
+            '?' => Token::Any,
+            '*' => Token::Star,
+            '[' => match parse_class(&chars[index + 1..]) {
+                Some((class, length)) => {
+                    index += length;
+                    class
+                },
+                None => Token::Char('[')
+            },
+            c => Token::Char(c)
+        };
+
+        tokens.push(token);
+        index += 1;
+    }
+
+    tokens
+}
+
+/// Parses a bracket expression after its opening bracket, returning it along with
+/// the amount of characters it used. A `]` right at the start is taken literally.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut index = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut ranges = vec![];
+    let mut first = true;
+
+    loop {
+        let mut c = *chars.get(index)?;
+        if c == ']' && !first {
+            return Some((Token::Class(negated, ranges), index + 1));
+        }
+
+        if c == '\\' {
+            index += 1;
+            c = *chars.get(index)?;
+        }
+
+        first = false;
+        index += 1;
+
+        match (chars.get(index), chars.get(index + 1)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                ranges.push((c, end));
+                index += 2;
+            },
+            _ => ranges.push((c, c))
+        }
+    }
+}
+
+impl Token {
+    /// Checks whether a token that isn't a `*` matches a character.
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => c == *expected,
+            Token::Any => true,
+            Token::Class(negated, ranges) => ranges.iter().any(|(start, end)| (*start..=*end).contains(&c)) != *negated,
+            Token::Star => false
+        }
+    }
+}
+
+/// Matches the tokens against a name without recursion. When a token fails, the
+/// last `*` takes one more character and the match goes on from there. Earlier
+/// stars never need to take more, since the last one can already skip anything,
+/// so it runs in quadratic time at worst.
+fn matches_tokens(tokens: &[Token], name: &[char]) -> bool {
+    let (mut token, mut position) = (0, 0);
+    // The token after the last `*` seen and the position of the name it resumes from
+    let mut resume: Option<(usize, usize)> = None;
+
+    while position < name.len() {
+        match tokens.get(token) {
+            Some(Token::Star) => {
+                token += 1;
+                resume = Some((token, position));
+            },
+            Some(current) if current.matches(name[position]) => {
+                token += 1;
+                position += 1;
+            },
+            _ => match resume {
+                Some((after_star, skipped)) => {
+                    token = after_star;
+                    position = skipped + 1;
+                    resume = Some((after_star, position));
+                },
+                None => return false
+            }
+        }
+    }
+
+    tokens[token..].iter().all(|token| matches!(token, Token::Star))
+}
+
+/// Checks whether a file name matches a pattern. Hidden names (starting with
+/// a dot) only match patterns that start with a dot too.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') && !pattern.starts_with("\\.") {
+        return false;
+    }
+
+    matches_text(pattern, name)
+}
+
+/// Checks whether a whole text matches a pattern, like the word of a `case`
+/// command. Unlike with file names, wildcards match slashes and leading dots.
+pub fn matches_text(pattern: &str, text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    matches_tokens(&parse(pattern), &text)
+}
+
+/// Gets the sorted names of a directory that match a pattern.
+fn matching_entries(directory: &Path, pattern: &str, only_directories: bool) -> Vec<String> {
+    let Ok(entries) = directory.read_dir() else {
+        return vec![];
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !only_directories || entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| matches(pattern, name))
+        .collect();
+
+    names.sort();
+    names
+}
+
+fn walk(base: &Path, prefix: &str, components: &[&str], results: &mut Vec<String>) {
+    let Some((component, rest)) = components.split_first() else {
+        results.push(prefix.to_owned());
+        return;
+    };
+
+    let directory = base.join(prefix);
+
+    // A trailing slash only matches directories, and repeated slashes are ignored
+    if component.is_empty() {
+        if rest.is_empty() {
+            if directory.is_dir() {
+                results.push(prefix.to_owned());
+            }
+        }
+        else {
+            walk(base, prefix, rest, results);
+        }
+        return;
+    }
+
+    // `**` matches any amount of directories, including none, and every
+    // file below them when it's the last component
+    if *component == "**" && rest.is_empty() {
+        walk(base, prefix, &["**", "*"], results);
+        return;
+    }
+
+    if *component == "**" {
+        walk(base, prefix, rest, results);
+        for name in matching_entries(&directory, "*", true) {
+            // Links to directories aren't followed, since they could lead back to one of their parents
+            if directory.join(&name).symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+                walk(base, &format!("{prefix}{name}/"), components, results);
+            }
+        }
+        return;
+    }
+
+    if !has_wildcards(component) {
+        let name = unescape(component);
+        let found = if rest.is_empty() { directory.join(&name).symlink_metadata().is_ok() } else { directory.join(&name).is_dir() };
+        if found {
+            walk_next(base, &format!("{prefix}{name}"), rest, results);
+        }
+        return;
+    }
+
+    for name in matching_entries(&directory, component, !rest.is_empty()) {
+        walk_next(base, &format!("{prefix}{name}"), rest, results);
+    }
+}
+
+fn walk_next(base: &Path, path: &str, rest: &[&str], results: &mut Vec<String>) {
+    if rest.is_empty() {
+        results.push(path.to_owned());
+    }
+    else {
+        walk(base, &format!("{path}/"), rest, results);
+    }
+}
+
+/// Finds the paths that match a pattern, relative to a base directory unless the
+/// pattern is absolute. The results are sorted and written like the pattern.
+pub fn expand(base: &Path, pattern: &str) -> Vec<String> {
+    let mut results = vec![];
+
+    match pattern.strip_prefix('/') {
+        Some(rest) => {
+            let components: Vec<&str> = rest.split('/').collect();
+            walk(base, "/", &components, &mut results);
+        },
+        None => {
+            let components: Vec<&str> = pattern.split('/').collect();
+            walk(base, "", &components, &mut results);
+        }
+    }
+
+    results.sort();
+    results.dedup();
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn matches_stars_and_question_marks() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("a*b*c", "abc"));
+        assert!(matches("a*b*c", "axxbyyc"));
+        assert!(!matches("a*b*c", "axxbyy"));
+        assert!(matches("?at", "cat"));
+        assert!(!matches("?at", "at"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(matches("[abc]x", "bx"));
+        assert!(!matches("[abc]x", "dx"));
+        assert!(matches("[a-c0-9]", "7"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(!matches("[^a-c]", "b"));
+        // A `]` right at the start is part of the class, and an unclosed `[` is literal
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[a", "[a"));
+    }
+
+    #[test]
+    fn escaped_wildcards_are_literal() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(!has_wildcards("a\\*b"));
+        assert!(has_wildcards("a[b]"));
+        assert_eq!(unescape(&escape("a*b?[c]\\")), "a*b?[c]\\");
+        assert!(matches(&escape("f[1].txt"), "f[1].txt"));
+    }
+
+    #[test]
+    fn hidden_names_need_a_dot() {
+        assert!(!matches("*", ".hidden"));
+        assert!(matches(".*", ".hidden"));
+        assert!(matches("\\.h*", ".hidden"));
+        // Unlike file names, `case` words can start with a dot
+        assert!(matches_text("*", ".hidden"));
+    }
+
+    #[test]
+    fn pathological_patterns_finish_quickly() {
+        let text = "a".repeat(100);
+        let pattern = format!("{}b", "*a".repeat(50));
+        assert!(!matches_text(&pattern, &text));
+        assert!(matches_text(&format!("{}*", "*a".repeat(50)), &text));
+        assert!(!matches_text(&"*?".repeat(101), &text));
+        assert!(matches_text(&"*?".repeat(100), &text));
+    }
+
+    /// A directory of its own under the temporary one, removed when dropped.
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("shell-glob-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn expands_paths() {
+        let dir = TempDir::new("expand");
+        fs::create_dir_all(dir.0.join("src/nested")).unwrap();
+        for file in ["a.txt", "b.txt", ".hidden.txt", "src/main.rs", "src/nested/deep.rs"] {
+            fs::write(dir.0.join(file), "").unwrap();
+        }
+
+        assert_eq!(expand(&dir.0, "*.txt"), ["a.txt", "b.txt"]);
+        assert_eq!(expand(&dir.0, "*/"), ["src/"]);
+        assert_eq!(expand(&dir.0, "src/*.rs"), ["src/main.rs"]);
+        assert_eq!(expand(&dir.0, "**/*.rs"), ["src/main.rs", "src/nested/deep.rs"]);
+        assert!(expand(&dir.0, "*.md").is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn double_stars_skip_linked_directories() {
+        let dir = TempDir::new("links");
+        fs::create_dir_all(dir.0.join("src")).unwrap();
+        fs::write(dir.0.join("src/main.rs"), "").unwrap();
+        // A link back to a parent would make `**` go around forever
+        std::os::unix::fs::symlink(&dir.0, dir.0.join("src/up")).unwrap();
+
+        assert_eq!(expand(&dir.0, "**/*.rs"), ["src/main.rs"]);
+        assert_eq!(expand(&dir.0, "src/up/src/*.rs"), ["src/up/src/main.rs"]);
+    }
+}
@@ -0,0 +1,360 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// How many entries are remembered.
+const MAX_ENTRIES: usize = 10_000;
+
+/// The commands typed in interactive sessions. Each entry is appended to a file
+/// as soon as it's added, so several sessions running at once can share it.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// The file where the entries are saved, if there's one.
+    file: Option<PathBuf>,
+}
+
+impl History {
+    /// Loads the entries saved in a file, which new ones are appended to. A file
+    /// that doesn't exist yet is created with the first entry.
+    pub fn load(file: PathBuf) -> io::Result<History> {
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error)
+        };
+
+        let saved = contents.lines().count();
+        let mut history = History { entries: vec![], file: Some(file) };
+        for line in contents.lines() {
+            history.push(decode(line));
+        }
+
+        // The file only grows while sessions append to it, so it's shortened from time to time
+        if saved > MAX_ENTRIES * 2 {
+            history.rewrite(|_| ())?;
+        }
+
+        Ok(history)
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// A copy of the entries that doesn't change the file, for commands whose
+    /// changes are undone.
+    pub fn detached(&self) -> History {
+        History { entries: self.entries.clone(), file: None }
+    }
+
+    /// Gets an entry by its number, starting at 1.
+    pub fn get(&self, number: usize) -> Option<&str> {
+        self.entries.get(number.checked_sub(1)?).map(String::as_str)
+    }
+
+    /// Adds a command, unless it's empty or starts with a space (which is a way
+    /// to keep it out of the history). Earlier entries with the same command are
+    /// removed, so each command is there only once.
+    pub fn add(&mut self, entry: &str) -> io::Result<()> {
+        if entry.trim().is_empty() || entry.starts_with(' ') {
+            return Ok(());
+        }
+
+        self.push(entry.to_owned());
+
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // A single write to a file opened for appending doesn't get mixed with
+        // the ones of other sessions, and the lock keeps them off while it's rewritten
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        lock(&file)?;
+        file.write_all(format!("{}\n", encode(entry)).as_bytes())
+    }
+
+    /// Removes an entry by its number, returning whether it existed.
+    pub fn remove(&mut self, number: usize) -> io::Result<bool> {
+        if number == 0 || number > self.entries.len() {
+            return Ok(false);
+        }
+
+        let entry = self.entries.remove(number - 1);
+        self.rewrite(|entries| entries.retain(|existing| *existing != entry))?;
+        Ok(true)
+    }
+
+    /// Removes all the entries, also from the file.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.rewrite(Vec::clear)
+    }
+
+    fn push(&mut self, entry: String) {
+        self.entries.retain(|existing| *existing != entry);
+        self.entries.push(entry);
+
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+    }
+
+    /// Changes the entries saved in the file. They're read again once the file
+    /// is locked, so the ones other sessions added since it was loaded stay.
+    fn rewrite(&self, change: impl FnOnce(&mut Vec<String>)) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // The file is truncated after locking it instead of when opening it, so
+        // nothing is lost from the sessions appending to it at the same time
+        let mut file = OpenOptions::new().create(true).read(true).write(true).truncate(false).open(path)?;
+        lock(&file)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut saved = History::default();
+        for line in contents.lines() {
+            saved.push(decode(line));
+        }
+        change(&mut saved.entries);
+
+        let contents: String = saved.entries.iter().map(|entry| format!("{}\n", encode(entry))).collect();
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        file.write_all(contents.as_bytes())
+    }
+
+    /// Replaces the references to earlier commands in a line: `!!` is the last
+    /// command, `!n` the one with number n, `!-n` the nth from the end and
+    /// `!prefix` the last one starting with the prefix. A line like `^old^new`
+    /// runs the last command replacing `old` with `new`. Returns `None` when the
+    /// line has no references.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, String> {
+        if let Some(rest) = line.strip_prefix('^') {
+            let mut parts = rest.trim_end_matches('\n').splitn(3, '^');
+            let old = parts.next().unwrap_or_default();
+            let new = parts.next().unwrap_or_default();
+            let tail = parts.next().unwrap_or_default();
+
+            return match self.entries.last() {
+                Some(last) if !old.is_empty() && last.contains(old) => Ok(Some(last.replacen(old, new, 1) + tail)),
+                _ => Err(format!("^{old}^{new}: Substitution failed."))
+            };
+        }
+
+        let chars: Vec<char> = line.chars().collect();
+        let mut result = String::new();
+        let mut expanded = false;
+        let (mut single, mut double) = (false, false);
+        let mut index = 0;
+
+        while index < chars.len() {
+            let c = chars[index];
+            index += 1;
+
+            match c {
+                '\\' if !single => {
+                    result.push(c);
+                    if let Some(&next) = chars.get(index) {
+                        result.push(next);
+                        index += 1;
+                    }
+                    continue;
+                },
+                '\'' if !double => single = !single,
+                '"' if !single => double = !double,
+                '!' if !single => {
+                    // Like in other shells, a `!` before a space, `=` or `(` is kept as it is
+                    let event: String = match chars.get(index) {
+                        Some('!') => String::from("!"),
+                        Some(c) if c.is_whitespace() || "=(\"".contains(*c) => String::new(),
+                        None => String::new(),
+                        Some(_) => chars[index..].iter()
+                            .take_while(|c| !c.is_whitespace() && !";&|()<>\"'".contains(**c))
+                            .collect()
+                    };
+
+                    if !event.is_empty() {
+                        let entry = self.event(&event).ok_or_else(|| format!("!{event}: Event not found."))?;
+                        result.push_str(entry);
+                        index += event.chars().count();
+                        expanded = true;
+                        continue;
+                    }
+                },
+                _ => ()
+            }
+
+            result.push(c);
+        }
+
+        Ok(expanded.then_some(result))
+    }
+
+    /// Finds the entry an event after `!` refers to.
+    fn event(&self, event: &str) -> Option<&str> {
+        if event == "!" {
+            return self.entries.last().map(String::as_str);
+        }
+
+        if let Some(back) = event.strip_prefix('-').and_then(|number| number.parse::<usize>().ok()) {
+            return self.get((self.entries.len() + 1).checked_sub(back)?);
+        }
+
+        if let Ok(number) = event.parse::<usize>() {
+            return self.get(number);
+        }
+
+        self.entries.iter().rev().find(|entry| entry.starts_with(event)).map(String::as_str)
+    }
+}
+
+/// Writes an entry in a single line of the file, escaping its line breaks.
+fn encode(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn decode(line: &str) -> String {
+    let mut entry = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            },
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            },
+            _ => entry.push(c)
+        }
+    }
+
+    entry
+}
+
+/// Locks a file for writing until it's closed, waiting for other sessions to release it.
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::default();
+        for entry in entries {
+            history.add(entry).unwrap();
+        }
+        history
+    }
+
+    fn expand(line: &str) -> Result<Option<String>, String> {
+        history(&["ls -l", "echo hello", "cat file", "echo bye"]).expand(line)
+    }
+
+    fn expanded(line: &str) -> String {
+        expand(line).unwrap().unwrap_or_else(|| panic!("{line:?} wasn't expanded"))
+    }
+
+    #[test]
+    fn repeats_the_last_command() {
+        assert_eq!(expanded("!!"), "echo bye");
+        assert_eq!(expanded("!! | wc -l"), "echo bye | wc -l");
+        assert_eq!(expanded("sudo !!"), "sudo echo bye");
+    }
+
+    #[test]
+    fn finds_commands_by_number() {
+        assert_eq!(expanded("!1"), "ls -l");
+        assert_eq!(expanded("!3 x"), "cat file x");
+        assert_eq!(expanded("!-1"), "echo bye");
+        assert_eq!(expanded("!-3"), "echo hello");
+        assert_eq!(expand("!5"), Err(String::from("!5: Event not found.")));
+        assert!(expand("!-5").is_err());
+    }
+
+    #[test]
+    fn finds_commands_by_prefix() {
+        assert_eq!(expanded("!ec"), "echo bye");
+        assert_eq!(expanded("!ca; !l"), "cat file; ls -l");
+        assert_eq!(expand("!nothing"), Err(String::from("!nothing: Event not found.")));
+    }
+
+    #[test]
+    fn replaces_in_the_last_command() {
+        assert_eq!(expanded("^bye^hi"), "echo hi");
+        assert_eq!(expanded("^bye^hi^ there"), "echo hi there");
+        assert_eq!(expanded("^e^a"), "acho bye");
+        assert!(expand("^missing^x").is_err());
+        assert!(expand("^^x").is_err());
+    }
+
+    #[test]
+    fn leaves_lines_without_references() {
+        for line in ["echo hi", "echo 'a!!b'", "echo \\!!", "[ a != b ]", "echo !", "echo ! x"] {
+            assert_eq!(expand(line), Ok(None), "{line:?}");
+        }
+
+        // Double quotes don't stop references
+        assert_eq!(expanded("echo \"!!\""), "echo \"echo bye\"");
+    }
+
+    #[test]
+    fn keeps_each_command_once() {
+        let history = history(&["a", "b", "a", " secret", ""]);
+        assert_eq!(history.entries(), ["b", "a"]);
+    }
+
+    #[test]
+    fn keeps_the_entries_of_other_sessions() {
+        let path = std::env::temp_dir().join(format!("shell-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut first = History::load(path.clone()).unwrap();
+        first.add("a").unwrap();
+        first.add("b").unwrap();
+        let mut second = History::load(path.clone()).unwrap();
+        second.add("c").unwrap();
+
+        // Removing an entry only removes it from the file, even if it changed since it was loaded
+        assert!(first.remove(1).unwrap());
+        assert_eq!(first.entries(), ["b"]);
+        assert_eq!(History::load(path.clone()).unwrap().entries(), ["b", "c"]);
+
+        second.clear().unwrap();
+        assert!(History::load(path.clone()).unwrap().entries().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn encodes_entries_in_single_lines() {
+        let entry = "for i in 1 2\ndo echo \\n $i\ndone";
+        assert!(!encode(entry).contains('\n'));
+        assert_eq!(decode(&encode(entry)), entry);
+    }
+}
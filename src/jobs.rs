@@ -0,0 +1,602 @@
+use std::process::ExitStatus;
+#[cfg(not(unix))]
+use std::process::Child;
+
+use crate::signals;
+use crate::streams::Streams;
+
+/// The exit status of a job stopped with Control-Z (128 plus SIGTSTP), like in other shells.
+const STOPPED_STATUS: i32 = 148;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Running,
+    Stopped,
+    Done(ExitStatus),
+}
+
+#[derive(Debug)]
+struct Process {
+    pid: i32,
+    state: State,
+}
+
+/// Commands running in the background or stopped, which are all the processes
+/// of a pipeline, or a copy of the shell running a list in the background.
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    /// The process group of the job, which is the id of its first process.
+    group: i32,
+    processes: Vec<Process>,
+    /// The commands of the job, as they're shown by `jobs`.
+    pub command: String,
+    /// The settings of the terminal when the job was stopped, to restore them when it's resumed.
+    #[cfg(unix)]
+    modes: Option<libc::termios>,
+}
+
+impl Job {
+    fn new(pids: Vec<i32>, command: String) -> Job {
+        Job {
+            id: 0,
+            group: pids.first().copied().unwrap_or_default(),
+            processes: pids.into_iter().map(|pid| Process { pid, state: State::Running }).collect(),
+            command,
+            #[cfg(unix)]
+            modes: None,
+        }
+    }
+
+    fn state(&self) -> State {
+        if self.processes.iter().any(|process| process.state == State::Stopped) {
+            return State::Stopped;
+        }
+
+        match self.processes.iter().all(|process| matches!(process.state, State::Done(_))) {
+            true => self.processes.last().map_or(State::Done(ExitStatus::default()), |process| process.state),
+            false => State::Running
+        }
+    }
+
+    /// The exit status of the job, which is the one of its last process.
+    fn status(&self) -> i32 {
+        match self.state() {
+            State::Done(status) => signals::exit_code(status),
+            State::Stopped => STOPPED_STATUS,
+            State::Running => 0
+        }
+    }
+
+    /// The commands of the job, with a `&` when it's running in the background.
+    fn display(&self) -> String {
+        match self.state() {
+            State::Running => format!("{} &", self.command),
+            _ => self.command.clone()
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.state() {
+            State::Running => String::from("Running"),
+            State::Stopped => String::from("Stopped"),
+            State::Done(status) => match (signals::exit_code(status), signals::killed_by(status)) {
+                (0, _) => String::from("Done"),
+                (_, Some(signal)) => signals::name(signal).map_or_else(|| format!("Signal {signal}"), str::to_owned),
+                (code, None) => format!("Exit {code}")
+            }
+        }
+    }
+}
+
+/// The jobs of the shell. Interactive shells also have job control: each job
+/// gets its own process group, and only the one in the foreground can use the
+/// terminal, so Control-C and Control-Z only reach it.
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// The ids of the jobs from the least to the most recently used. The last
+    /// one is the current job (`%+`), and the one before it the previous job (`%-`).
+    order: Vec<usize>,
+    control: bool,
+    /// The process group of the shell, which gets the terminal back after each job.
+    group: i32,
+    /// The settings of the terminal used by the shell.
+    #[cfg(unix)]
+    modes: Option<libc::termios>,
+    /// The process id of the last job started in the background, as `$!`.
+    pub last: Option<i32>,
+}
+
+impl Jobs {
+    pub fn is_control(&self) -> bool {
+        self.control
+    }
+
+    /// How many jobs there are, for the prompt.
+    pub fn count(&self) -> usize {
+        self.jobs.len()
+    }
+
+    fn add(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or_default() + 1;
+        }
+
+        let id = job.id;
+        self.jobs.push(job);
+        self.jobs.sort_by_key(|job| job.id);
+        self.touch(id);
+        id
+    }
+
+    /// Makes a job the current one.
+    fn touch(&mut self, id: usize) {
+        self.order.retain(|existing| *existing != id);
+        self.order.push(id);
+    }
+
+    fn remove(&mut self, id: usize) -> Option<Job> {
+        self.order.retain(|existing| *existing != id);
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// Forgets a job without stopping it, so it keeps running after the shell exits.
+    pub fn disown(&mut self, id: usize) {
+        self.remove(id);
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.jobs.iter().map(|job| job.id).collect()
+    }
+
+    /// The `+` of the current job, the `-` of the previous one, or a space.
+    fn marker(&self, id: usize) -> char {
+        match self.order.iter().rev().position(|existing| *existing == id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' '
+        }
+    }
+
+    /// Describes a job like `jobs` does, with its id, state and commands.
+    pub fn describe(&self, id: usize, pids: bool) -> Option<String> {
+        let job = self.jobs.iter().find(|job| job.id == id)?;
+        let marker = self.marker(id);
+
+        match pids {
+            true => {
+                let mut lines = vec![];
+                for (index, process) in job.processes.iter().enumerate() {
+                    let prefix = if index == 0 { format!("[{id}]{marker}") } else { String::new() };
+                    let state = if index == 0 { job.describe() } else { String::new() };
+                    let command = if index == 0 { job.display() } else { String::new() };
+                    lines.push(format!("{prefix:<5}{:>7} {state:<16}{command}", process.pid));
+                }
+                Some(lines.join("\n").trim_end().to_owned())
+            },
+            false => Some(format!("[{id}]{marker}  {:<24}{}", job.describe(), job.display()))
+        }
+    }
+
+    /// The process group of a job, for `jobs -p`.
+    pub fn group(&self, id: usize) -> Option<i32> {
+        self.jobs.iter().find(|job| job.id == id).map(|job| job.group)
+    }
+
+    /// Finds the job a job specification refers to: `%n` (or just `n`) is the
+    /// job with id n, `%+` or `%%` the current one, `%-` the previous one,
+    /// `%text` the one whose commands start with the text, and `%?text` the one
+    /// whose commands contain it.
+    pub fn find(&self, spec: &str) -> Result<usize, String> {
+        let text = spec.strip_prefix('%').unwrap_or(spec);
+        let found = match text {
+            "" | "+" | "%" => self.order.last().copied(),
+            "-" => self.order.iter().rev().nth(1).or(self.order.last()).copied(),
+            _ if text.chars().all(|c| c.is_ascii_digit()) => {
+                let id = text.parse().ok();
+                self.jobs.iter().find(|job| Some(job.id) == id).map(|job| job.id)
+            },
+            _ => {
+                let matching: Vec<usize> = match text.strip_prefix('?') {
+                    Some(part) => self.jobs.iter().filter(|job| job.command.contains(part)).map(|job| job.id).collect(),
+                    None => self.jobs.iter().filter(|job| job.command.starts_with(text)).map(|job| job.id).collect()
+                };
+
+                if matching.len() > 1 {
+                    return Err(format!("{spec}: More than one job matches."));
+                }
+                matching.first().copied()
+            }
+        };
+
+        found.ok_or_else(|| format!("{spec}: No such job."))
+    }
+
+    /// Finds the job a process belongs to, for `wait`.
+    pub fn find_process(&self, pid: i32) -> Option<usize> {
+        self.jobs.iter().find(|job| job.processes.iter().any(|process| process.pid == pid)).map(|job| job.id)
+    }
+
+    /// Checks which jobs changed without waiting, returning the messages about
+    /// them. Finished jobs are forgotten after that.
+    pub fn update(&mut self) -> Vec<String> {
+        let mut messages = vec![];
+
+        for index in 0..self.jobs.len() {
+            let before = self.jobs[index].state();
+            for process in &mut self.jobs[index].processes {
+                poll(process);
+            }
+
+            let job = &self.jobs[index];
+            if job.state() != before && job.state() != State::Running {
+                messages.push((job.id, job.state() == State::Stopped));
+            }
+        }
+
+        let mut lines = vec![];
+        for (id, stopped) in messages {
+            if stopped {
+                self.touch(id);
+            }
+
+            lines.extend(self.describe(id, false));
+            if !stopped {
+                self.remove(id);
+            }
+        }
+
+        lines
+    }
+
+    /// Writes the jobs that finished since the last time, and forgets them. The
+    /// shell does it before showing the prompt.
+    pub fn notify(&mut self, io: &mut Streams) {
+        for line in self.update() {
+            errln!(io, "{line}");
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Jobs {
+    /// Turns on job control: the shell puts itself in its own process group and
+    /// takes the terminal, waiting until it's in the foreground if it was started
+    /// in the background.
+    pub fn enable_control(&mut self) {
+        unsafe {
+            let terminal = libc::STDIN_FILENO;
+            if libc::isatty(terminal) == 0 {
+                return;
+            }
+
+            loop {
+                let foreground = libc::tcgetpgrp(terminal);
+                let group = libc::getpgrp();
+                if foreground == -1 || foreground == group {
+                    break;
+                }
+                libc::kill(-group, libc::SIGTTIN);
+            }
+
+            signals::ignore_job_signals();
+
+            let pid = libc::getpid();
+            // It fails when the shell leads a session, which already makes it lead a group
+            libc::setpgid(0, 0);
+            if libc::tcsetpgrp(terminal, pid) == -1 && libc::getpgrp() != pid {
+                return;
+            }
+
+            let mut modes: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(terminal, &mut modes) == 0 {
+                self.modes = Some(modes);
+            }
+
+            self.group = libc::getpgrp();
+            self.control = true;
+        }
+    }
+
+    /// Waits for the processes of a pipeline started in the foreground, returning
+    /// their exit statuses. If they're stopped, they become a job.
+    pub fn run_foreground(&mut self, pids: Vec<i32>, command: String, io: &mut Streams) -> Vec<i32> {
+        let job = Job::new(pids, command);
+        self.wait_foreground(job, io)
+    }
+
+    fn wait_foreground(&mut self, mut job: Job, io: &mut Streams) -> Vec<i32> {
+        if self.control {
+            self.give_terminal(&job);
+        }
+
+        for index in 0..job.processes.len() {
+            let process = &mut job.processes[index];
+            if process.state == State::Running {
+                wait(process, self.control);
+            }
+
+            if process.state == State::Stopped {
+                // Control-Z stops all the processes of the group, which is checked after that
+                for process in &mut job.processes {
+                    poll(process);
+                }
+                break;
+            }
+        }
+
+        if self.control {
+            self.take_terminal(&mut job);
+
+            // Control-C stops the rest of the commands too, like the loop the program was in
+            let interrupted = job.processes.iter()
+                .any(|process| matches!(process.state, State::Done(status) if signals::killed_by(status) == Some(libc::SIGINT)));
+            if interrupted {
+                signals::interrupt();
+            }
+        }
+
+        if job.state() == State::Stopped {
+            let id = self.add(job);
+            // The line where Control-Z was pressed is left as it is
+            errln!(io, "");
+            errln!(io, "{}", self.describe(id, false).unwrap_or_default());
+            let job = self.jobs.iter().find(|job| job.id == id).expect("the job was just added");
+            return job.processes.iter().map(|_| STOPPED_STATUS).collect();
+        }
+
+        job.processes.iter()
+            .map(|process| match process.state {
+                State::Done(status) => {
+                    if let Some(message) = signals::termination_message(status) {
+                        errln!(io, "{message}");
+                    }
+                    signals::exit_code(status)
+                },
+                _ => STOPPED_STATUS
+            })
+            .collect()
+    }
+
+    /// Gives the terminal to a job, along with the settings it had when it was stopped.
+    fn give_terminal(&self, job: &Job) {
+        unsafe {
+            libc::tcsetpgrp(libc::STDIN_FILENO, job.group);
+            if let Some(modes) = &job.modes {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes);
+            }
+        }
+    }
+
+    /// Gets the terminal back from a job. The settings it leaves are kept if it
+    /// finished by itself, but not if it was stopped or killed.
+    fn take_terminal(&mut self, job: &mut Job) {
+        unsafe {
+            libc::tcsetpgrp(libc::STDIN_FILENO, self.group);
+
+            let mut modes: libc::termios = std::mem::zeroed();
+            let current = (libc::tcgetattr(libc::STDIN_FILENO, &mut modes) == 0).then_some(modes);
+            match job.state() {
+                State::Done(status) if signals::killed_by(status).is_none() => self.modes = current.or(self.modes),
+                state => {
+                    if state == State::Stopped {
+                        job.modes = current;
+                    }
+                    if let Some(modes) = &self.modes {
+                        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds a job started in the background, returning its id.
+    pub fn add_background(&mut self, pid: i32, command: String) -> usize {
+        self.last = Some(pid);
+        self.add(Job::new(vec![pid], command))
+    }
+
+    /// Continues a stopped job, in the foreground (`fg`) or in the background (`bg`).
+    /// Returns the exit status of the job when it's run in the foreground.
+    pub fn resume(&mut self, id: usize, foreground: bool, io: &mut Streams) -> i32 {
+        let Some(index) = self.jobs.iter().position(|job| job.id == id) else {
+            return 1;
+        };
+
+        let job = &mut self.jobs[index];
+        for process in &mut job.processes {
+            if process.state == State::Stopped {
+                process.state = State::Running;
+            }
+        }
+
+        if !foreground {
+            let line = format!("[{id}]{} {} &", self.marker(id), self.jobs[index].command);
+            outln!(io, "{line}");
+            self.touch(id);
+            self.signal(id, libc::SIGCONT);
+            return 0;
+        }
+
+        let job = self.remove(id).expect("the job exists");
+        outln!(io, "{}", job.command);
+        // The job gets the terminal before it continues, so it doesn't stop again trying to use it
+        if self.control {
+            self.give_terminal(&job);
+        }
+        kill(&job, self.control, libc::SIGCONT);
+
+        let statuses = self.wait_foreground(job, io);
+        statuses.last().copied().unwrap_or_default()
+    }
+
+    /// Sends a signal to all the processes of a job.
+    fn signal(&self, id: usize, signal: i32) {
+        if let Some(job) = self.jobs.iter().find(|job| job.id == id) {
+            kill(job, self.control, signal);
+        }
+    }
+
+    /// Waits until a job finishes (or is stopped), returning its exit status. It's
+    /// forgotten if it finished. Control-C or a signal with a trap stops waiting,
+    /// which gives 128 plus the signal number.
+    pub fn wait(&mut self, id: usize) -> i32 {
+        let Some(index) = self.jobs.iter().position(|job| job.id == id) else {
+            return 127;
+        };
+
+        let flags = if self.control { libc::WUNTRACED } else { 0 };
+        let job = &mut self.jobs[index];
+        for process in &mut job.processes {
+            if process.state == State::Running {
+                wait_process(process, flags, true);
+            }
+
+            if let Some(signal) = signals::received() {
+                return 128 + signal;
+            }
+        }
+
+        let status = job.status();
+        if job.state() != State::Stopped {
+            self.remove(id);
+        }
+
+        status
+    }
+}
+
+#[cfg(not(unix))]
+impl Jobs {
+    /// Job control needs process groups, which other systems don't have.
+    pub fn enable_control(&mut self) {}
+
+    pub fn run_foreground(&mut self, children: Vec<Child>, _command: String, io: &mut Streams) -> Vec<i32> {
+        children.into_iter()
+            .map(|mut child| match child.wait() {
+                Ok(status) => signals::exit_code(status),
+                Err(error) => {
+                    errln!(io, "Error waiting for the command: {error}");
+                    1
+                }
+            })
+            .collect()
+    }
+
+    pub fn resume(&mut self, _id: usize, _foreground: bool, _io: &mut Streams) -> i32 {
+        1
+    }
+
+    pub fn wait(&mut self, _id: usize) -> i32 {
+        127
+    }
+}
+
+/// Sends a signal to the processes of a job, through its process group when it has one.
+#[cfg(unix)]
+fn kill(job: &Job, control: bool, signal: i32) {
+    unsafe {
+        match control {
+            true => {
+                libc::kill(-job.group, signal);
+            },
+            false => for process in &job.processes {
+                libc::kill(process.pid, signal);
+            }
+        }
+    }
+}
+
+/// Waits until a process finishes, or until it's stopped with job control.
+#[cfg(unix)]
+fn wait(process: &mut Process, control: bool) {
+    let flags = if control { libc::WUNTRACED } else { 0 };
+    wait_process(process, flags, false);
+}
+
+/// Checks whether a process finished, was stopped or was continued, without waiting.
+#[cfg(unix)]
+fn poll(process: &mut Process) {
+    if matches!(process.state, State::Done(_)) {
+        return;
+    }
+
+    wait_process(process, libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED, false);
+}
+
+#[cfg(not(unix))]
+fn poll(_process: &mut Process) {}
+
+/// Updates the state of a process with `waitpid`. When `interruptible`, Control-C
+/// or a signal with a trap stops the wait, leaving the process as it was.
+#[cfg(unix)]
+fn wait_process(process: &mut Process, flags: i32, interruptible: bool) {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(process.pid, &mut status, flags) } {
+            0 => return,
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {
+                if interruptible && signals::received().is_some() {
+                    return;
+                }
+            },
+            // Processes that aren't children of the shell anymore can't be followed
+            -1 => {
+                process.state = State::Done(ExitStatus::default());
+                return;
+            },
+            _ => break
+        }
+    }
+
+    process.state = if libc::WIFSTOPPED(status) { State::Stopped }
+    else if libc::WIFCONTINUED(status) { State::Running }
+    else { State::Done(std::os::unix::process::ExitStatusExt::from_raw(status)) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Jobs made of processes that don't exist, which are only looked at.
+    fn jobs(commands: &[&str]) -> Jobs {
+        let mut jobs = Jobs::default();
+        for (index, command) in commands.iter().enumerate() {
+            jobs.add(Job::new(vec![1000 + index as i32], command.to_string()));
+        }
+        jobs
+    }
+
+    #[test]
+    fn finds_jobs_by_spec() {
+        let jobs = jobs(&["sleep 10", "vim notes", "sleep 20"]);
+        assert_eq!(jobs.find("%1"), Ok(1));
+        assert_eq!(jobs.find("2"), Ok(2));
+        assert_eq!(jobs.find("%%"), Ok(3));
+        assert_eq!(jobs.find("%+"), Ok(3));
+        assert_eq!(jobs.find("%-"), Ok(2));
+        assert_eq!(jobs.find("%vim"), Ok(2));
+        assert_eq!(jobs.find("%?notes"), Ok(2));
+        assert_eq!(jobs.find("%sleep"), Err(String::from("%sleep: More than one job matches.")));
+        assert_eq!(jobs.find("%4"), Err(String::from("%4: No such job.")));
+    }
+
+    #[test]
+    fn using_a_job_makes_it_current() {
+        let mut jobs = jobs(&["a", "b", "c"]);
+        jobs.touch(1);
+        assert_eq!(jobs.find("%+"), Ok(1));
+        assert_eq!(jobs.find("%-"), Ok(3));
+        assert_eq!(jobs.describe(1, false).as_deref(), Some("[1]+  Running                 a &"));
+        assert_eq!(jobs.describe(2, false).as_deref(), Some("[2]   Running                 b &"));
+
+        // Ids are reused once the highest ones are gone
+        jobs.disown(3);
+        assert_eq!(jobs.find("%-"), Ok(2));
+        assert_eq!(jobs.add(Job::new(vec![2000], String::from("d"))), 3);
+        assert_eq!(jobs.find_process(2000), Some(3));
+        assert_eq!(jobs.group(3), Some(2000));
+    }
+}
@@ -0,0 +1,775 @@
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Unquoted text, still subject to expansions.
+    Literal(String),
+    /// Text that came from quotes or backslash escapes and must be kept as is.
+    Quoted(String),
+    /// A parameter like `$HOME` or `${HOME}`, along with whether it was inside double quotes.
+    Parameter(Parameter, bool),
+    /// The source of a `$(command)` or `` `command` `` substitution, along with
+    /// whether it was inside double quotes.
+    Command(String, bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Modifier {
+    /// `-`: uses a default value.
+    Default,
+    /// `=`: assigns a default value to the variable.
+    Assign,
+    /// `?`: fails with an error message.
+    Error,
+    /// `+`: uses an alternative value.
+    Alternative,
+}
+
+impl Modifier {
+    fn symbol(self) -> char {
+        match self {
+            Modifier::Default => '-',
+            Modifier::Assign => '=',
+            Modifier::Error => '?',
+            Modifier::Alternative => '+',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    /// The modifier of `${name:-word}`, whether it had a colon (which makes it
+    /// apply to empty values and not only to missing ones) and its word.
+    pub modifier: Option<(Modifier, bool, Word)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    /// Column (starting at 1) where the word begins, counted from the start of
+    /// the source, which can have several lines.
+    pub column: usize,
+}
+
+impl Word {
+    fn new(column: usize) -> Word {
+        Word { parts: vec![], column }
+    }
+
+    /// Returns the word with all its parts joined and the quotes removed, without
+    /// expanding anything.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(value) | WordPart::Quoted(value) => text.push_str(value),
+                WordPart::Parameter(parameter, _) => match &parameter.modifier {
+                    None => text.push_str(&format!("${{{}}}", parameter.name)),
+                    Some((modifier, colon, word)) => {
+                        let colon = if *colon { ":" } else { "" };
+                        text.push_str(&format!("${{{}{colon}{}{}}}", parameter.name, modifier.symbol(), word.text()));
+                    }
+                },
+                WordPart::Command(source, _) => text.push_str(&format!("$({source})"))
+            }
+        }
+
+        text
+    }
+
+    fn push_literal(&mut self, c: char) {
+        if let Some(WordPart::Literal(value)) = self.parts.last_mut() {
+            value.push(c);
+            return;
+        }
+
+        self.parts.push(WordPart::Literal(c.to_string()));
+    }
+
+    fn push_quoted(&mut self, text: String) {
+        if let Some(WordPart::Quoted(value)) = self.parts.last_mut() {
+            value.push_str(&text);
+            return;
+        }
+
+        self.parts.push(WordPart::Quoted(text));
+    }
+}
+
+/// Writes the word back as source that reads into the same word: quoted text goes
+/// between single quotes, and parameters and substitutions that were inside double
+/// quotes go back in them.
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_parts(f, &self.parts, false)
+    }
+}
+
+/// Writes the parts of a word, which are inside double quotes when `quoted` is set,
+/// like the word of `"${name:-word}"`. There, quoted text is written with backslashes.
+fn write_parts(f: &mut fmt::Formatter<'_>, parts: &[WordPart], quoted: bool) -> fmt::Result {
+    // Double quotes leave empty quoted parts around parameters, which are only
+    // needed to keep a word that could expand to nothing
+    let kept = parts.iter().any(|part| match part {
+        WordPart::Quoted(value) => !value.is_empty(),
+        WordPart::Parameter(_, in_quotes) | WordPart::Command(_, in_quotes) => *in_quotes,
+        WordPart::Literal(_) => false
+    });
+
+    for part in parts {
+        match part {
+            WordPart::Literal(value) => f.write_str(value)?,
+            WordPart::Quoted(value) if value.is_empty() && kept => (),
+            WordPart::Quoted(value) if quoted => {
+                for c in value.chars() {
+                    if matches!(c, '\\' | '"' | '$' | '`' | '}') {
+                        f.write_char('\\')?;
+                    }
+                    f.write_char(c)?;
+                }
+            },
+            WordPart::Quoted(value) => write!(f, "'{}'", value.replace('\'', "'\\''"))?,
+            WordPart::Parameter(parameter, in_quotes) => {
+                let quote = if *in_quotes && !quoted { "\"" } else { "" };
+                write!(f, "{quote}${{{}", parameter.name)?;
+                if let Some((modifier, colon, word)) = &parameter.modifier {
+                    write!(f, "{}{}", if *colon { ":" } else { "" }, modifier.symbol())?;
+                    write_parts(f, &word.parts, *in_quotes)?;
+                }
+                write!(f, "}}{quote}")?;
+            },
+            WordPart::Command(source, in_quotes) => {
+                let quote = if *in_quotes && !quoted { "\"" } else { "" };
+                write!(f, "{quote}$({source}){quote}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redirect {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `>&`
+    Duplicate,
+    /// `&>`
+    OutputBoth,
+    /// `&>>`
+    AppendBoth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    /// `|`
+    Pipe,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `&`, which runs the commands before it in the background.
+    Background,
+    /// `;`
+    Semicolon,
+    /// `;;`, which ends an item of a `case` command.
+    DoubleSemicolon,
+    /// The end of a line, which separates commands like `;`.
+    Newline,
+    /// `(`
+    OpenParen,
+    /// `)`
+    CloseParen,
+    /// A redirection, along with the file descriptor number written before it.
+    Redirect(Option<u32>, Redirect),
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Operator::Pipe => "|",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::Background => "&",
+            Operator::Semicolon => ";",
+            Operator::DoubleSemicolon => ";;",
+            Operator::Newline => "newline",
+            Operator::OpenParen => "(",
+            Operator::CloseParen => ")",
+            Operator::Redirect(descriptor, redirect) => {
+                if let Some(descriptor) = descriptor {
+                    write!(f, "{descriptor}")?;
+                }
+
+                match redirect {
+                    Redirect::Input => "<",
+                    Redirect::Output => ">",
+                    Redirect::Append => ">>",
+                    Redirect::Duplicate => ">&",
+                    Redirect::OutputBoth => "&>",
+                    Redirect::AppendBoth => "&>>",
+                }
+            }
+        };
+
+        write!(f, "{text}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(Word),
+    /// An operator along with its column.
+    Operator(Operator, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    /// Line (starting at 1) where the error was found.
+    pub line: usize,
+    /// Column (starting at 1) where the error was found.
+    pub column: usize,
+    /// Whether the source just ended too soon, like with open quotes or blocks,
+    /// so the error would go away with more lines.
+    pub incomplete: bool,
+}
+
+impl SyntaxError {
+    pub fn new(message: &str, column: usize) -> SyntaxError {
+        SyntaxError { message: message.to_owned(), line: 1, column, incomplete: false }
+    }
+
+    /// An error caused by the source ending before something was closed.
+    pub fn unfinished(message: &str, column: usize) -> SyntaxError {
+        SyntaxError { incomplete: true, ..SyntaxError::new(message, column) }
+    }
+
+    /// Turns the column, counted from the start of the source, into a line and
+    /// a column in that line.
+    pub fn locate(self, source: &str) -> SyntaxError {
+        let before: Vec<char> = source.chars().take(self.column.saturating_sub(1)).collect();
+        let line = 1 + before.iter().filter(|c| **c == '\n').count();
+        let column = self.column - before.iter().rposition(|c| *c == '\n').map_or(0, |index| index + 1);
+
+        SyntaxError { line, column, ..self }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            1 => write!(f, "{} (column {})", self.message, self.column),
+            line => write!(f, "{} (line {line}, column {})", self.message, self.column)
+        }
+    }
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.position += 1;
+        }
+
+        c
+    }
+
+    /// Column of the character that was just consumed.
+    fn column(&self) -> usize {
+        self.position
+    }
+
+    fn single_quoted(&mut self) -> Result<String, SyntaxError> {
+        let start = self.column();
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(text),
+                Some(c) => text.push(c),
+                None => return Err(SyntaxError::unfinished("Single quotes not closed", start))
+            }
+        }
+    }
+
+    /// Reads what follows a `$` sign: a command substitution or a parameter, if any.
+    fn dollar(&mut self, quoted: bool) -> Result<Option<WordPart>, SyntaxError> {
+        let start = self.column();
+        if self.next_if('(') {
+            let source = self.command_source(start)?;
+            return Ok(Some(WordPart::Command(source, quoted)));
+        }
+
+        Ok(self.parameter(quoted)?.map(|parameter| WordPart::Parameter(parameter, quoted)))
+    }
+
+    /// Reads the source of a `$(command)` substitution after its opening parenthesis,
+    /// skipping over the quotes and substitutions nested in it.
+    fn command_source(&mut self, start: usize) -> Result<String, SyntaxError> {
+        let begin = self.position;
+        let mut depth = 0;
+
+        loop {
+            match self.next() {
+                Some(')') if depth == 0 => break,
+                Some(')') => depth -= 1,
+                Some('(') => depth += 1,
+                Some('\\') => {
+                    self.next();
+                },
+                Some('\'') => {
+                    self.single_quoted()?;
+                },
+                Some('"') => self.double_quoted(&mut Word::new(0))?,
+                Some('`') => {
+                    self.backquoted(false)?;
+                },
+                Some(_) => (),
+                None => return Err(SyntaxError::unfinished("Parentheses not closed", start))
+            }
+        }
+
+        Ok(self.chars[begin..self.position - 1].iter().collect())
+    }
+
+    /// Reads the source of a `` `command` `` substitution after its opening backquote.
+    /// Backslashes only escape backquotes, dollar signs and themselves (and double
+    /// quotes when the substitution is inside them), so nested ones must be escaped.
+    fn backquoted(&mut self, quoted: bool) -> Result<String, SyntaxError> {
+        let start = self.column();
+        let mut source = String::new();
+
+        loop {
+            match self.next() {
+                Some('`') => return Ok(source),
+                Some('\\') => match self.next() {
+                    Some(c @ ('`' | '$' | '\\')) => source.push(c),
+                    Some('"') if quoted => source.push('"'),
+                    Some(c) => {
+                        source.push('\\');
+                        source.push(c);
+                    },
+                    None => return Err(SyntaxError::unfinished("Backquotes not closed", start))
+                },
+                Some(c) => source.push(c),
+                None => return Err(SyntaxError::unfinished("Backquotes not closed", start))
+            }
+        }
+    }
+
+    /// Reads a parameter after its `$` sign, if there's one.
+    fn parameter(&mut self, quoted: bool) -> Result<Option<Parameter>, SyntaxError> {
+        let start = self.column();
+
+        if self.next_if('{') {
+            // Positional parameters past the ninth one can only be written inside braces
+            let name = match self.peek() {
+                Some('0'..='9') => {
+                    let mut digits = String::new();
+                    while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+                        self.next();
+                        digits.push(c);
+                    }
+                    Some(digits)
+                },
+                _ => self.parameter_name()
+            };
+
+            let name = name.ok_or(SyntaxError::new("Bad substitution", start))?;
+            if self.next_if('}') {
+                return Ok(Some(Parameter { name, modifier: None }));
+            }
+
+            let colon = self.next_if(':');
+            let modifier = match self.next() {
+                Some('-') => Modifier::Default,
+                Some('=') => Modifier::Assign,
+                Some('?') => Modifier::Error,
+                Some('+') => Modifier::Alternative,
+                _ => return Err(SyntaxError::new("Bad substitution", start))
+            };
+
+            let word = self.operand(quoted, start)?;
+            return Ok(Some(Parameter { name, modifier: Some((modifier, colon, word)) }));
+        }
+
+        Ok(self.parameter_name().map(|name| Parameter { name, modifier: None }))
+    }
+
+    /// Reads a variable name, or one of the special parameters like `?` or `1`.
+    fn parameter_name(&mut self) -> Option<String> {
+        match self.peek()? {
+            c @ ('?' | '$' | '#' | '@' | '*' | '!' | '0'..='9') => {
+                self.next();
+                Some(c.to_string())
+            },
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = self.peek().filter(|c| *c == '_' || c.is_ascii_alphanumeric()) {
+                    self.next();
+                    name.push(c);
+                }
+
+                Some(name)
+            },
+            _ => None
+        }
+    }
+
+    /// Reads the word of a `${name:-word}` parameter until its closing brace.
+    fn operand(&mut self, quoted: bool, start: usize) -> Result<Word, SyntaxError> {
+        let mut word = Word::new(self.position + 1);
+
+        loop {
+            match self.next() {
+                Some('}') => return Ok(word),
+                Some('\\') => match self.next() {
+                    Some(c) => word.push_quoted(c.to_string()),
+                    None => return Err(SyntaxError::unfinished("Braces not closed", start))
+                },
+                Some('\'') if !quoted => {
+                    let text = self.single_quoted()?;
+                    word.push_quoted(text);
+                },
+                Some('"') => self.double_quoted(&mut word)?,
+                Some('$') => match self.dollar(quoted)? {
+                    Some(part) => word.parts.push(part),
+                    None if quoted => word.push_quoted(String::from("$")),
+                    None => word.push_literal('$')
+                },
+                Some('`') => {
+                    let source = self.backquoted(quoted)?;
+                    word.parts.push(WordPart::Command(source, quoted));
+                },
+                Some(c) if quoted => word.push_quoted(c.to_string()),
+                Some(c) => word.push_literal(c),
+                None => return Err(SyntaxError::unfinished("Braces not closed", start))
+            }
+        }
+    }
+
+    fn double_quoted(&mut self, word: &mut Word) -> Result<(), SyntaxError> {
+        let start = self.column();
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => {
+                    word.push_quoted(text);
+                    return Ok(());
+                },
+                Some('$') => match self.dollar(true)? {
+                    Some(part) => {
+                        word.push_quoted(std::mem::take(&mut text));
+                        word.parts.push(part);
+                    },
+                    None => text.push('$')
+                },
+                Some('`') => {
+                    word.push_quoted(std::mem::take(&mut text));
+                    let source = self.backquoted(true)?;
+                    word.parts.push(WordPart::Command(source, true));
+                },
+                Some('\\') => match self.peek() {
+                    Some(c @ ('"' | '\\' | '$' | '`')) => {
+                        self.next();
+                        text.push(c);
+                    },
+                    Some('\n') => {
+                        self.next();
+                    },
+                    _ => text.push('\\')
+                },
+                Some(c) => text.push(c),
+                None => return Err(SyntaxError::unfinished("Double quotes not closed", start))
+            }
+        }
+    }
+
+    fn word(&mut self) -> Result<Word, SyntaxError> {
+        let mut word = Word::new(self.position + 1);
+
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || self.at_operator() {
+                break;
+            }
+
+            self.next();
+            match c {
+                '\'' => {
+                    let text = self.single_quoted()?;
+                    word.push_quoted(text);
+                },
+                '"' => self.double_quoted(&mut word)?,
+                '$' => match self.dollar(false)? {
+                    Some(part) => word.parts.push(part),
+                    None => word.push_literal('$')
+                },
+                '`' => {
+                    let source = self.backquoted(false)?;
+                    word.parts.push(WordPart::Command(source, false));
+                },
+                '\\' => match self.next() {
+                    // Line continuation, which needs the next line if there isn't one yet
+                    Some('\n') if self.peek().is_none() => return Err(SyntaxError::unfinished("Backslash at the end of the line", self.column())),
+                    Some('\n') => (),
+                    Some(c) => word.push_quoted(c.to_string()),
+                    None => return Err(SyntaxError::unfinished("Backslash at the end of the line", self.column()))
+                },
+                _ => word.push_literal(c)
+            }
+        }
+
+        Ok(word)
+    }
+
+    fn at_operator(&self) -> bool {
+        matches!(self.peek(), Some('|' | ';' | '&' | '<' | '>' | '(' | ')' | '\n'))
+    }
+
+    /// Reads the digits of a file descriptor number if a redirection follows them.
+    fn descriptor(&mut self) -> Option<u32> {
+        let digits = self.chars[self.position..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 || !matches!(self.peek_at(digits), Some('<' | '>')) {
+            return None;
+        }
+
+        let number: String = self.chars[self.position..self.position + digits].iter().collect();
+        self.position += digits;
+        number.parse().ok()
+    }
+
+    fn operator(&mut self, descriptor: Option<u32>) -> Operator {
+        let redirect = match self.next() {
+            Some('|') => return if self.next_if('|') { Operator::Or } else { Operator::Pipe },
+            Some(';') => return if self.next_if(';') { Operator::DoubleSemicolon } else { Operator::Semicolon },
+            Some('\n') => return Operator::Newline,
+            Some('(') => return Operator::OpenParen,
+            Some(')') => return Operator::CloseParen,
+            Some('<') => Redirect::Input,
+            Some('>') => {
+                if self.next_if('>') { Redirect::Append }
+                else if self.next_if('&') { Redirect::Duplicate }
+                else { Redirect::Output }
+            },
+            Some('&') => {
+                if self.next_if('&') {
+                    return Operator::And;
+                }
+
+                if self.peek() != Some('>') {
+                    return Operator::Background;
+                }
+
+                self.next();
+                if self.next_if('>') { Redirect::AppendBoth } else { Redirect::OutputBoth }
+            },
+            c => unreachable!("{c:?} doesn't start an operator")
+        };
+
+        Operator::Redirect(descriptor, redirect)
+    }
+}
+
+/// Splits the source of some commands into words and operators. Quotes and backslashes are removed, but
+/// the parts they protected are remembered so later expansions can leave them untouched.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, SyntaxError> {
+    let mut lexer = Lexer { chars: line.chars().collect(), position: 0 };
+    let mut tokens = vec![];
+
+    while let Some(c) = lexer.peek() {
+        if c.is_whitespace() && c != '\n' {
+            lexer.next();
+            continue;
+        }
+
+        // Comments run until the end of the line
+        if c == '#' {
+            while lexer.peek().is_some_and(|c| c != '\n') {
+                lexer.next();
+            }
+            continue;
+        }
+
+        let column = lexer.position + 1;
+        let descriptor = lexer.descriptor();
+        if descriptor.is_some() || lexer.at_operator() {
+            tokens.push(Token::Operator(lexer.operator(descriptor), column));
+            continue;
+        }
+
+        tokens.push(Token::Word(lexer.word()?));
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parts of each word of a line, leaving out the operators.
+    fn words(line: &str) -> Vec<Vec<WordPart>> {
+        tokenize(line).unwrap().into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word.parts),
+                Token::Operator(..) => None
+            })
+            .collect()
+    }
+
+    fn literal(text: &str) -> WordPart {
+        WordPart::Literal(text.to_owned())
+    }
+
+    fn quoted(text: &str) -> WordPart {
+        WordPart::Quoted(text.to_owned())
+    }
+
+    fn parameter(name: &str, in_quotes: bool) -> WordPart {
+        WordPart::Parameter(Parameter { name: name.to_owned(), modifier: None }, in_quotes)
+    }
+
+    #[test]
+    fn splits_words_on_blanks() {
+        assert_eq!(words("echo  hello\tworld"), [vec![literal("echo")], vec![literal("hello")], vec![literal("world")]]);
+        assert_eq!(words("  a   b  "), [vec![literal("a")], vec![literal("b")]]);
+    }
+
+    #[test]
+    fn single_quotes_keep_everything() {
+        assert_eq!(words("'a $b \\c \"d\"'"), [vec![quoted("a $b \\c \"d\"")]]);
+    }
+
+    #[test]
+    fn double_quotes_allow_some_escapes() {
+        // Only `"`, `\\`, `$` and `` ` `` are escaped, other backslashes stay
+        assert_eq!(words("\"a\\\"b\\\\c\\$d\\e\""), [vec![quoted("a\"b\\c$d\\e")]]);
+    }
+
+    #[test]
+    fn backslashes_quote_the_next_character() {
+        assert_eq!(words("a\\ b"), [vec![literal("a"), quoted(" "), literal("b")]]);
+        // A backslash before a line break joins the lines
+        assert_eq!(words("ec\\\nho"), [vec![literal("echo")]]);
+    }
+
+    #[test]
+    fn quoted_parts_join_the_same_word() {
+        assert_eq!(words("a'b'\"c\"d"), [vec![literal("a"), quoted("bc"), literal("d")]]);
+        // Empty quotes still make a word
+        assert_eq!(words("''"), [vec![quoted("")]]);
+    }
+
+    #[test]
+    fn comments_start_only_at_words() {
+        assert_eq!(words("echo a # b"), [vec![literal("echo")], vec![literal("a")]]);
+        assert_eq!(words("a#b '#c'"), [vec![literal("a#b")], vec![quoted("#c")]]);
+    }
+
+    #[test]
+    fn unclosed_quotes_are_errors() {
+        for line in ["'open", "\"open", "a\\"] {
+            assert!(tokenize(line).is_err(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn pipes_end_words() {
+        let tokens = tokenize("a|b | c").unwrap();
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[1], Token::Operator(Operator::Pipe, 2));
+        assert_eq!(tokens[3], Token::Operator(Operator::Pipe, 5));
+        assert_eq!(words("'a|b'"), [vec![quoted("a|b")]]);
+    }
+
+    #[test]
+    fn reads_redirections_with_descriptors() {
+        let tokens = tokenize("2>&1 >>f &>g <h").unwrap();
+        assert_eq!(tokens[0], Token::Operator(Operator::Redirect(Some(2), Redirect::Duplicate), 1));
+        assert_eq!(tokens[2], Token::Operator(Operator::Redirect(None, Redirect::Append), 6));
+        assert_eq!(tokens[4], Token::Operator(Operator::Redirect(None, Redirect::OutputBoth), 10));
+        assert_eq!(tokens[6], Token::Operator(Operator::Redirect(None, Redirect::Input), 14));
+        // Digits only name a descriptor right before the operator
+        assert_eq!(words("a2 >b"), [vec![literal("a2")], vec![literal("b")]]);
+    }
+
+    #[test]
+    fn recognizes_operators() {
+        let operators: Vec<Operator> = tokenize("a|b&&c||d;e&;;(f)\n").unwrap().into_iter()
+            .filter_map(|token| match token {
+                Token::Operator(operator, _) => Some(operator),
+                Token::Word(_) => None
+            })
+            .collect();
+
+        assert_eq!(operators, [
+            Operator::Pipe, Operator::And, Operator::Or, Operator::Semicolon, Operator::Background,
+            Operator::DoubleSemicolon, Operator::OpenParen, Operator::CloseParen, Operator::Newline,
+        ]);
+    }
+
+    #[test]
+    fn reads_parameters() {
+        assert_eq!(words("a$b\"x $HOME y\""), [vec![literal("a"), parameter("b", false), quoted("x "), parameter("HOME", true), quoted(" y")]]);
+        assert_eq!(words("${A}B $? $1"), [vec![parameter("A", false), literal("B")], vec![parameter("?", false)], vec![parameter("1", false)]]);
+        // An escaped or lone `$` is just text
+        assert_eq!(words("\\$HOME $"), [vec![quoted("$"), literal("HOME")], vec![literal("$")]]);
+    }
+
+    #[test]
+    fn reads_command_substitutions() {
+        assert_eq!(words("\"$(echo 'x)')\""), [vec![quoted(""), WordPart::Command("echo 'x)'".to_owned(), true), quoted("")]]);
+        assert_eq!(words("a`b c`"), [vec![literal("a"), WordPart::Command("b c".to_owned(), false)]]);
+        assert!(tokenize("$(echo").is_err());
+    }
+
+    #[test]
+    fn words_are_written_back_as_source() {
+        let write = |line: &str| -> String {
+            let words: Vec<String> = tokenize(line).unwrap().into_iter()
+                .filter_map(|token| match token {
+                    Token::Word(word) => Some(word.to_string()),
+                    Token::Operator(..) => None
+                })
+                .collect();
+            words.join(" ")
+        };
+
+        let line = r#"a'b c'\ d "$x-$y" `echo '$'` "$(echo ")")" \' "" *"*""#;
+        assert_eq!(words(&write(line)), words(line));
+
+        // The words of modifiers start at other columns once written back
+        let line = r#""${y:-"1 }"}" ${z:-'2 3'"$w"}"#;
+        assert_eq!(write(line), r#""${y:-1 \}}" ${z:-'2 3'"${w}"}"#);
+        assert_eq!(write(&write(line)), write(line));
+    }
+}
@@ -1,127 +1,321 @@
-use std::io::{self, Write};
-use std::env;
+use std::io::{self, IsTerminal};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::{env, process};
 use enable_ansi_support as ansi;
 
+#[macro_use]
+mod streams;
+mod aliases;
 mod commands;
+mod completion;
+#[cfg(unix)]
+mod copy;
+mod editor;
+mod expand;
+mod git;
+mod glob;
+mod history;
+mod jobs;
+mod lexer;
+mod parser;
+mod prompt;
+mod shell;
+mod signals;
+mod terminal;
+mod traps;
+mod users;
+mod variables;
+
+use editor::{Editor, Entry};
+use history::History;
+use shell::Shell;
+use streams::Streams;
 
 fn main() {
-    let version_number = "v0.1.0";
-    let mut stdout = io::stdout();
-    let mut path = env::current_dir().expect("Working directory couldn't be determined.");
-
-    let mut ftitle = "\x1B[1;31m";
-    let mut fversion = "\x1B[33m";
-    let mut fauthor = "\x1B[36m";
-    let mut freset = "\x1B[0m";
-
-    match ansi::enable_ansi_support() {
-        Err(_) => {
-            ftitle = "";
-            fversion = "";
-            fauthor = "";
-            freset = "";
-        },
-        Ok(_) => ()
+    let path = env::current_dir().expect("Working directory couldn't be determined.");
+    let ansi = ansi::enable_ansi_support().is_ok();
+
+    let mut shell = Shell::new(path, ansi);
+
+    let arguments = match Arguments::parse(env::args()) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{error}");
+            process::exit(2);
+        }
+    };
+
+    #[cfg(unix)]
+    if let Some(descriptor) = arguments.copy {
+        shell.run_copy(descriptor);
     }
 
-    println!("{ftitle}Rust Shell{freset} {fversion}{version_number}{freset}");
-    println!("Created by {fauthor}@FacuA0{freset}\n");
+    // Commands piped or redirected into the shell are run without the banner and the prompts
+    let interactive = arguments.command.is_none() && arguments.rest.is_empty() && io::stdin().is_terminal();
+    shell.interactive = interactive;
 
-    loop {
-        let prompt = String::from(path.to_str().unwrap()) + "> ";
-        
-        print!("{prompt}");
-        stdout.flush().unwrap();
-    
-        let mut value = String::new();
-        io::stdin().read_line(&mut value).unwrap();
-    
-        let raw_parts: Vec<&str> = value.trim().split(" ").collect();
-        let mut parts: Vec<String> = vec![];
+    // Login shells run the profile first, whatever they run after it
+    if arguments.login {
+        if let Some(profile) = shell.config_dir().map(|dir| dir.join("profile")) {
+            run_startup(&mut shell, &profile, false);
+        }
+    }
+
+    // `shell -c command [name args...]` runs a command, and `shell script args...`
+    // runs a script, which is also how scripts with a shebang pointing to this
+    // shell are run
+    if let Some(command) = &arguments.command {
+        run_command(&mut shell, command, &arguments.rest);
+    }
 
-        let mut is_string = false;
-        let mut sum_string = String::new();
+    if let Some((script, args)) = arguments.rest.split_first() {
+        run_script(&mut shell, script, args);
+    }
 
-        for part in raw_parts {
-            if part.chars().next() == Some('"') {
-                is_string = true;
-            }
-            
-            if is_string {
-                sum_string.push(' ');
-                sum_string.push_str(part);
+    let mut editor = Editor::new();
+    if interactive {
+        signals::handle_interactive();
+        shell.jobs.enable_control();
+        commands::show_version(ansi, &mut Streams::standard());
+
+        if let Some(file) = shell.data_dir().map(|dir| dir.join("history")) {
+            match History::load(file) {
+                Ok(history) => shell.history = history,
+                Err(error) => eprintln!("Error loading the history: {error}")
             }
-            else {
-                parts.push(part.to_string());
+        }
+
+        if let Some(dir) = shell.config_dir().map(|dir| dir.join("completions")) {
+            load_completions(&mut shell, &dir);
+        }
+
+        match arguments.rc {
+            Startup::Default => {
+                if let Some(rc) = shell.config_dir().map(|dir| dir.join("rc")) {
+                    run_startup(&mut shell, &rc, false);
+                }
+            },
+            Startup::File(rc) => run_startup(&mut shell, Path::new(&rc), true),
+            Startup::None => ()
+        }
+    }
+
+    // Control-D presses in a row, which are ignored up to a limit with `ignoreeof`
+    let mut eofs = 0;
+    while !shell.exiting {
+        if interactive {
+            // What Control-C stopped ends there, and the next command runs normally
+            if signals::take_interrupt() {
+                shell.discard_input();
             }
-            
-            if part.chars().last() == Some('"') {
-                if is_string {
-                    parts.push(sum_string[1..].to_string());
+            shell.run_traps(&mut Streams::standard());
+
+            // The jobs that finished in the background are reported before the prompt
+            shell.jobs.notify(&mut Streams::standard());
+
+            // Lines that continue an unfinished command get the prompt in `PS2`
+            let continuation = shell.is_pending();
+            let prompt = prompt::render(&mut shell, continuation);
+
+            let entry = match editor.read_line(&prompt, &mut shell) {
+                Ok(entry) => entry,
+                Err(error) => {
+                    eprintln!("Error reading the input: {error}");
+                    shell.status = 1;
+                    break;
                 }
+            };
 
-                is_string = false;
-                sum_string.clear();
+            if !matches!(entry, Entry::End) {
+                eofs = 0;
             }
-        }
 
-        if is_string {
-            println!("Invalid syntax: Double quotes not closed.");
+            match entry {
+                Entry::Line(line) => {
+                    let start = Instant::now();
+                    shell.run_input(&line, &mut Streams::standard());
+                    if !shell.is_pending() {
+                        shell.duration = Some(start.elapsed());
+                    }
+
+                },
+                Entry::Interrupted => {
+                    shell.discard_input();
+                    // The INT trap runs for Control-C at the prompt too
+                    signals::interrupt();
+                },
+                Entry::End if eofs < ignored_eofs(&shell) => {
+                    eofs += 1;
+                    eprintln!("Use 'exit' to leave the shell.");
+                },
+                Entry::End => {
+                    shell.finish_input(&mut Streams::standard());
+                    break;
+                }
+            }
             continue;
         }
 
-        let parts = {
-            let mut str_parts: Vec<&str> = vec![];
-            for str in parts.iter() {
-                str_parts.push(str.as_str());
+        let mut value = String::new();
+        match io::stdin().read_line(&mut value) {
+            Ok(0) => {
+                shell.finish_input(&mut Streams::standard());
+                break;
+            },
+            Ok(_) => (),
+            Err(error) => {
+                eprintln!("Error reading the input: {error}");
+                shell.status = 1;
+                break;
             }
+        }
 
-            str_parts
-        };
+        shell.run_line(&value, &mut Streams::standard());
+    }
 
-        let command = parts[0];
-        let mut args = parts.clone();
-        args.remove(0);
+    if interactive {
+        println!("Exit");
+    }
 
-        match command {
-            "cd" => {
-                commands::change_directory(&mut path, args);
-            },
-            "ls" => {
-                commands::list_elements(&mut path);
-            },
-            "md" => {
-                commands::make_directory(&mut path, args);
-            },
-            "touch" => {
-                commands::create_file(&mut path, args);
-            },
-            "rm" => {
-                commands::remove_element(&mut path, args);
-            },
-            "mv" => {
-                commands::move_files(&mut path, args);
-            },
-            "cp" => {
-                commands::copy_files(&mut path, args);
-            },
-            "help" => {
-                commands::help_command(args);
+    shell.run_exit_trap(&mut Streams::standard());
+    process::exit(shell.status);
+}
+
+/// How many times in a row Control-D is ignored before the shell exits: the
+/// number in `IGNOREEOF`, or 10 with the `ignoreeof` option or when it isn't a number.
+fn ignored_eofs(shell: &Shell) -> usize {
+    match shell.variables.get("IGNOREEOF") {
+        Some(count) => count.parse().unwrap_or(10),
+        None if shell.options.ignoreeof => 10,
+        None => 0
+    }
+}
+
+/// The options the shell was started with.
+struct Arguments {
+    /// The commands given with `-c`.
+    command: Option<String>,
+    /// The file run at the start of an interactive session.
+    rc: Startup,
+    /// Whether it's a login shell, started as `-shell` or with `--login`.
+    login: bool,
+    /// The script and its arguments, or the name and arguments for `-c`.
+    rest: Vec<String>,
+    /// The pipe where a copy of the shell, started to run some commands at the
+    /// same time as it, reads them.
+    #[cfg(unix)]
+    copy: Option<i32>,
+}
+
+enum Startup {
+    Default,
+    File(String),
+    None,
+}
+
+impl Arguments {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+        let name = args.next().unwrap_or_default();
+        let mut arguments = Arguments {
+            command: None,
+            rc: Startup::Default,
+            login: name.starts_with('-'),
+            rest: vec![],
+            #[cfg(unix)]
+            copy: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" => {
+                    let command = args.next().ok_or("-c: There's no command parameter.")?;
+                    arguments.command = Some(command);
+                    break;
+                },
+                "-l" | "--login" => arguments.login = true,
+                "--norc" => arguments.rc = Startup::None,
+                "--rcfile" => {
+                    let file = args.next().ok_or("--rcfile: There's no file parameter.")?;
+                    arguments.rc = Startup::File(file);
+                },
+                #[cfg(unix)]
+                "--copy" => {
+                    let descriptor = args.next().and_then(|descriptor| descriptor.parse().ok());
+                    arguments.copy = Some(descriptor.ok_or("--copy: There's no descriptor parameter.")?);
+                },
+                "--" => break,
+                _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("{arg}: Unknown option.")),
+                _ => {
+                    arguments.rest.push(arg);
+                    break;
+                }
             }
-            "version" => {
-                println!("{ftitle}Rust Shell{freset} {fversion}{version_number}{freset}");
-                println!("Created by {fauthor}@FacuA0{freset}\n");
-            },
-            "exit" => break,
-            "" => (),
-            _ => {
-                if commands::execute_command(&mut path, command, args.clone()).is_ok() { continue }
-                if commands::execute_local_file(&mut path, command, args.clone()).is_ok() { continue }
-                
-                println!("Command '{command}' not found. Type 'help' to show available commands.")
-            },
         }
+
+        arguments.rest.extend(args);
+        Ok(arguments)
+    }
+}
+
+/// Runs a startup file, like the rc file or the profile. Missing files are only
+/// reported when they were asked for explicitly.
+fn run_startup(shell: &mut Shell, file: &Path, required: bool) {
+    if !required && !file.is_file() {
+        return;
+    }
+
+    let mut io = Streams::standard();
+    if let Err(error) = shell.run_file(file, &mut io) {
+        errln!(io, "Error reading {}: {error}", file.display());
     }
-    
-    println!("Exit");
-}
\ No newline at end of file
+}
+
+/// Loads the completion specs of the files in a directory, in the order of their names.
+fn load_completions(shell: &mut Shell, dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut files: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).filter(|path| path.is_file()).collect();
+    files.sort();
+
+    for file in files {
+        let result = fs::read_to_string(&file);
+        match result.as_deref().map(|contents| shell.completions.parse(contents)) {
+            Ok(Ok(())) => (),
+            Ok(Err((line, message))) => eprintln!("{}: line {line}: {message}", file.display()),
+            Err(error) => eprintln!("Error reading {}: {error}", file.display())
+        }
+    }
+}
+
+/// Runs the commands given with `-c`, and exits with the status of the last one.
+/// The arguments after them are `$0` and the positional parameters.
+fn run_command(shell: &mut Shell, command: &str, args: &[String]) -> ! {
+    if let Some((name, args)) = args.split_first() {
+        shell.name = name.clone();
+        shell.positional = args.to_vec();
+    }
+
+    let mut io = Streams::standard();
+    shell.run_source(command, &mut io);
+    shell.run_exit_trap(&mut io);
+    process::exit(shell.status);
+}
+
+/// Runs a script with its arguments as positional parameters, and exits with its status.
+fn run_script(shell: &mut Shell, script: &str, args: &[String]) -> ! {
+    shell.name = script.to_owned();
+    shell.positional = args.to_vec();
+
+    let mut io = Streams::standard();
+    if let Err(error) = shell.run_file(Path::new(script), &mut io) {
+        errln!(io, "{script}: {error}");
+        process::exit(if error.kind() == io::ErrorKind::NotFound { 127 } else { 126 });
+    }
+
+    shell.run_exit_trap(&mut io);
+    process::exit(shell.status);
+}
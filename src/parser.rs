@@ -0,0 +1,781 @@
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use crate::lexer::{Operator, Redirect, SyntaxError, Token, Word, WordPart};
+use crate::variables;
+
+/// A `NAME=value` word written before a command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirection {
+    /// The file descriptor number written before the operator, if any.
+    pub descriptor: Option<u32>,
+    pub redirect: Redirect,
+    pub target: Word,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirections: Vec<Redirection>,
+}
+
+/// A command made of other commands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compound {
+    /// `{ list; }`, which runs its commands in the current shell.
+    Group(List),
+    /// `if list; then list; elif list; then list; else list; fi`, with each
+    /// condition along with its body, and the body of `else`.
+    If(Vec<(List, List)>, Option<List>),
+    /// `while list; do list; done`, with its condition and its body.
+    While(List, List),
+    /// `until list; do list; done`, with its condition and its body.
+    Until(List, List),
+    /// `for name in words; do list; done`. Without `in`, it goes through the
+    /// positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    /// `case word in pattern | pattern) list;; esac`.
+    Case {
+        word: Word,
+        items: Vec<(Vec<Word>, List)>,
+    },
+}
+
+/// A `name() { ... }` definition. The body is shared with the functions table,
+/// so a function can be redefined while it runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub body: Compound,
+    /// Redirections applied every time the function is called.
+    pub redirections: Vec<Redirection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(Compound, Vec<Redirection>),
+    Function(Rc<Function>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    /// `&&`: runs the next pipeline only if the previous one succeeded.
+    And,
+    /// `||`: runs the next pipeline only if the previous one failed.
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Set when it ends with `&`, to run it in the background.
+    pub background: bool,
+}
+
+/// Commands separated by `;` or `&`, run one after the other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub items: Vec<AndOr>,
+}
+
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", Operator::Redirect(self.descriptor, self.redirect), self.target)
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let assignments = self.assignments.iter().map(|assignment| format!("{}={}", assignment.name, assignment.value));
+        let words = self.words.iter().map(Word::to_string);
+        let redirections = self.redirections.iter().map(Redirection::to_string);
+        write!(f, "{}", assignments.chain(words).chain(redirections).collect::<Vec<_>>().join(" "))
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compound::Group(list) => write!(f, "{{ {} }}", Terminated(list)),
+            Compound::If(branches, otherwise) => {
+                for (index, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if index == 0 { "if" } else { "elif" };
+                    write!(f, "{keyword} {} then {} ", Terminated(condition), Terminated(body))?;
+                }
+
+                if let Some(otherwise) = otherwise {
+                    write!(f, "else {} ", Terminated(otherwise))?;
+                }
+
+                write!(f, "fi")
+            },
+            Compound::While(condition, body) => write!(f, "while {} do {} done", Terminated(condition), Terminated(body)),
+            Compound::Until(condition, body) => write!(f, "until {} do {} done", Terminated(condition), Terminated(body)),
+            Compound::For { name, words, body } => {
+                write!(f, "for {name}")?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {word}")?;
+                    }
+                }
+
+                write!(f, "; do {} done", Terminated(body))
+            },
+            Compound::Case { word, items } => {
+                write!(f, "case {word} in")?;
+                for (patterns, body) in items {
+                    let patterns: Vec<String> = patterns.iter().map(Word::to_string).collect();
+                    write!(f, " {}) {body};;", patterns.join(" | "))?;
+                }
+
+                write!(f, " esac")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redirections = match self {
+            Command::Simple(command) => return write!(f, "{command}"),
+            Command::Compound(compound, redirections) => {
+                write!(f, "{compound}")?;
+                redirections
+            },
+            Command::Function(function) => {
+                write!(f, "{}() {}", function.name, function.body)?;
+                &function.redirections
+            }
+        };
+
+        for redirection in redirections {
+            write!(f, " {redirection}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(Command::to_string).collect();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            let connector = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||"
+            };
+            write!(f, " {connector} {pipeline}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes commands back in a single line, which is how jobs are shown and how
+/// copies of the shell get them.
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, item) in self.items.iter().enumerate() {
+            if index > 0 {
+                write!(f, "{}", if self.items[index - 1].background { " " } else { "; " })?;
+            }
+
+            write!(f, "{item}")?;
+            if item.background {
+                write!(f, " &")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A list written with a `;` at its end unless it ends with `&`, like inside blocks.
+struct Terminated<'a>(&'a List);
+
+impl fmt::Display for Terminated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.items.last() {
+            Some(last) if !last.background => write!(f, "{};", self.0),
+            _ => write!(f, "{}", self.0)
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    /// Gets the next token if it's an unquoted word, which can be a reserved word
+    /// like `{` when it's found where a command starts.
+    fn peek_reserved(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => match word.parts.as_slice() {
+                [WordPart::Literal(text)] => Some(text),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    fn next_reserved(&mut self, reserved: &str) -> bool {
+        if self.peek_reserved() == Some(reserved) {
+            self.position += 1;
+            return true;
+        }
+
+        false
+    }
+
+    /// Checks whether the list being read ends at the next token.
+    fn at_list_end(&self) -> bool {
+        match self.peek() {
+            None | Some(Token::Operator(Operator::DoubleSemicolon, _)) => true,
+            Some(_) => matches!(self.peek_reserved(), Some(word) if CLOSING_WORDS.contains(&word))
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.next_operator(Operator::Newline) {}
+    }
+
+    /// Reads a word that must be there, like the name of a `for` loop.
+    fn next_word(&mut self) -> Result<Word, SyntaxError> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                let word = word.clone();
+                self.position += 1;
+                Ok(word)
+            },
+            _ => Err(self.unexpected())
+        }
+    }
+
+    fn next_operator(&mut self, operator: Operator) -> bool {
+        match self.peek() {
+            Some(Token::Operator(found, _)) if *found == operator => {
+                self.position += 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn simple_command(&mut self) -> Result<SimpleCommand, SyntaxError> {
+        let mut assignments = vec![];
+        let mut words = vec![];
+        let mut redirections = vec![];
+
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    match assignment(word) {
+                        Some(assignment) if words.is_empty() => assignments.push(assignment),
+                        _ => words.push(word.clone())
+                    }
+
+                    self.position += 1;
+                },
+                Some(Token::Operator(Operator::Redirect(..), _)) => redirections.push(self.redirection()?),
+                _ => break
+            }
+        }
+
+        Ok(SimpleCommand { assignments, words, redirections })
+    }
+
+    /// Reads a redirection operator along with its file name.
+    fn redirection(&mut self) -> Result<Redirection, SyntaxError> {
+        let Some(Token::Operator(operator @ Operator::Redirect(descriptor, redirect), column)) = self.next() else {
+            unreachable!("there's no redirection to read");
+        };
+
+        let Some(Token::Word(target)) = self.next() else {
+            return Err(SyntaxError::new(&format!("Missing file name after '{operator}'"), column));
+        };
+
+        Ok(Redirection { descriptor, redirect, target })
+    }
+
+    /// Reads the redirections written after a compound command.
+    fn redirections(&mut self) -> Result<Vec<Redirection>, SyntaxError> {
+        let mut redirections = vec![];
+        while let Some(Token::Operator(Operator::Redirect(..), _)) = self.peek() {
+            redirections.push(self.redirection()?);
+        }
+
+        Ok(redirections)
+    }
+
+    /// Reads a list that can't be empty, like the body of a block.
+    fn body(&mut self) -> Result<List, SyntaxError> {
+        let list = self.list()?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+
+        Ok(list)
+    }
+
+    /// Reads a list that must end with a reserved word, like the body of a `{ list; }` group.
+    fn list_until(&mut self, end: &str) -> Result<List, SyntaxError> {
+        let list = self.body()?;
+        if !self.next_reserved(end) {
+            return Err(self.unexpected());
+        }
+
+        Ok(list)
+    }
+
+    /// Reads an `if` command after its first word.
+    fn if_command(&mut self) -> Result<Compound, SyntaxError> {
+        let mut branches = vec![];
+
+        loop {
+            let condition = self.list_until("then")?;
+            branches.push((condition, self.body()?));
+
+            if self.next_reserved("elif") {
+                continue;
+            }
+
+            if self.next_reserved("else") {
+                return Ok(Compound::If(branches, Some(self.list_until("fi")?)));
+            }
+
+            if self.next_reserved("fi") {
+                return Ok(Compound::If(branches, None));
+            }
+
+            return Err(self.unexpected());
+        }
+    }
+
+    /// Reads a `for` loop after its first word.
+    fn for_command(&mut self) -> Result<Compound, SyntaxError> {
+        let word = self.next_word()?;
+        let name = match word.parts.as_slice() {
+            [WordPart::Literal(name)] if variables::is_valid_name(name) => name.clone(),
+            _ => return Err(SyntaxError::new(&format!("Invalid variable name '{}'", word.text()), word.column))
+        };
+
+        self.skip_newlines();
+        let mut words = None;
+        if self.next_reserved("in") {
+            let mut list = vec![];
+            while let Some(Token::Word(word)) = self.peek() {
+                list.push(word.clone());
+                self.position += 1;
+            }
+
+            if !self.next_operator(Operator::Semicolon) && !self.next_operator(Operator::Newline) {
+                return Err(self.unexpected());
+            }
+
+            words = Some(list);
+        }
+        else {
+            self.next_operator(Operator::Semicolon);
+        }
+
+        self.skip_newlines();
+        if !self.next_reserved("do") {
+            return Err(self.unexpected());
+        }
+
+        let body = self.list_until("done")?;
+        Ok(Compound::For { name, words, body })
+    }
+
+    /// Reads a `case` command after its first word.
+    fn case_command(&mut self) -> Result<Compound, SyntaxError> {
+        let word = self.next_word()?;
+        self.skip_newlines();
+        if !self.next_reserved("in") {
+            return Err(self.unexpected());
+        }
+
+        let mut items = vec![];
+        loop {
+            self.skip_newlines();
+            if self.next_reserved("esac") {
+                return Ok(Compound::Case { word, items });
+            }
+
+            self.next_operator(Operator::OpenParen);
+            let mut patterns = vec![self.next_word()?];
+            while self.next_operator(Operator::Pipe) {
+                patterns.push(self.next_word()?);
+            }
+
+            if !self.next_operator(Operator::CloseParen) {
+                return Err(self.unexpected());
+            }
+
+            // The body of an item can be empty, and the last one doesn't need `;;`
+            let body = self.list()?;
+            items.push((patterns, body));
+
+            if !self.next_operator(Operator::DoubleSemicolon) {
+                self.skip_newlines();
+                if !self.next_reserved("esac") {
+                    return Err(self.unexpected());
+                }
+
+                return Ok(Compound::Case { word, items });
+            }
+        }
+    }
+
+    /// Reads a compound command, if one starts at the next token.
+    fn compound(&mut self) -> Result<Option<Compound>, SyntaxError> {
+        let Some(keyword) = self.peek_reserved().filter(|word| ["{", "if", "while", "until", "for", "case"].contains(word)) else {
+            return Ok(None);
+        };
+
+        let keyword = keyword.to_owned();
+        self.position += 1;
+
+        let compound = match keyword.as_str() {
+            "{" => Compound::Group(self.list_until("}")?),
+            "if" => self.if_command()?,
+            "while" => Compound::While(self.list_until("do")?, self.list_until("done")?),
+            "until" => Compound::Until(self.list_until("do")?, self.list_until("done")?),
+            "for" => self.for_command()?,
+            _ => self.case_command()?
+        };
+
+        Ok(Some(compound))
+    }
+
+    /// Reads a function definition, written as `name() body` or `function name[()] body`.
+    fn function(&mut self) -> Result<Option<Function>, SyntaxError> {
+        let keyword = self.next_reserved("function");
+
+        let name = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Word(word)), next) if keyword || matches!(next, Some(Token::Operator(Operator::OpenParen, _))) => {
+                match word.parts.as_slice() {
+                    [WordPart::Literal(name)] if !RESERVED_WORDS.contains(&name.as_str()) => name.clone(),
+                    _ => return Err(SyntaxError::new(&format!("Invalid function name '{}'", word.text()), word.column))
+                }
+            },
+            _ if keyword => return Err(self.unexpected()),
+            _ => return Ok(None)
+        };
+
+        self.position += 1;
+        if self.next_operator(Operator::OpenParen) && !self.next_operator(Operator::CloseParen) {
+            return Err(self.unexpected());
+        }
+
+        let Some(body) = self.compound()? else {
+            return Err(self.unexpected());
+        };
+
+        let redirections = self.redirections()?;
+        Ok(Some(Function { name, body, redirections }))
+    }
+
+    fn command(&mut self) -> Result<Command, SyntaxError> {
+        if let Some(compound) = self.compound()? {
+            let redirections = self.redirections()?;
+            return Ok(Command::Compound(compound, redirections));
+        }
+
+        if let Some(function) = self.function()? {
+            return Ok(Command::Function(Rc::new(function)));
+        }
+
+        let command = self.simple_command()?;
+        if command.assignments.is_empty() && command.words.is_empty() && command.redirections.is_empty() {
+            return Err(self.unexpected());
+        }
+
+        Ok(Command::Simple(command))
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, SyntaxError> {
+        let mut commands = vec![];
+
+        loop {
+            commands.push(self.command()?);
+
+            if !self.next_operator(Operator::Pipe) {
+                return Ok(Pipeline { commands });
+            }
+
+            self.skip_newlines();
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, SyntaxError> {
+        let first = self.pipeline()?;
+        let mut rest = vec![];
+
+        loop {
+            let connector = if self.next_operator(Operator::And) { Connector::And }
+            else if self.next_operator(Operator::Or) { Connector::Or }
+            else { return Ok(AndOr { first, rest, background: false }) };
+
+            self.skip_newlines();
+            rest.push((connector, self.pipeline()?));
+        }
+    }
+
+    fn list(&mut self) -> Result<List, SyntaxError> {
+        let mut items = vec![];
+
+        loop {
+            self.skip_newlines();
+            if self.at_list_end() {
+                break;
+            }
+
+            // `&` also separates commands, like `;`
+            let mut and_or = self.and_or()?;
+            and_or.background = self.next_operator(Operator::Background);
+            let background = and_or.background;
+            items.push(and_or);
+
+            if !background && !self.next_operator(Operator::Semicolon) && !self.next_operator(Operator::Newline) {
+                break;
+            }
+        }
+
+        Ok(List { items })
+    }
+
+    fn unexpected(&mut self) -> SyntaxError {
+        match self.next() {
+            Some(Token::Operator(operator, column)) => SyntaxError::new(&format!("Unexpected '{operator}'"), column),
+            Some(Token::Word(word)) => SyntaxError::new(&format!("Unexpected word '{}'", word.text()), word.column),
+            None => {
+                let column = match self.tokens.last() {
+                    Some(Token::Operator(_, column)) => column + 1,
+                    Some(Token::Word(word)) => word.column + 1,
+                    None => 1
+                };
+
+                SyntaxError::unfinished("Unexpected end of line", column)
+            }
+        }
+    }
+}
+
+/// Words with a special meaning when they're found where a command starts.
+pub const RESERVED_WORDS: &[&str] = &[
+    "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "function", "if", "in", "then", "until", "while",
+];
+
+/// Reserved words after which a new command starts.
+pub const OPENING_WORDS: &[&str] = &["{", "do", "elif", "else", "if", "then", "until", "while"];
+
+/// Reserved words that end a list of commands.
+const CLOSING_WORDS: &[&str] = &["}", "do", "done", "elif", "else", "esac", "fi", "then"];
+
+/// Recognizes a `NAME=value` word. The name and the equals sign must not be quoted.
+pub fn assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart::Literal(first)) = word.parts.first() else {
+        return None;
+    };
+
+    let (name, rest) = first.split_once('=')?;
+    if !variables::is_valid_name(name) {
+        return None;
+    }
+
+    let mut value = word.clone();
+    match rest.is_empty() {
+        true => value.parts.remove(0),
+        false => mem::replace(&mut value.parts[0], WordPart::Literal(rest.to_owned()))
+    };
+
+    Some(Assignment { name: name.to_owned(), value })
+}
+
+/// Builds the list of commands out of the tokens of some lines.
+pub fn parse(tokens: Vec<Token>) -> Result<List, SyntaxError> {
+    let mut parser = Parser { tokens, position: 0 };
+    let list = parser.list()?;
+
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn parse_line(line: &str) -> Result<List, SyntaxError> {
+        parse(tokenize(line)?)
+    }
+
+    /// Writes a simple command as its words, and the others as what they are.
+    fn text(command: &Command) -> String {
+        match command {
+            Command::Simple(simple) => simple.words.iter().map(Word::text).collect::<Vec<_>>().join(" "),
+            Command::Compound(..) => String::from("compound"),
+            Command::Function(function) => format!("{}()", function.name)
+        }
+    }
+
+    /// Writes the structure of a list: each item between braces, followed by `&`
+    /// when it runs in the background, and pipelines of several commands in parentheses.
+    fn shape(line: &str) -> String {
+        let list = parse_line(line).unwrap();
+        let pipeline = |pipeline: &Pipeline| {
+            let commands: Vec<String> = pipeline.commands.iter().map(text).collect();
+
+            match commands.len() {
+                1 => commands[0].clone(),
+                _ => format!("({})", commands.join(" | "))
+            }
+        };
+
+        let items: Vec<String> = list.items.iter()
+            .map(|item| {
+                let mut text = pipeline(&item.first);
+                for (connector, next) in &item.rest {
+                    let connector = match connector {
+                        Connector::And => "&&",
+                        Connector::Or => "||"
+                    };
+                    text += &format!(" {connector} {}", pipeline(next));
+                }
+
+                match item.background {
+                    true => format!("{{{text}}} &"),
+                    false => format!("{{{text}}}")
+                }
+            })
+            .collect();
+
+        items.join("; ")
+    }
+
+    #[test]
+    fn pipes_separate_commands() {
+        assert_eq!(shape("a 1 | b | c 2 3"), "{(a 1 | b | c 2 3)}");
+        assert_eq!(shape("a"), "{a}");
+    }
+
+    #[test]
+    fn pipes_bind_tighter_than_and_or() {
+        assert_eq!(shape("a | b && c || d | e"), "{(a | b) && c || (d | e)}");
+    }
+
+    #[test]
+    fn and_or_are_left_to_right() {
+        let list = parse_line("a || b && c").unwrap();
+        let connectors: Vec<Connector> = list.items[0].rest.iter().map(|(connector, _)| *connector).collect();
+        assert_eq!(connectors, [Connector::Or, Connector::And]);
+    }
+
+    #[test]
+    fn semicolons_separate_items() {
+        assert_eq!(shape("a; b && c; d | e;"), "{a}; {b && c}; {(d | e)}");
+        assert!(parse_line("  # nothing").unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn ampersands_separate_items_too() {
+        assert_eq!(shape("a; b && c & d | e; f &"), "{a}; {b && c} &; {(d | e)}; {f} &");
+    }
+
+    #[test]
+    fn background_applies_to_the_whole_and_or() {
+        let list = parse_line("a && b | c &").unwrap();
+        assert_eq!(list.items.len(), 1);
+        assert!(list.items[0].background);
+        assert_eq!(list.items[0].rest[0].1.commands.len(), 2);
+    }
+
+    #[test]
+    fn operators_continue_on_the_next_line() {
+        assert_eq!(shape("a &&\nb |\nc"), "{a && (b | c)}");
+        assert_eq!(shape("a\nb;"), "{a}; {b}");
+    }
+
+    #[test]
+    fn rejects_misplaced_operators() {
+        for line in ["| a", "a ;; b", "a && || b", "; a", "a & & b"] {
+            let error = parse_line(line).unwrap_err();
+            assert!(!error.incomplete, "{line:?} gave {error:?}");
+        }
+    }
+
+    #[test]
+    fn unfinished_lines_are_incomplete() {
+        for line in ["a |", "a &&", "a ||", "if true; then a", "while a; do", "{ a;"] {
+            let error = parse_line(line).unwrap_err();
+            assert!(error.incomplete, "{line:?} gave {error:?}");
+        }
+    }
+
+    #[test]
+    fn compound_commands_are_single_stages() {
+        assert_eq!(shape("if a; then b; fi | c && { d; e; }"), "{(compound | c) && compound}");
+    }
+
+    #[test]
+    fn commands_are_written_back_as_source() {
+        let list = parse_line("A='x y'  echo \"$HOME\"/*.txt a\\ b 2>&1 | cat &\nf() { :; }").unwrap();
+        assert_eq!(list.to_string(), "A='x y' echo \"${HOME}\"/*.txt a' 'b 2>&1 | cat & f() { :; }");
+
+        let lines = [
+            "f() { for x in a 'b c'; do case $x in a | 'b'*) echo \"<$x>\";; *) ;; esac; done; } >log",
+            "while true; do if a; then b & else c; fi; done | d || e",
+        ];
+        for line in lines {
+            let written = parse_line(line).unwrap().to_string();
+            assert_eq!(parse_line(&written).unwrap().to_string(), written);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use crate::git::Repository;
+use crate::shell::Shell;
+use crate::users;
+
+/// The prompt used when `PS1` isn't set: the current directory, and the status
+/// of the last command in red when it failed.
+const DEFAULT_PROMPT: &str = "\\w\\( \\{red}[\\?]\\{reset}\\)> ";
+
+/// The prompt used when `PS2` isn't set, for the lines that continue a command.
+const DEFAULT_CONTINUATION: &str = "> ";
+
+/// Builds the prompt from the template in the `PS1` variable, or in `PS2` for
+/// the lines that continue an unfinished command.
+pub fn render(shell: &mut Shell, continuation: bool) -> String {
+    // The template is copied since the segments can change the shell
+    let template = match continuation {
+        true => shell.variables.get("PS2").unwrap_or(DEFAULT_CONTINUATION),
+        false => shell.variables.get("PS1").unwrap_or(DEFAULT_PROMPT)
+    }.to_owned();
+
+    expand(shell, &template)
+}
+
+/// Replaces the segments of a prompt template, which start with a backslash:
+///
+/// - `\w` is the current directory, `\W` its name and `\p` the directory with
+///   the names of its parents shortened to their first letter.
+/// - `\u` is the user, `\h` the name of the computer and `\H` its full name.
+/// - `\t` is the time (`\T` with 12 hours), `\A` the hours and minutes, and `\d` the date.
+/// - `\?` is the status of the last command, `\c` how long it took, `\j` the
+///   amount of jobs and `\g` the git branch. They're empty when there's nothing
+///   to show: a status of 0, a command that took less than a second, no jobs or no repository.
+/// - `\G` is how many commits the branch is ahead (`↑`) and behind (`↓`) its
+///   upstream branch, and `\M` is `+` when there are staged changes and `*` when
+///   there are unstaged ones. The changes are checked in the background, since
+///   it can take a while in large repositories.
+/// - `\(` and `\)` surround a part that's only shown when a segment in it isn't empty.
+/// - `\{name}` changes the color (`red`, `bold`, `reset`...), and is left out
+///   when the terminal doesn't support colors.
+/// - `\$` is `#` for the superuser and `$` for the rest, `\n` a line break,
+///   `\e` an escape character and `\\` a backslash.
+fn expand(shell: &mut Shell, template: &str) -> String {
+    let mut output = String::new();
+    // The repository is only looked for when a segment needs it
+    let mut repository: Option<Option<Repository>> = None;
+    // The start of each open part, and whether any segment in it has been shown
+    let mut parts: Vec<(usize, bool)> = vec![];
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+
+        let Some(code) = chars.next() else {
+            output.push('\\');
+            break;
+        };
+
+        let segment = match code {
+            '(' => {
+                parts.push((output.len(), false));
+                continue;
+            },
+            ')' => {
+                match parts.pop() {
+                    Some((start, false)) => output.truncate(start),
+                    Some((_, true)) => mark_shown(&mut parts),
+                    None => ()
+                }
+                continue;
+            },
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                match color(&name) {
+                    Some(code) if shell.ansi => output += &format!("\x1B[{code}m"),
+                    Some(_) => (),
+                    None => output += &format!("\\{{{name}}}")
+                }
+                continue;
+            },
+            'w' => shell.display_path(),
+            'W' => match shell.display_path().as_str() {
+                "~" => String::from("~"),
+                _ => shell.path.file_name().map_or_else(|| shell.path.display().to_string(), |name| name.to_string_lossy().into_owned())
+            },
+            'p' => abbreviate(&shell.display_path()),
+            'u' => shell.variables.get("USER").map(str::to_owned).or_else(users::current_user).unwrap_or_default(),
+            'h' => users::host_name().unwrap_or_default().split('.').next().unwrap_or_default().to_owned(),
+            'H' => users::host_name().unwrap_or_default(),
+            't' | 'T' | 'A' | 'd' => local_time().map(|time| time.format(code)).unwrap_or_default(),
+            '?' if shell.status != 0 => shell.status.to_string(),
+            '?' => String::new(),
+            'c' => shell.duration.filter(|duration| duration.as_secs() >= 1).map(format_duration).unwrap_or_default(),
+            'j' => match shell.jobs.count() {
+                0 => String::new(),
+                count => count.to_string()
+            },
+            'g' | 'G' | 'M' => {
+                let Some(repository) = repository.get_or_insert_with(|| Repository::find(&shell.path)) else {
+                    continue;
+                };
+
+                match code {
+                    'g' => repository.branch().unwrap_or_default(),
+                    'G' => match shell.git.divergence(repository) {
+                        Some((ahead, behind)) => {
+                            let ahead = if ahead > 0 { format!("↑{ahead}") } else { String::new() };
+                            let behind = if behind > 0 { format!("↓{behind}") } else { String::new() };
+                            ahead + &behind
+                        },
+                        None => String::new()
+                    },
+                    _ => match shell.git.changes(repository) {
+                        Some(changes) => format!("{}{}", if changes.staged { "+" } else { "" }, if changes.dirty { "*" } else { "" }),
+                        None => String::new()
+                    }
+                }
+            },
+            '$' => String::from(if users::is_root() { "#" } else { "$" }),
+            'n' => String::from("\n"),
+            'e' => String::from("\x1B"),
+            'a' => String::from("\x07"),
+            '\\' => String::from("\\"),
+            // Other shells use these to mark the parts that take no space, which isn't needed here
+            '[' | ']' => continue,
+            _ => format!("\\{code}")
+        };
+
+        if !segment.is_empty() {
+            mark_shown(&mut parts);
+        }
+        output += &segment;
+    }
+
+    output
+}
+
+/// Marks the innermost open part of a prompt as shown, since a segment in it isn't empty.
+fn mark_shown(parts: &mut [(usize, bool)]) {
+    if let Some((_, shown)) = parts.last_mut() {
+        *shown = true;
+    }
+}
+
+/// The ANSI code of a color or style.
+fn color(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "reset" => "0",
+        "bold" => "1",
+        "dim" => "2",
+        "italic" => "3",
+        "underline" => "4",
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        "gray" => "90",
+        _ => return None
+    })
+}
+
+/// Shortens the names of the parent directories of a path to their first letter,
+/// like `~/p/rust-shell/src`. Hidden directories keep their dot.
+fn abbreviate(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').collect();
+    let last = parts.len().saturating_sub(1);
+
+    parts.iter().enumerate()
+        .map(|(index, part)| match index {
+            _ if index == last || *part == "~" => part.to_string(),
+            _ => {
+                let take = if part.starts_with('.') { 2 } else { 1 };
+                part.chars().take(take).collect()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Writes how long a command took, like `4s`, `2m 5s` or `1h 30m`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds / 60 % 60)
+    }
+}
+
+struct Time {
+    hour: i32,
+    minute: i32,
+    second: i32,
+    /// Day of the week, starting with Sunday at 0.
+    weekday: i32,
+    day: i32,
+    /// Month, starting with January at 0.
+    month: i32,
+}
+
+impl Time {
+    /// Writes the time like the `\t`, `\T`, `\A` and `\d` segments.
+    fn format(&self, code: char) -> String {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+        match code {
+            'T' => format!("{:02}:{:02}:{:02}", (self.hour + 11) % 12 + 1, self.minute, self.second),
+            'A' => format!("{:02}:{:02}", self.hour, self.minute),
+            'd' => format!(
+                "{} {} {:02}",
+                WEEKDAYS[self.weekday.rem_euclid(7) as usize],
+                MONTHS[self.month.rem_euclid(12) as usize],
+                self.day
+            ),
+            _ => format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+        }
+    }
+}
+
+/// The current time in the time zone of the system.
+#[cfg(unix)]
+fn local_time() -> Option<Time> {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return None;
+    }
+
+    Some(Time { hour: tm.tm_hour, minute: tm.tm_min, second: tm.tm_sec, weekday: tm.tm_wday, day: tm.tm_mday, month: tm.tm_mon })
+}
+
+/// The current time, in UTC since the time zone isn't known.
+#[cfg(not(unix))]
+fn local_time() -> Option<Time> {
+    let seconds = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() as i64;
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400) as i32;
+
+    // The civil date of a day count, from Howard Hinnant's algorithm
+    let z = days + 719468;
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+
+    Some(Time {
+        hour: time / 3600,
+        minute: time / 60 % 60,
+        second: time % 60,
+        weekday: (days + 4).rem_euclid(7) as i32,
+        day: (day_of_year - (153 * month + 2) / 5 + 1) as i32,
+        month: (if month < 10 { month + 2 } else { month - 10 }) as i32,
+    })
+}
@@ -0,0 +1,1301 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::aliases::Aliases;
+use crate::commands;
+use crate::completion::Specs;
+#[cfg(unix)]
+use crate::copy::{self, Task};
+use crate::editor::Keymap;
+use crate::git::Watcher;
+use crate::glob;
+use crate::history::History;
+use crate::jobs::Jobs;
+use crate::lexer::{self, Redirect, SyntaxError, Word};
+use crate::signals;
+use crate::traps::Traps;
+use crate::parser::{self, AndOr, Command, Compound, Connector, Function, List, Pipeline, Redirection, SimpleCommand};
+use crate::streams::{self, Input, Output, Streams};
+#[cfg(not(unix))]
+use crate::streams::Capture;
+use crate::users;
+use crate::variables::Variables;
+
+/// How deep functions can call each other, to stop runaway recursion before
+/// the shell runs out of stack.
+const MAX_DEPTH: usize = 1000;
+
+/// Names of the commands run by the shell itself.
+pub const BUILTINS: &[&str] = &[
+    ".", ":", "[", "alias", "bg", "break", "cd", "complete", "continue", "cp", "disown", "env", "exit", "export", "false", "fg", "functions", "help",
+    "history", "jobs", "local", "ls", "md", "mv", "return", "rm", "set", "shift", "source", "test", "touch", "trap", "true", "unalias", "unset",
+    "version", "wait",
+];
+
+/// The built-in commands whose assignments stay after they run, like in other shells.
+const SPECIAL_BUILTINS: &[&str] = &[".", ":", "break", "continue", "exit", "export", "return", "set", "shift", "source", "trap", "unset"];
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Makes a pipeline fail if any of its commands fails, not only the last one.
+    pub pipefail: bool,
+    /// Keeps an interactive shell open when Control-D is pressed in an empty line.
+    pub ignoreeof: bool,
+    /// Turns off the expansion of wildcards.
+    pub noglob: bool,
+    /// Removes the patterns that don't match any file, instead of leaving them as they are.
+    pub nullglob: bool,
+    /// Makes the command fail when a pattern doesn't match any file.
+    pub failglob: bool,
+    /// Edits lines with the keys of Vi instead of the ones of Emacs.
+    pub vi: bool,
+}
+
+impl Options {
+    /// The names of the options along with their values.
+    pub fn list(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("emacs", !self.vi),
+            ("failglob", self.failglob),
+            ("ignoreeof", self.ignoreeof),
+            ("noglob", self.noglob),
+            ("nullglob", self.nullglob),
+            ("pipefail", self.pipefail),
+            ("vi", self.vi),
+        ]
+    }
+
+    /// Turns an option on or off, returning whether it exists. `emacs` and `vi`
+    /// choose the keys of the line editor, so turning one on turns the other off.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match name {
+            "emacs" => self.vi = !value,
+            "failglob" => self.failglob = value,
+            "ignoreeof" => self.ignoreeof = value,
+            "noglob" => self.noglob = value,
+            "nullglob" => self.nullglob = value,
+            "pipefail" => self.pipefail = value,
+            "vi" => self.vi = value,
+            _ => return false
+        }
+
+        true
+    }
+
+    pub fn keymap(&self) -> Keymap {
+        if self.vi { Keymap::Vi } else { Keymap::Emacs }
+    }
+}
+
+pub struct Shell {
+    /// Current working directory.
+    pub path: PathBuf,
+    pub options: Options,
+    pub variables: Variables,
+    pub aliases: Aliases,
+    /// The commands typed by the user, which is empty when not running interactively.
+    pub history: History,
+    /// How the arguments of some commands are completed.
+    pub completions: Specs,
+    /// Whether the terminal understands ANSI escape codes.
+    pub ansi: bool,
+    /// Exit status of the last command.
+    pub status: i32,
+    /// How long the last command typed by the user took to run.
+    pub duration: Option<Duration>,
+    /// Checks the changes in git repositories for the prompt.
+    pub git: Watcher,
+    /// The commands running in the background or stopped.
+    pub jobs: Jobs,
+    /// The commands run when signals arrive, the shell exits or a command fails.
+    pub traps: Traps,
+    /// Whether the commands are typed by the user. Other shells end on errors like
+    /// the one of `${NAME:?message}`.
+    pub interactive: bool,
+    /// Set by the `exit` command.
+    pub exiting: bool,
+    /// Set by the commands that stop the ones being run, like `return`.
+    pub flow: Option<Flow>,
+    pub functions: BTreeMap<String, Rc<Function>>,
+    /// The name of the shell or the script being run, as `$0`.
+    pub name: String,
+    /// The process id of the shell, as `$$`, which its copies running in the background keep.
+    pub pid: u32,
+    /// The arguments of the running function or script, as `$1`, `$2` and so on.
+    pub positional: Vec<String>,
+    /// How many function calls are running.
+    pub depth: usize,
+    /// How many files are being run with `source`.
+    pub sourcing: usize,
+    /// How many loops are running in the current function (or outside functions).
+    pub loops: usize,
+    /// How many conditions of `if`, `while` or `until` are running, where failing
+    /// commands don't run the ERR trap.
+    conditions: usize,
+    /// Set in a copy of the shell writing into a pipe, which ends along with a
+    /// command that found the pipe closed, like `yes` would.
+    piped: bool,
+    /// The lines of a command that isn't complete yet, like an open block.
+    pending: String,
+    /// The file being run and the line of it where the current command starts,
+    /// so syntax errors can say where they are.
+    location: Option<(String, usize)>,
+    /// Exit status of the last command substitution run while expanding the
+    /// current command, which is the status of a command with only assignments.
+    pub substitution: Option<i32>,
+}
+
+/// A change in the order commands run in, which stops the ones being run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    /// `return`, which leaves the running function.
+    Return,
+    /// `break`, with the amount of loops left to leave.
+    Break(usize),
+    /// `continue`, with the amount of loops left to leave before going on with the next one.
+    Continue(usize),
+}
+
+enum Stage {
+    Running(Child),
+    Finished(i32),
+}
+
+impl Stage {
+    /// The process id of the stage, while it's running.
+    fn pid(&self) -> Option<u32> {
+        match self {
+            Stage::Running(child) => Some(child.id()),
+            Stage::Finished(_) => None
+        }
+    }
+}
+
+impl Shell {
+    pub fn new(path: PathBuf, ansi: bool) -> Shell {
+        let mut variables = Variables::from_environment();
+        variables.set("PWD", &path.to_string_lossy());
+        variables.export("PWD");
+
+        Shell {
+            path,
+            options: Options::default(),
+            variables,
+            aliases: Aliases::default(),
+            history: History::default(),
+            completions: Specs::default(),
+            ansi,
+            status: 0,
+            duration: None,
+            git: Watcher::default(),
+            jobs: Jobs::default(),
+            traps: Traps::default(),
+            interactive: false,
+            exiting: false,
+            flow: None,
+            functions: BTreeMap::new(),
+            name: env::args().next().unwrap_or_default(),
+            pid: process::id(),
+            positional: vec![],
+            depth: 0,
+            sourcing: 0,
+            loops: 0,
+            conditions: 0,
+            piped: false,
+            pending: String::new(),
+            location: None,
+            substitution: None,
+        }
+    }
+
+    /// The home directory of the user, taken from HOME or the system if it isn't set.
+    pub fn home(&self) -> Option<String> {
+        match self.variables.get("HOME") {
+            Some(home) if !home.is_empty() => Some(home.to_owned()),
+            _ => users::current_home()
+        }
+    }
+
+    /// The current directory as shown to the user, with the home directory written as `~`.
+    pub fn display_path(&self) -> String {
+        if let Some(home) = self.home() {
+            match self.path.strip_prefix(&home) {
+                Ok(rest) if rest.as_os_str().is_empty() => return String::from("~"),
+                Ok(rest) => return Path::new("~").join(rest).display().to_string(),
+                Err(_) => ()
+            }
+        }
+
+        self.path.display().to_string()
+    }
+
+    /// The directory with the configuration files of the shell, usually `~/.config/rust-shell`.
+    pub fn config_dir(&self) -> Option<PathBuf> {
+        let base = match self.variables.get("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(self.home()?).join(".config")
+        };
+
+        Some(base.join("rust-shell"))
+    }
+
+    /// The directory with the data files of the shell, like the history, usually `~/.local/share/rust-shell`.
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        let base = match self.variables.get("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(self.home()?).join(".local").join("share")
+        };
+
+        Some(base.join("rust-shell"))
+    }
+
+    /// Runs every line of a file, like the startup file with the aliases of the user.
+    pub fn run_file(&mut self, file: &Path, io: &mut Streams) -> io::Result<()> {
+        let contents = fs::read_to_string(file)?;
+
+        let location = self.location.replace((file.display().to_string(), 1));
+        self.run_source(&contents, io);
+        self.location = location;
+
+        Ok(())
+    }
+
+    /// Runs some lines of commands, like the ones of a file or given with `-c`.
+    pub fn run_source(&mut self, source: &str, io: &mut Streams) {
+        for (index, line) in source.lines().enumerate() {
+            if let Some((_, start)) = self.location.as_mut().filter(|_| self.pending.is_empty()) {
+                *start = index + 1;
+            }
+
+            self.run_line(line, io);
+            if self.interrupted() {
+                break;
+            }
+        }
+
+        self.finish_input(io);
+    }
+
+    /// Splits some lines into tokens, replaces their aliases and builds their commands.
+    pub fn parse(&self, source: &str) -> Result<List, SyntaxError> {
+        lexer::tokenize(source)
+            .and_then(|tokens| parser::parse(self.aliases.expand(tokens)?))
+            .map_err(|error| error.locate(source))
+    }
+
+    /// Runs a line of input. A line that leaves something open, like quotes or a
+    /// block, is kept until the lines that complete it come.
+    pub fn run_line(&mut self, line: &str, io: &mut Streams) {
+        if let Some(list) = self.read_command(line, io) {
+            self.run_list(&list, io);
+        }
+    }
+
+    /// Runs a line typed by the user, replacing its references to earlier commands
+    /// (like `!!`) and adding it to the history.
+    pub fn run_input(&mut self, line: &str, io: &mut Streams) {
+        let line = match self.history.expand(line) {
+            Ok(Some(expanded)) => {
+                // The command is shown as it's going to run
+                outln!(io, "{expanded}");
+                expanded
+            },
+            Ok(None) => line.to_owned(),
+            Err(error) => {
+                errln!(io, "{error}");
+                self.status = 1;
+                return;
+            }
+        };
+
+        let command = format!("{}{line}", self.pending);
+        let list = self.read_command(&line, io);
+
+        if !self.is_pending() {
+            if let Err(error) = self.history.add(command.trim_end_matches('\n')) {
+                errln!(io, "Error saving the history: {error}");
+            }
+        }
+
+        if let Some(list) = list {
+            self.run_list(&list, io);
+        }
+    }
+
+    /// Adds a line to the ones of the command being read, returning the command
+    /// once it's complete.
+    fn read_command(&mut self, line: &str, io: &mut Streams) -> Option<List> {
+        self.pending.push_str(line);
+        if !self.pending.ends_with('\n') {
+            self.pending.push('\n');
+        }
+
+        match self.parse(&self.pending) {
+            Ok(list) => {
+                self.pending.clear();
+                Some(list)
+            },
+            Err(error) if error.incomplete => None,
+            Err(error) => {
+                self.pending.clear();
+                self.syntax_error(error, io);
+                None
+            }
+        }
+    }
+
+    /// Forgets the lines of an unfinished command, like when Control-C is pressed.
+    pub fn discard_input(&mut self) {
+        self.pending.clear();
+        self.status = 130;
+    }
+
+    /// Checks whether the lines read so far leave a command unfinished.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Reports the command left unfinished when there are no more lines.
+    pub fn finish_input(&mut self, io: &mut Streams) {
+        let pending = mem::take(&mut self.pending);
+        if let Err(error) = self.parse(&pending) {
+            self.syntax_error(error, io);
+        }
+    }
+
+    /// Shows a syntax error, along with the file and line it's in when running a file.
+    fn syntax_error(&mut self, error: SyntaxError, io: &mut Streams) {
+        match &self.location {
+            Some((file, start)) => {
+                let line = start + error.line - 1;
+                errln!(io, "{file}: line {line}: Invalid syntax: {} (column {}).", error.message, error.column);
+            },
+            None => errln!(io, "Invalid syntax: {error}.")
+        }
+
+        self.status = 2;
+    }
+
+    /// Checks whether the commands being run must stop, because of `exit`, `return`,
+    /// `break`, `continue` or Control-C.
+    fn interrupted(&self) -> bool {
+        self.exiting || self.flow.is_some() || signals::is_interrupted()
+    }
+
+    pub fn run_list(&mut self, list: &List, io: &mut Streams) -> i32 {
+        let mut status = 0;
+        for item in &list.items {
+            if self.interrupted() {
+                break;
+            }
+
+            status = match item.background {
+                true => self.run_background(item, io),
+                false => self.run_and_or(item, io)
+            };
+            self.run_traps(io);
+        }
+
+        status
+    }
+
+    /// Starts running some commands in the background, in a copy of the shell.
+    #[cfg(unix)]
+    fn run_background(&mut self, and_or: &AndOr, io: &mut Streams) -> i32 {
+        let mut streams = match io.try_clone() {
+            Ok(streams) => streams,
+            Err(error) => {
+                errln!(io, "Error starting the job: {error}");
+                return 1;
+            }
+        };
+
+        // Without job control, jobs can't take the input of the shell
+        let control = self.jobs.is_control();
+        if !control && matches!(streams.stdin, Input::Stdin) {
+            if let Ok(null) = File::open("/dev/null") {
+                streams.stdin = Input::File(null);
+            }
+        }
+
+        let pid = match copy::start(self, &Task::Job(and_or.to_string()), control.then_some(0), &streams) {
+            Ok(child) => child.id() as i32,
+            Err(error) => {
+                errln!(io, "Error starting the job: {error}");
+                return 1;
+            }
+        };
+
+        let id = self.jobs.add_background(pid, and_or.to_string());
+        if control {
+            errln!(io, "[{id}] {pid}");
+        }
+
+        0
+    }
+
+    /// Without process groups there's no job control, so the commands just run
+    /// in the foreground.
+    #[cfg(not(unix))]
+    fn run_background(&mut self, and_or: &AndOr, io: &mut Streams) -> i32 {
+        self.run_and_or(and_or, io)
+    }
+
+    fn run_and_or(&mut self, and_or: &AndOr, io: &mut Streams) -> i32 {
+        self.status = self.run_pipeline(&and_or.first, io);
+        // Only a failure of the last pipeline runs the ERR trap, the others are tested.
+        // Like in other shells, it doesn't run inside functions, only for their calls
+        let mut last = and_or.rest.is_empty();
+
+        for (index, (connector, pipeline)) in and_or.rest.iter().enumerate() {
+            if self.interrupted() {
+                break;
+            }
+
+            let run = match connector {
+                Connector::And => self.status == 0,
+                Connector::Or => self.status != 0,
+            };
+
+            if run {
+                self.status = self.run_pipeline(pipeline, io);
+                last = index == and_or.rest.len() - 1;
+            }
+        }
+
+        if self.status != 0 && last && self.conditions == 0 && self.depth == 0 && !self.exiting {
+            self.run_err_trap(io);
+        }
+
+        self.status
+    }
+
+    /// Runs a command of the shell that isn't the last one of a pipeline in a copy
+    /// of the shell, so it runs at the same time as the next ones like a program
+    /// does. The copy ends with SIGPIPE when the next command stops reading.
+    #[cfg(unix)]
+    fn run_copied(&mut self, command: &Command, words: Vec<String>, group: Option<u32>, mut io: Streams) -> Stage {
+        match copy::start(self, &Task::Stage(command.to_string(), words), group, &io) {
+            Ok(child) => Stage::Running(child),
+            Err(error) => {
+                errln!(io, "Error starting the command: {error}");
+                Stage::Finished(1)
+            }
+        }
+    }
+
+    /// Runs what a copy of the shell was started for, reading it from a pipe along
+    /// with the state of the shell, and exits with its status.
+    #[cfg(unix)]
+    pub fn run_copy(&mut self, descriptor: i32) -> ! {
+        // Rust's runtime ignores SIGPIPE, but the copy must end like a program when
+        // it writes into a closed pipe
+        unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+        let mut io = Streams::standard();
+        let status = match copy::load(self, descriptor) {
+            Ok(Task::Job(source)) => copy::parse(&source).map(|list| self.run_list(&list, &mut io)),
+            Ok(Task::Stage(source, words)) => copy::parse_command(&source).map(|command| {
+                self.piped = true;
+                match self.run_command(&command, Ok(words), None, Streams::standard()) {
+                    Stage::Running(mut child) => child.wait().map(signals::exit_code).unwrap_or(1),
+                    Stage::Finished(status) => status
+                }
+            }),
+            Err(error) => Err(error)
+        };
+
+        self.status = status.unwrap_or_else(|error| {
+            errln!(io, "Error starting a copy of the shell: {error}");
+            1
+        });
+        self.run_exit_trap(&mut io);
+        process::exit(self.status);
+    }
+
+    /// Runs every command of the pipeline at the same time, connecting the output
+    /// of each one to the input of the next. Built-in commands run inside the shell,
+    /// or in a copy of it when they aren't the last one.
+    fn run_pipeline(&mut self, pipeline: &Pipeline, io: &mut Streams) -> i32 {
+        let last = pipeline.commands.len() - 1;
+        let mut stages = vec![];
+        let mut input: Option<Input> = None;
+        // With job control, the programs of the pipeline get a process group, created by the first one
+        let mut group = self.jobs.is_control().then_some(0);
+
+        for (index, command) in pipeline.commands.iter().enumerate() {
+            let mut streams = match io.try_clone() {
+                Ok(streams) => streams,
+                Err(error) => {
+                    errln!(io, "Error preparing the pipeline: {error}");
+                    return 1;
+                }
+            };
+
+            if let Some(input) = input.take() {
+                streams.stdin = input;
+            }
+
+            // Simple commands are expanded first, since their name decides where they run
+            let args = match command {
+                Command::Simple(simple) => {
+                    self.substitution = None;
+                    self.expand_words(&simple.words)
+                },
+                _ => Ok(vec![])
+            };
+
+            if index == last {
+                stages.push(self.run_command(command, args, group, streams));
+                continue;
+            }
+
+            // Words that couldn't be expanded are only reported
+            let in_process = match (command, &args) {
+                (Command::Simple(_), Ok(args)) => args.first().map_or(true, |name| self.is_internal(name)),
+                (Command::Simple(_), Err(_)) => false,
+                _ => true
+            };
+
+            #[cfg(unix)]
+            let next = streams::pipe().map(|(reader, writer)| {
+                streams.stdout = Output::File(writer);
+                stages.push(match (in_process, args) {
+                    (true, Ok(words)) => self.run_copied(command, words, group, streams),
+                    (_, args) => self.run_command(command, args, group, streams)
+                });
+                Input::File(reader)
+            });
+
+            // Without copies of the shell, commands run by the shell finish before the next one starts,
+            // so their output is collected first to avoid filling the pipe with no one reading it
+            #[cfg(not(unix))]
+            let next = if in_process {
+                Capture::new().and_then(|(output, capture)| {
+                    streams.stdout = output;
+                    stages.push(self.run_command(command, args, group, streams));
+                    streams::feed(capture.finish())
+                })
+            }
+            else {
+                streams::pipe().map(|(reader, writer)| {
+                    streams.stdout = Output::File(writer);
+                    stages.push(self.run_command(command, args, group, streams));
+                    Input::File(reader)
+                })
+            };
+
+            if group == Some(0) {
+                group = stages.last().and_then(Stage::pid).or(group);
+            }
+
+            match next {
+                Ok(next) => input = Some(next),
+                Err(error) => {
+                    errln!(io, "Error creating a pipe: {error}");
+                    break;
+                }
+            }
+        }
+
+        // The programs are waited for together, since they can be stopped as a job
+        let mut children = vec![];
+        let mut statuses: Vec<Option<i32>> = vec![];
+        for stage in stages {
+            match stage {
+                #[cfg(unix)]
+                Stage::Running(_) => {
+                    children.extend(stage.pid().map(|pid| pid as i32));
+                    statuses.push(None);
+                },
+                #[cfg(not(unix))]
+                Stage::Running(child) => {
+                    children.push(child);
+                    statuses.push(None);
+                },
+                Stage::Finished(status) => statuses.push(Some(status))
+            }
+        }
+
+        let mut waited = match children.is_empty() {
+            true => vec![],
+            false => self.jobs.run_foreground(children, pipeline.to_string(), io)
+        }.into_iter();
+
+        let statuses: Vec<i32> = statuses.into_iter()
+            .map(|status| status.or_else(|| waited.next()).unwrap_or(1))
+            .collect();
+
+        let last_status = statuses.last().copied().unwrap_or(1);
+        #[cfg(unix)]
+        if self.piped && last_status == 128 + libc::SIGPIPE {
+            signals::die(libc::SIGPIPE);
+        }
+
+        if self.options.pipefail {
+            return statuses.into_iter().rev().find(|status| *status != 0).unwrap_or(0);
+        }
+
+        last_status
+    }
+
+    /// Checks whether a command is run by the shell itself, as a built-in or a function.
+    fn is_internal(&self, name: &str) -> bool {
+        BUILTINS.contains(&name) || self.functions.contains_key(name)
+    }
+
+    /// Runs a command of a pipeline. Simple commands are given their expanded words.
+    fn run_command(&mut self, command: &Command, words: Result<Vec<String>, String>, group: Option<u32>, io: Streams) -> Stage {
+        match command {
+            Command::Simple(simple) => self.run_simple(simple, words, group, io),
+            Command::Compound(compound, redirections) => Stage::Finished(self.run_compound(compound, redirections, io)),
+            Command::Function(function) => {
+                self.functions.insert(function.name.clone(), function.clone());
+                Stage::Finished(0)
+            }
+        }
+    }
+
+    fn run_compound(&mut self, compound: &Compound, redirections: &[Redirection], mut io: Streams) -> i32 {
+        if let Err(error) = self.redirect(redirections, &mut io) {
+            errln!(io, "{error}");
+            return 1;
+        }
+
+        match compound {
+            Compound::Group(list) => self.run_list(list, &mut io),
+            Compound::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    let status = self.run_condition(condition, &mut io);
+                    if self.interrupted() {
+                        return status;
+                    }
+
+                    if status == 0 {
+                        return self.run_list(body, &mut io);
+                    }
+                }
+
+                match otherwise {
+                    Some(body) => self.run_list(body, &mut io),
+                    None => 0
+                }
+            },
+            Compound::While(condition, body) => self.run_loop(condition, body, false, &mut io),
+            Compound::Until(condition, body) => self.run_loop(condition, body, true, &mut io),
+            Compound::For { name, words, body } => self.run_for(name, words.as_deref(), body, &mut io),
+            Compound::Case { word, items } => self.run_case(word, items, &mut io)
+        }
+    }
+
+    /// Runs the condition of an `if`, `while` or `until`, whose failures are expected.
+    fn run_condition(&mut self, condition: &List, io: &mut Streams) -> i32 {
+        self.conditions += 1;
+        let status = self.run_list(condition, io);
+        self.conditions -= 1;
+        status
+    }
+
+    /// Runs a `while` loop, or an `until` one when `until` is true, which stops
+    /// once its condition succeeds instead.
+    fn run_loop(&mut self, condition: &List, body: &List, until: bool, io: &mut Streams) -> i32 {
+        let mut status = 0;
+        self.loops += 1;
+
+        loop {
+            let result = self.run_condition(condition, io);
+            if self.loop_done() || (result == 0) == until {
+                break;
+            }
+
+            status = self.run_list(body, io);
+            if self.loop_done() {
+                break;
+            }
+        }
+
+        self.loops -= 1;
+        status
+    }
+
+    fn run_for(&mut self, name: &str, words: Option<&[Word]>, body: &List, io: &mut Streams) -> i32 {
+        let values = match words {
+            Some(words) => match self.expand_words(words) {
+                Ok(values) => values,
+                Err(error) => {
+                    errln!(io, "{error}");
+                    return 1;
+                }
+            },
+            None => self.positional.clone()
+        };
+
+        let mut status = 0;
+        self.loops += 1;
+
+        for value in values {
+            self.variables.set(name, &value);
+            status = self.run_list(body, io);
+            if self.loop_done() {
+                break;
+            }
+        }
+
+        self.loops -= 1;
+        status
+    }
+
+    /// Runs the body of the first item of a `case` command with a pattern that
+    /// matches its word.
+    fn run_case(&mut self, word: &Word, items: &[(Vec<Word>, List)], io: &mut Streams) -> i32 {
+        let subject = match self.expand_word(word) {
+            Ok(subject) => subject,
+            Err(error) => {
+                errln!(io, "{error}");
+                return 1;
+            }
+        };
+
+        for (patterns, body) in items {
+            for pattern in patterns {
+                match self.expand_pattern(pattern) {
+                    Ok(pattern) if glob::matches_text(&pattern, &subject) => return self.run_list(body, io),
+                    Ok(_) => (),
+                    Err(error) => {
+                        errln!(io, "{error}");
+                        return 1;
+                    }
+                }
+            }
+        }
+
+        0
+    }
+
+    /// Runs the commands of a file in the current shell, so the variables, functions
+    /// and aliases it defines stay. The arguments after the file name become the
+    /// positional parameters while it runs.
+    fn source(&mut self, command: &str, args: &[&str], io: &mut Streams) -> i32 {
+        let Some((file, args)) = args.split_first() else {
+            errln!(io, "{command}: There's no file parameter.");
+            return 1;
+        };
+
+        let file = self.path.join(file);
+        let positional = match args.is_empty() {
+            true => None,
+            false => Some(mem::replace(&mut self.positional, args.iter().map(|arg| arg.to_string()).collect()))
+        };
+
+        self.sourcing += 1;
+        let result = self.run_file(&file, io);
+        self.sourcing -= 1;
+
+        if let Some(positional) = positional {
+            self.positional = positional;
+        }
+
+        if let Some(Flow::Return) = self.flow {
+            self.flow = None;
+        }
+
+        match result {
+            Ok(()) => self.status,
+            Err(error) => {
+                errln!(io, "{command}: {}: {error}", file.display());
+                1
+            }
+        }
+    }
+
+    /// Takes care of a `break` or `continue` that reached the loop being run,
+    /// returning whether the loop must stop.
+    fn loop_done(&mut self) -> bool {
+        match self.flow {
+            Some(Flow::Break(1)) => {
+                self.flow = None;
+                true
+            },
+            Some(Flow::Break(levels)) => {
+                self.flow = Some(Flow::Break(levels - 1));
+                true
+            },
+            Some(Flow::Continue(1)) => {
+                self.flow = None;
+                false
+            },
+            Some(Flow::Continue(levels)) => {
+                self.flow = Some(Flow::Continue(levels - 1));
+                true
+            },
+            Some(Flow::Return) => true,
+            None => self.exiting || signals::is_interrupted()
+        }
+    }
+
+    /// Runs a function with its own positional parameters and local variables.
+    fn call_function(&mut self, function: &Function, args: &[&str], assignments: Vec<(String, String)>, mut io: Streams) -> i32 {
+        if self.depth >= MAX_DEPTH {
+            errln!(io, "{}: Too many nested function calls.", function.name);
+            return 1;
+        }
+
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let positional = mem::replace(&mut self.positional, args);
+        // Loops outside the function can't be left from inside it
+        let loops = mem::take(&mut self.loops);
+        self.variables.push_scope();
+        self.depth += 1;
+
+        // Assignments written before the call only last until it returns
+        for (name, value) in assignments {
+            self.variables.make_local(&name);
+            self.variables.set(&name, &value);
+            self.variables.export(&name);
+        }
+
+        let status = self.run_compound(&function.body, &function.redirections, io);
+        if let Some(Flow::Return) = self.flow {
+            self.flow = None;
+        }
+
+        self.depth -= 1;
+        self.variables.pop_scope();
+        self.loops = loops;
+        self.positional = positional;
+        status
+    }
+
+    /// Runs a single command, given its expanded words, with its redirections.
+    /// External programs are left running.
+    fn run_simple(&mut self, command: &SimpleCommand, words: Result<Vec<String>, String>, group: Option<u32>, mut io: Streams) -> Stage {
+        let words = match words {
+            Ok(words) => words,
+            Err(error) => {
+                errln!(io, "{error}");
+                return Stage::Finished(1);
+            }
+        };
+
+        if let Err(error) = self.redirect(&command.redirections, &mut io) {
+            errln!(io, "{error}");
+            return Stage::Finished(1);
+        }
+
+        let mut assignments = vec![];
+        for assignment in &command.assignments {
+            match self.expand_word(&assignment.value) {
+                Ok(value) => assignments.push((assignment.name.clone(), value)),
+                Err(error) => {
+                    errln!(io, "{error}");
+                    return Stage::Finished(1);
+                }
+            }
+        }
+
+        let mut args: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+        if args.is_empty() {
+            for (name, value) in assignments {
+                self.variables.set(&name, &value);
+            }
+            return Stage::Finished(self.substitution.unwrap_or(0));
+        }
+
+        // Assignments written before a built-in only last while it runs, unless
+        // it's a special one or it changes them itself, like `PWD=x cd dir`
+        let name = args.remove(0);
+        let status = match SPECIAL_BUILTINS.contains(&name) {
+            true => {
+                for (name, value) in &assignments {
+                    self.variables.set(name, value);
+                }
+                self.run_builtin(name, &args, &mut io)
+            },
+            false => {
+                let saved = self.variables.set_temporarily(&assignments);
+                let status = self.run_builtin(name, &args, &mut io);
+                self.variables.restore_unchanged(saved, &assignments);
+                status
+            }
+        };
+
+        if let Some(status) = status {
+            return Stage::Finished(status);
+        }
+
+        if let Some(function) = self.functions.get(name).cloned() {
+            return Stage::Finished(self.call_function(&function, &args, assignments, io));
+        }
+
+        // Assignments written before a program only apply to it
+        let mut environment = self.variables.environment();
+        environment.extend(assignments);
+
+        match commands::execute_command(&self.path, name, &args, &environment, group, &mut io) {
+            Ok(child) => Stage::Running(child),
+            Err(status) => Stage::Finished(status)
+        }
+    }
+
+    /// Applies the redirections of a command, in order, to its streams.
+    fn redirect(&mut self, redirections: &[Redirection], io: &mut Streams) -> Result<(), String> {
+        for redirection in redirections {
+            let target = self.expand_word(&redirection.target)?;
+            let descriptor = redirection.descriptor;
+            let open_error = |error| format!("{target}: {error}");
+
+            match redirection.redirect {
+                Redirect::Input => {
+                    if let Some(descriptor @ 1..) = descriptor {
+                        return Err(format!("Input can't be redirected to file descriptor {descriptor}"));
+                    }
+
+                    let file = File::open(self.path.join(&target)).map_err(open_error)?;
+                    io.stdin = Input::File(file);
+                },
+                Redirect::Output | Redirect::Append => {
+                    let file = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .append(redirection.redirect == Redirect::Append)
+                        .truncate(redirection.redirect == Redirect::Output)
+                        .open(self.path.join(&target))
+                        .map_err(open_error)?;
+
+                    io.set_output(descriptor.unwrap_or(1), Output::File(file)).map_err(|error| error.to_string())?;
+                },
+                Redirect::Duplicate => {
+                    let Ok(source) = target.parse::<u32>() else {
+                        return Err(format!("{target}: Expected a file descriptor number"));
+                    };
+
+                    let output = io.output(source).map_err(|error| error.to_string())?;
+                    io.set_output(descriptor.unwrap_or(1), output).map_err(|error| error.to_string())?;
+                },
+                Redirect::OutputBoth | Redirect::AppendBoth => {
+                    let file = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .append(redirection.redirect == Redirect::AppendBoth)
+                        .truncate(redirection.redirect == Redirect::OutputBoth)
+                        .open(self.path.join(&target))
+                        .map_err(open_error)?;
+
+                    let copy = file.try_clone().map_err(open_error)?;
+                    io.stdout = Output::File(file);
+                    io.stderr = Output::File(copy);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs a built-in command, or returns `None` if there's no built-in with that name.
+    fn run_builtin(&mut self, command: &str, args: &[&str], io: &mut Streams) -> Option<i32> {
+        let args = args.to_vec();
+
+        let status = match command {
+            "cd" => {
+                let home = self.home();
+                commands::change_directory(&mut self.path, &mut self.variables, args, home.as_deref(), io)
+            },
+            "ls" => commands::list_elements(&mut self.path, io),
+            "md" => commands::make_directory(&mut self.path, args, io),
+            "touch" => commands::create_file(&mut self.path, args, io),
+            "rm" => commands::remove_element(&mut self.path, args, io),
+            "mv" => commands::move_files(&mut self.path, args, io),
+            "cp" => commands::copy_files(&mut self.path, args, io),
+            "set" => commands::set_options(&mut self.options, &self.variables, args, io),
+            "export" => commands::export_variables(&mut self.variables, args, io),
+            "unset" => commands::unset_variables(&mut self.variables, &mut self.functions, args, io),
+            "local" => commands::declare_locals(&mut self.variables, args, io),
+            "functions" => commands::list_functions(&self.functions, io),
+            "history" => commands::history_command(&mut self.history, args, io),
+            "complete" => commands::complete_command(&mut self.completions, args, io),
+            "jobs" => commands::list_jobs(&mut self.jobs, args, io),
+            "fg" | "bg" => commands::resume_jobs(&mut self.jobs, command, args, io),
+            "disown" => commands::disown_jobs(&mut self.jobs, args, io),
+            "wait" => commands::wait_jobs(&mut self.jobs, args, io),
+            "trap" => commands::trap_command(&mut self.traps, args, io),
+            "alias" => commands::define_aliases(&mut self.aliases, args, io),
+            "unalias" => commands::remove_aliases(&mut self.aliases, args, io),
+            "env" if args.is_empty() => commands::show_environment(&self.variables, io),
+            "help" => commands::help_command(args, io),
+            "true" | ":" => 0,
+            "false" => 1,
+            "test" | "[" => commands::test_command(&self.path, command, args, io),
+            "version" => {
+                commands::show_version(self.ansi, io);
+                0
+            },
+            "return" if self.depth == 0 && self.sourcing == 0 => {
+                errln!(io, "return: Can only be used in a function or a sourced file.");
+                1
+            },
+            "return" => {
+                self.flow = Some(Flow::Return);
+                match args.first() {
+                    None => self.status,
+                    Some(code) => match code.parse() {
+                        Ok(status @ 0..=255) => status,
+                        _ => {
+                            errln!(io, "return: '{code}' is not a valid exit code, it must be between 0 and 255.");
+                            2
+                        }
+                    }
+                }
+            },
+            "source" | "." => self.source(command, &args, io),
+            "shift" => {
+                let count = match args.first() {
+                    None => Some(1),
+                    Some(count) => count.parse().ok()
+                };
+
+                match count {
+                    Some(count) if count <= self.positional.len() => {
+                        self.positional.drain(..count);
+                        0
+                    },
+                    Some(_) => {
+                        errln!(io, "shift: There aren't enough positional parameters.");
+                        1
+                    },
+                    None => {
+                        errln!(io, "shift: '{}' is not a valid number.", args[0]);
+                        1
+                    }
+                }
+            },
+            "break" | "continue" if self.loops == 0 => {
+                errln!(io, "{command}: Can only be used in a loop.");
+                1
+            },
+            "break" | "continue" => {
+                let levels = match args.first() {
+                    None => Some(1),
+                    Some(levels) => levels.parse().ok().filter(|levels| *levels > 0)
+                };
+
+                match levels {
+                    Some(levels) => {
+                        let levels = usize::min(levels, self.loops);
+                        self.flow = Some(if command == "break" { Flow::Break(levels) } else { Flow::Continue(levels) });
+                        0
+                    },
+                    None => {
+                        errln!(io, "{command}: '{}' is not a valid number of loops.", args[0]);
+                        1
+                    }
+                }
+            },
+            "exit" => {
+                self.exiting = true;
+                match args.first() {
+                    None => self.status,
+                    Some(code) => match code.parse() {
+                        Ok(status @ 0..=255) => status,
+                        _ => {
+                            errln!(io, "exit: '{code}' is not a valid exit code, it must be between 0 and 255.");
+                            2
+                        }
+                    }
+                }
+            },
+            _ => return None
+        };
+
+        Some(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    /// Runs some commands, returning what they wrote to their output.
+    fn run(shell: &mut Shell, source: &str) -> String {
+        let list = shell.parse(source).unwrap();
+        let (stdout, capture) = streams::Capture::new().unwrap();
+        let mut io = Streams { stdin: Input::Stdin, stdout, stderr: Output::Stderr };
+        shell.run_list(&list, &mut io);
+        drop(io);
+
+        String::from_utf8(capture.finish()).unwrap()
+    }
+
+    #[test]
+    fn cd_goes_home_and_back() {
+        let first = env::temp_dir().canonicalize().unwrap();
+        let home = first.join(format!("shell-cd-{}", std::process::id()));
+        fs::create_dir_all(&home).unwrap();
+
+        let mut shell = Shell::new(first.clone(), false);
+        shell.variables.set("HOME", &home.to_string_lossy());
+        assert_eq!(run(&mut shell, "cd"), "");
+        assert_eq!(shell.path, home);
+        assert_eq!(shell.variables.get("PWD"), Some(&*home.to_string_lossy()));
+        assert_eq!(shell.variables.get("OLDPWD"), Some(&*first.to_string_lossy()));
+
+        // `cd -` shows the directory it goes back to
+        assert_eq!(run(&mut shell, "cd -"), format!("{}\n", first.display()));
+        assert_eq!(shell.path, first);
+        assert_eq!(shell.variables.get("OLDPWD"), Some(&*home.to_string_lossy()));
+
+        fs::remove_dir(&home).unwrap();
+    }
+
+    #[test]
+    fn aliases_replace_command_names() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        // Aliases apply from the next line on, since lines are parsed before they run
+        assert_eq!(run(&mut shell, "alias say='echo said' both='say one; say' lead='echo '; say"), "");
+        assert_eq!(run(&mut shell, "say echo"), "said echo\n");
+        assert_eq!(run(&mut shell, "both"), "said one\nsaid\n");
+        // A value ending in a space makes the next word an alias too
+        assert_eq!(run(&mut shell, "lead say"), "echo said\n");
+        assert_eq!(run(&mut shell, "alias say"), "alias say='echo said'\n");
+
+        // An alias isn't expanded again inside its own value, but others are
+        run(&mut shell, "alias echo='echo x'");
+        assert_eq!(run(&mut shell, "echo y; say"), "x y\nx said\n");
+
+        assert_eq!(run(&mut shell, "unalias say echo; alias"), "alias both='say one; say'\nalias lead='echo '\n");
+    }
+
+    #[test]
+    fn functions_get_arguments_and_locals() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        shell.positional = vec![String::from("top")];
+        run(&mut shell, "X=global; f() { local X=local; echo $1 $# $X; g; }; g() { echo g sees $X; }");
+
+        assert_eq!(run(&mut shell, "f a b; echo $1 $X"), "a 2 local\ng sees local\ntop global\n");
+        // Assignments before the call last only until it returns
+        assert_eq!(run(&mut shell, "X=passed g; echo $X"), "g sees passed\nglobal\n");
+    }
+
+    #[test]
+    fn return_leaves_the_function() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        run(&mut shell, "f() { echo in; return 3; echo never; }; g() { false; return; }");
+
+        assert_eq!(run(&mut shell, "f; echo $?"), "in\n3\n");
+        // Without a status, it keeps the one of the last command
+        assert_eq!(run(&mut shell, "g; echo $?"), "1\n");
+        assert_eq!(run(&mut shell, "return 4; echo $?"), "1\n");
+        // Like with `exit`, the status must fit in a byte
+        assert_eq!(run(&mut shell, "h() { return 256; }; h; echo $?; h() { return -1; }; h; echo $?"), "2\n2\n");
+    }
+
+    #[test]
+    fn blocks_use_exit_statuses_as_conditions() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        assert_eq!(run(&mut shell, "if false; then echo a; elif true; then echo b; else echo c; fi"), "b\n");
+        assert_eq!(run(&mut shell, "i=; while [ \"$i\" != xxx ]; do i=x$i; done; echo $i"), "xxx\n");
+        assert_eq!(run(&mut shell, "until true; do echo never; done; echo $?"), "0\n");
+        assert_eq!(run(&mut shell, "for w in a 'b c'; do echo $w; done"), "a\nb c\n");
+        assert_eq!(run(&mut shell, "for w in 1 2 3 4; do [ $w = 2 ] && continue; [ $w = 4 ] && break; echo $w; done"), "1\n3\n");
+        assert_eq!(run(&mut shell, "for w in .x y.rs z; do case $w in *.rs|z) echo src;; .*) echo hidden;; esac; done"), "hidden\nsrc\nsrc\n");
+    }
+
+    #[test]
+    fn test_checks_conditions() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        let status = |shell: &mut Shell, source: &str| {
+            run(shell, source);
+            shell.status
+        };
+
+        assert_eq!(status(&mut shell, "[ -d / ]"), 0);
+        assert_eq!(status(&mut shell, "test -f /"), 1);
+        assert_eq!(status(&mut shell, "[ abc = abc ] && [ a != b ]"), 0);
+        assert_eq!(status(&mut shell, "[ 10 -lt 9 ]"), 1);
+        assert_eq!(status(&mut shell, "[ ! -z x ]"), 0);
+        assert_eq!(status(&mut shell, "test ''"), 1);
+        assert_eq!(status(&mut shell, "test"), 1);
+        assert_eq!(status(&mut shell, ": ignored"), 0);
+        // Invalid conditions fail with 2
+        assert_eq!(status(&mut shell, "[ x -lt 1 ]"), 2);
+        assert_eq!(status(&mut shell, "[ -d /"), 2);
+        assert_eq!(status(&mut shell, "test a b c d"), 2);
+    }
+
+    #[test]
+    fn source_keeps_definitions() {
+        let file = env::temp_dir().join(format!("shell-source-{}", std::process::id()));
+        fs::write(&file, "X=$1; f() { echo f $X; }\nshift; echo $#\nreturn 5\necho never\n").unwrap();
+
+        let mut shell = Shell::new(env::temp_dir(), false);
+        assert_eq!(run(&mut shell, &format!(". {} a b c; echo $?", file.display())), "2\n5\n");
+        assert_eq!(run(&mut shell, "f; echo $#"), "f a\n0\n");
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn err_trap_runs_for_failures_outside_conditions() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        run(&mut shell, "trap 'echo failed $?' ERR");
+
+        assert_eq!(run(&mut shell, "false; echo next"), "failed 1\nnext\n");
+        // Conditions and all but the last command of && and || are tested, not failures
+        assert_eq!(run(&mut shell, "if false; then :; fi; false || true; while false; do :; done"), "");
+        assert_eq!(run(&mut shell, "true && false"), "failed 1\n");
+        // Inside functions it only runs for the call
+        assert_eq!(run(&mut shell, "f() { false; echo in; return 3; }; f"), "in\nfailed 3\n");
+        assert_eq!(shell.status, 3);
+    }
+
+    #[test]
+    fn traps_are_shown_and_removed() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        run(&mut shell, "trap 'echo \"it'\\''s\" over' EXIT; trap : ERR");
+        assert_eq!(run(&mut shell, "trap"), "trap -- 'echo \"it'\\''s\" over' EXIT\ntrap -- : ERR\n");
+        assert_eq!(run(&mut shell, "trap -p ERR"), "trap -- : ERR\n");
+
+        // A copy of the shell forgets the traps that run commands
+        assert_eq!(run(&mut shell, "trap | cat"), "");
+
+        let (stdout, capture) = streams::Capture::new().unwrap();
+        let mut io = Streams { stdin: Input::Stdin, stdout, stderr: Output::Stderr };
+        shell.run_exit_trap(&mut io);
+        drop(io);
+        assert_eq!(capture.finish(), b"it's over\n");
+
+        assert_eq!(run(&mut shell, "trap - ERR; trap"), "");
+    }
+
+    #[test]
+    fn assignments_stay_when_built_ins_keep_them() {
+        let dir = env::temp_dir().canonicalize().unwrap();
+        let mut shell = Shell::new(dir.clone(), false);
+
+        assert_eq!(run(&mut shell, "X=1 true; echo \"[$X]\""), "[]\n");
+        assert_eq!(run(&mut shell, "X=2 export X; Y=3 :; echo $X $Y"), "2 3\n");
+        // `cd` sets PWD itself, which isn't undone
+        assert_eq!(run(&mut shell, &format!("PWD=elsewhere cd {}; echo $PWD", dir.display())), format!("{}\n", dir.display()));
+    }
+
+    #[test]
+    fn parameter_errors_end_scripts() {
+        let mut shell = Shell::new(env::temp_dir(), false);
+        assert_eq!(run(&mut shell, "echo a; echo ${Q:?missing}; echo never"), "a\n");
+        assert!(shell.exiting);
+        assert_eq!(shell.status, 1);
+
+        let mut shell = Shell::new(env::temp_dir(), false);
+        shell.interactive = true;
+        assert_eq!(run(&mut shell, "echo ${Q:?missing}; echo next"), "next\n");
+        assert!(!shell.exiting);
+    }
+}
@@ -0,0 +1,352 @@
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(unix)]
+use std::sync::Once;
+
+/// Names of the standard signals on Linux, indexed by their number.
+#[cfg(target_os = "linux")]
+const NAMES: &[&str] = &[
+    "", "SIGHUP", "SIGINT", "SIGQUIT", "SIGILL", "SIGTRAP", "SIGABRT", "SIGBUS", "SIGFPE",
+    "SIGKILL", "SIGUSR1", "SIGSEGV", "SIGUSR2", "SIGPIPE", "SIGALRM", "SIGTERM", "SIGSTKFLT",
+    "SIGCHLD", "SIGCONT", "SIGSTOP", "SIGTSTP", "SIGTTIN", "SIGTTOU", "SIGURG", "SIGXCPU",
+    "SIGXFSZ", "SIGVTALRM", "SIGPROF", "SIGWINCH", "SIGIO", "SIGPWR", "SIGSYS",
+];
+
+#[cfg(not(target_os = "linux"))]
+const NAMES: &[&str] = &[];
+
+/// Gets the name of a signal from its number, like `SIGINT` for 2.
+pub fn name(signal: i32) -> Option<&'static str> {
+    usize::try_from(signal).ok()
+        .and_then(|index| NAMES.get(index))
+        .filter(|name| !name.is_empty())
+        .copied()
+}
+
+/// Converts the way a process finished into an exit status. Processes killed
+/// by a signal get 128 plus the signal number, like in other shells.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    1
+}
+
+/// Gets the signal that killed a process, if it was killed by one.
+#[cfg(unix)]
+pub fn killed_by(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn killed_by(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Describes the signal that killed a process, unless it was an expected one
+/// like an interruption (SIGINT) or a closed pipe (SIGPIPE).
+#[cfg(unix)]
+pub fn termination_message(status: ExitStatus) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    if signal == 2 || signal == 13 {
+        return None;
+    }
+
+    let name = match name(signal) {
+        Some(name) => format!("{name} ({signal})"),
+        None => signal.to_string()
+    };
+
+    let core = if status.core_dumped() { ", core dumped" } else { "" };
+    Some(format!("Terminated by signal {name}{core}"))
+}
+
+#[cfg(not(unix))]
+pub fn termination_message(_status: ExitStatus) -> Option<String> {
+    None
+}
+
+/// The signals of job control, which an interactive shell ignores so only the
+/// job in the foreground is stopped by them.
+#[cfg(unix)]
+const JOB_SIGNALS: [i32; 3] = [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+/// The signals that would end the shell, which are caught when there's an
+/// EXIT trap so it runs before the shell ends.
+#[cfg(unix)]
+pub const FATAL: [i32; 3] = [libc::SIGHUP, libc::SIGINT, libc::SIGTERM];
+
+#[cfg(not(unix))]
+pub const FATAL: [i32; 0] = [];
+
+/// Control-C, which is also what stops a built-in command that waits.
+pub const SIGINT: i32 = 2;
+
+/// Set when Control-C is pressed while the shell runs something by itself, so
+/// it stops instead of being killed.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Whether the shell is interactive, which changes what it does with some signals.
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+/// The signals caught for a trap and the ones ignored with an empty trap, as bit masks.
+static CAUGHT: AtomicU64 = AtomicU64::new(0);
+static IGNORED: AtomicU64 = AtomicU64::new(0);
+/// The signals that were already ignored when the shell started, which stay ignored.
+static INHERITED: AtomicU64 = AtomicU64::new(0);
+/// The caught signals that arrived and whose trap hasn't run yet.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+fn bit(signal: i32) -> u64 {
+    1 << signal
+}
+
+/// Gets the number of a signal from its name, with or without `SIG` and in any
+/// case (like `INT` or `sigint`), or from its number.
+pub fn number(spec: &str) -> Option<i32> {
+    if let Ok(number) = spec.parse() {
+        return name(number).map(|_| number);
+    }
+
+    let spec = spec.to_ascii_uppercase();
+    let spec = if spec.starts_with("SIG") { spec } else { format!("SIG{spec}") };
+    NAMES.iter().position(|name| *name == spec).map(|index| index as i32)
+}
+
+/// The numbers and names of the signals the system has.
+pub fn list() -> impl Iterator<Item = (i32, &'static str)> {
+    NAMES.iter().enumerate()
+        .filter(|(_, name)| !name.is_empty())
+        .map(|(number, name)| (number as i32, *name))
+}
+
+/// Checks whether a trap can be set for a signal. SIGKILL and SIGSTOP can't be
+/// caught or ignored.
+pub fn can_trap(signal: i32) -> bool {
+    name(signal).is_some() && !matches!(name(signal), Some("SIGKILL" | "SIGSTOP"))
+}
+
+/// Checks whether Control-C was pressed, which stops the commands being run.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Checks whether Control-C was pressed and forgets it, once the commands it stopped are over.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Acts as if Control-C was pressed, like when it killed a program in the
+/// foreground: the INT trap runs if there's one, and the commands being run stop otherwise.
+pub fn interrupt() {
+    if CAUGHT.load(Ordering::SeqCst) & bit(SIGINT) != 0 {
+        PENDING.fetch_or(bit(SIGINT), Ordering::SeqCst);
+    }
+    else if IGNORED.load(Ordering::SeqCst) & bit(SIGINT) == 0 {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Takes one of the caught signals that arrived, so its trap can be run.
+pub fn take_pending() -> Option<i32> {
+    let pending = PENDING.load(Ordering::SeqCst);
+    if pending == 0 {
+        return None;
+    }
+
+    let signal = pending.trailing_zeros() as i32;
+    PENDING.fetch_and(!bit(signal), Ordering::SeqCst);
+    Some(signal)
+}
+
+/// Gets the signal that must stop a built-in command that waits, like `wait`:
+/// Control-C, or a signal with a trap.
+pub fn received() -> Option<i32> {
+    if is_interrupted() {
+        return Some(SIGINT);
+    }
+
+    let pending = PENDING.load(Ordering::SeqCst);
+    (pending != 0).then(|| pending.trailing_zeros() as i32)
+}
+
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn on_trapped(signal: libc::c_int) {
+    PENDING.fetch_or(bit(signal), Ordering::SeqCst);
+}
+
+/// Changes what happens when a signal arrives, to `SIG_DFL`, `SIG_IGN` or a handler.
+#[cfg(unix)]
+fn install(signal: i32, handler: libc::sighandler_t) {
+    remember_inherited();
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        libc::sigemptyset(&mut action.sa_mask);
+        // Without SA_RESTART, a read or a wait in progress fails so the signal is noticed
+        action.sa_flags = 0;
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+/// Takes note of the signals ignored when the shell started, before it changes any of them.
+#[cfg(unix)]
+fn remember_inherited() {
+    static ONCE: Once = Once::new();
+
+    ONCE.call_once(|| {
+        for (signal, _) in list() {
+            // Rust's runtime ignores SIGPIPE itself, before the shell starts
+            if signal == libc::SIGPIPE {
+                continue;
+            }
+
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            let ok = unsafe { libc::sigaction(signal, std::ptr::null(), &mut action) } == 0;
+            if ok && action.sa_sigaction == libc::SIG_IGN {
+                INHERITED.fetch_or(bit(signal), Ordering::SeqCst);
+            }
+        }
+    });
+}
+
+/// Checks whether a signal was already ignored when the shell started, like
+/// SIGHUP with `nohup`.
+#[cfg(unix)]
+pub fn was_ignored(signal: i32) -> bool {
+    remember_inherited();
+    INHERITED.load(Ordering::SeqCst) & bit(signal) != 0
+}
+
+#[cfg(not(unix))]
+pub fn was_ignored(_signal: i32) -> bool {
+    false
+}
+
+/// Makes an interactive shell survive the signals sent from the keyboard:
+/// Control-C interrupts what it's running, and Control-\ and Control-Z are
+/// ignored. Programs in the foreground still get them.
+#[cfg(unix)]
+pub fn handle_interactive() {
+    INTERACTIVE.store(true, Ordering::SeqCst);
+    for signal in [libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP] {
+        reset(signal);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn handle_interactive() {
+    INTERACTIVE.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+pub fn ignore_job_signals() {
+    for signal in JOB_SIGNALS {
+        install(signal, libc::SIG_IGN);
+    }
+}
+
+/// Makes the shell take note of a signal when it arrives, so its trap runs.
+pub fn catch(signal: i32) {
+    CAUGHT.fetch_or(bit(signal), Ordering::SeqCst);
+    IGNORED.fetch_and(!bit(signal), Ordering::SeqCst);
+
+    #[cfg(unix)]
+    install(signal, on_trapped as extern "C" fn(libc::c_int) as libc::sighandler_t);
+}
+
+/// Makes the shell and the programs it runs ignore a signal.
+pub fn ignore(signal: i32) {
+    IGNORED.fetch_or(bit(signal), Ordering::SeqCst);
+    CAUGHT.fetch_and(!bit(signal), Ordering::SeqCst);
+    PENDING.fetch_and(!bit(signal), Ordering::SeqCst);
+
+    #[cfg(unix)]
+    install(signal, libc::SIG_IGN);
+}
+
+/// Gives a signal the behavior it has without a trap: the default one, or the
+/// one an interactive shell needs.
+pub fn reset(signal: i32) {
+    CAUGHT.fetch_and(!bit(signal), Ordering::SeqCst);
+    IGNORED.fetch_and(!bit(signal), Ordering::SeqCst);
+    PENDING.fetch_and(!bit(signal), Ordering::SeqCst);
+
+    #[cfg(unix)]
+    {
+        let interactive = is_interactive();
+        let handler = if was_ignored(signal) {
+            libc::SIG_IGN
+        }
+        else if interactive && signal == libc::SIGINT {
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t
+        }
+        else if interactive && (signal == libc::SIGQUIT || JOB_SIGNALS.contains(&signal)) {
+            libc::SIG_IGN
+        }
+        else {
+            libc::SIG_DFL
+        };
+
+        install(signal, handler);
+    }
+}
+
+/// Gives back the default behavior to the signals the shell changed. Programs
+/// would keep ignoring or catching them otherwise, so it's done before running
+/// them. The signals ignored with a trap or when the shell started stay ignored.
+/// SIGPIPE is ignored by Rust's runtime, so it's reset too: a command writing
+/// into a closed pipe should end.
+#[cfg(unix)]
+pub fn restore_defaults() {
+    let kept = IGNORED.load(Ordering::SeqCst) | INHERITED.load(Ordering::SeqCst);
+    let caught = CAUGHT.swap(0, Ordering::SeqCst);
+    PENDING.store(0, Ordering::SeqCst);
+    INTERACTIVE.store(false, Ordering::SeqCst);
+
+    for signal in 1..64 {
+        let changed = caught & bit(signal) != 0 || JOB_SIGNALS.contains(&signal) || signal == libc::SIGINT || signal == libc::SIGQUIT
+            || signal == libc::SIGPIPE;
+        if changed && kept & bit(signal) == 0 {
+            unsafe { libc::signal(signal, libc::SIG_DFL) };
+        }
+    }
+}
+
+/// Ends the shell with a signal, so the program that started it knows it was
+/// killed. It's used once the EXIT trap ran for a signal caught only for it.
+pub fn die(signal: i32) -> ! {
+    use std::io::Write;
+
+    let _ = std::io::stdout().flush();
+
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+
+    std::process::exit(128 + signal)
+}
@@ -0,0 +1,278 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::process::Stdio;
+use std::thread::{self, JoinHandle};
+
+use crate::signals;
+
+/// Writes a line to the standard output of a set of streams, ignoring errors
+/// like the ones caused by a closed pipe.
+macro_rules! outln {
+    ($io:expr) => {
+        outln!($io, "")
+    };
+    ($io:expr, $($arg:tt)*) => {{
+        let _ = ::std::io::Write::write_fmt(&mut $io.stdout, format_args!("{}\n", format_args!($($arg)*)));
+    }};
+}
+
+/// Writes a line to the standard error of a set of streams.
+macro_rules! errln {
+    ($io:expr, $($arg:tt)*) => {{
+        let _ = ::std::io::Write::write_fmt(&mut $io.stderr, format_args!("{}\n", format_args!($($arg)*)));
+    }};
+}
+
+pub enum Input {
+    /// The standard input of the shell.
+    Stdin,
+    /// A file or the reading end of a pipe.
+    File(File),
+}
+
+pub enum Output {
+    /// The standard output of the shell.
+    Stdout,
+    /// The standard error of the shell.
+    Stderr,
+    /// A file or the writing end of a pipe.
+    File(File),
+}
+
+impl Input {
+    pub fn try_clone(&self) -> io::Result<Input> {
+        match self {
+            Input::Stdin => Ok(Input::Stdin),
+            Input::File(file) => Ok(Input::File(file.try_clone()?))
+        }
+    }
+
+    pub fn stdio(&self) -> io::Result<Stdio> {
+        match self {
+            Input::Stdin => Ok(Stdio::inherit()),
+            Input::File(file) => Ok(Stdio::from(file.try_clone()?))
+        }
+    }
+
+    /// Reads a line without its line break, or `None` at the end of the input.
+    /// Control-C gives up with an `Interrupted` error. Files are read a byte at a
+    /// time, so what comes after the line is left for the commands reading next.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let bytes = match self {
+            Input::Stdin => read_stdin_line()?,
+            Input::File(file) => read_file_line(file)?
+        };
+
+        let Some(bytes) = bytes else {
+            return Ok(None);
+        };
+
+        let line = String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok(Some(line.trim_end_matches('\r').to_owned()))
+    }
+}
+
+fn read_stdin_line() -> io::Result<Option<Vec<u8>>> {
+    // The lock reads through a buffer, which unlike `read_line` gives up when it's interrupted
+    let mut stdin = io::stdin().lock();
+    let mut bytes = vec![];
+    loop {
+        let available = match stdin.fill_buf() {
+            Ok(available) => available,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                if signals::is_interrupted() {
+                    return Err(error);
+                }
+                continue;
+            },
+            Err(error) => return Err(error)
+        };
+
+        if available.is_empty() {
+            return Ok((!bytes.is_empty()).then_some(bytes));
+        }
+
+        match available.iter().position(|&byte| byte == b'\n') {
+            Some(index) => {
+                bytes.extend_from_slice(&available[..index]);
+                stdin.consume(index + 1);
+                return Ok(Some(bytes));
+            },
+            None => {
+                let length = available.len();
+                bytes.extend_from_slice(available);
+                stdin.consume(length);
+            }
+        }
+    }
+}
+
+fn read_file_line(mut file: &File) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = vec![];
+    let mut byte = [0];
+    loop {
+        match file.read(&mut byte) {
+            Ok(0) => return Ok((!bytes.is_empty()).then_some(bytes)),
+            Ok(_) if byte[0] == b'\n' => return Ok(Some(bytes)),
+            Ok(_) => bytes.push(byte[0]),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted && !signals::is_interrupted() => continue,
+            Err(error) => return Err(error)
+        }
+    }
+}
+
+impl Output {
+    pub fn try_clone(&self) -> io::Result<Output> {
+        match self {
+            Output::Stdout => Ok(Output::Stdout),
+            Output::Stderr => Ok(Output::Stderr),
+            Output::File(file) => Ok(Output::File(file.try_clone()?))
+        }
+    }
+
+    pub fn stdio(&self) -> io::Result<Stdio> {
+        match self {
+            Output::Stdout => Ok(Stdio::from(io::stdout())),
+            Output::Stderr => Ok(Stdio::from(io::stderr())),
+            Output::File(file) => Ok(Stdio::from(file.try_clone()?))
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout => io::stdout().write(buf),
+            Output::Stderr => io::stderr().write(buf),
+            Output::File(file) => file.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().flush(),
+            Output::Stderr => io::stderr().flush(),
+            Output::File(file) => file.flush()
+        }
+    }
+}
+
+/// The standard streams a command reads from and writes to.
+pub struct Streams {
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+}
+
+impl Streams {
+    /// The streams the shell itself was started with.
+    pub fn standard() -> Streams {
+        Streams {
+            stdin: Input::Stdin,
+            stdout: Output::Stdout,
+            stderr: Output::Stderr,
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Streams> {
+        Ok(Streams {
+            stdin: self.stdin.try_clone()?,
+            stdout: self.stdout.try_clone()?,
+            stderr: self.stderr.try_clone()?,
+        })
+    }
+
+    /// Gets the stream behind a file descriptor number, as an output.
+    pub fn output(&self, descriptor: u32) -> io::Result<Output> {
+        match descriptor {
+            1 => self.stdout.try_clone(),
+            2 => self.stderr.try_clone(),
+            _ => Err(io::Error::other(format!("Bad file descriptor {descriptor}")))
+        }
+    }
+
+    pub fn set_output(&mut self, descriptor: u32, output: Output) -> io::Result<()> {
+        match descriptor {
+            1 => self.stdout = output,
+            2 => self.stderr = output,
+            _ => return Err(io::Error::other(format!("Bad file descriptor {descriptor}")))
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates an anonymous pipe, returning its reading and writing ends. They're
+/// closed when a program starts, so programs only get the ones passed to them.
+#[cfg(unix)]
+pub fn pipe() -> io::Result<(File, File)> {
+    use std::os::fd::FromRawFd;
+
+    let mut ends = [0; 2];
+    if unsafe { libc::pipe(ends.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let (reader, writer) = unsafe { (File::from_raw_fd(ends[0]), File::from_raw_fd(ends[1])) };
+    for end in ends {
+        if unsafe { libc::fcntl(end, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((reader, writer))
+}
+
+/// Creates an anonymous pipe, returning its reading and writing ends. They can't
+/// be inherited, so programs only get the ones passed to them.
+#[cfg(windows)]
+pub fn pipe() -> io::Result<(File, File)> {
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreatePipe(reader: *mut RawHandle, writer: *mut RawHandle, attributes: *mut std::ffi::c_void, size: u32) -> i32;
+    }
+
+    let (mut reader, mut writer) = (std::ptr::null_mut(), std::ptr::null_mut());
+    if unsafe { CreatePipe(&mut reader, &mut writer, std::ptr::null_mut(), 0) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { (File::from_raw_handle(reader), File::from_raw_handle(writer)) })
+}
+
+/// Collects everything written to an output in the background.
+pub struct Capture {
+    handle: JoinHandle<Vec<u8>>,
+}
+
+impl Capture {
+    /// Creates the output to be captured. The capture ends once every copy of the
+    /// output (including the ones held by child processes) has been closed.
+    pub fn new() -> io::Result<(Output, Capture)> {
+        let (mut reader, writer) = pipe()?;
+        let handle = thread::spawn(move || {
+            let mut bytes = vec![];
+            let _ = reader.read_to_end(&mut bytes);
+            bytes
+        });
+
+        Ok((Output::File(writer), Capture { handle }))
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.handle.join().unwrap_or_default()
+    }
+}
+
+/// Creates an input that yields the given bytes, written from the background.
+#[cfg(not(unix))]
+pub fn feed(bytes: Vec<u8>) -> io::Result<Input> {
+    let (reader, mut writer) = pipe()?;
+    thread::spawn(move || {
+        let _ = writer.write_all(&bytes);
+    });
+
+    Ok(Input::File(reader))
+}
@@ -0,0 +1,288 @@
+use std::io;
+
+/// A key pressed in the terminal, decoded from the bytes it sends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    /// A letter pressed with Control, like `Ctrl('a')`.
+    Ctrl(char),
+    /// A character pressed with Alt (or after Escape).
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    /// Alt with Backspace.
+    AltBackspace,
+    Delete,
+    Escape,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// Control with the left arrow, which moves a word back.
+    WordLeft,
+    /// Control with the right arrow, which moves a word forward.
+    WordRight,
+    /// A sequence that doesn't mean anything to the editor.
+    Unknown,
+}
+
+/// How long to wait for the rest of an escape sequence before taking Escape as a key.
+const ESCAPE_TIMEOUT: i32 = 50;
+
+/// Keeps the terminal in raw mode, where keys are read one by one without being
+/// shown, until it's dropped.
+#[cfg(unix)]
+pub struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    /// Turns off the line buffering, echo and signal keys of the terminal.
+    /// Output is still processed, so `\n` goes to the start of the next line.
+    pub fn enable() -> io::Result<RawMode> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        raw.c_cflag |= libc::CS8;
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        // Draining instead of flushing keeps what was typed (or pasted) ahead
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original) };
+    }
+}
+
+#[cfg(not(unix))]
+pub struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// The amount of columns of the terminal, or 80 when it can't be known.
+#[cfg(unix)]
+pub fn width() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => size.ws_col as usize,
+        _ => 80
+    }
+}
+
+#[cfg(not(unix))]
+pub fn width() -> usize {
+    80
+}
+
+/// Reads a byte from the standard input without buffering it, so nothing typed
+/// ahead is lost for the commands run after. `None` means the input ended.
+#[cfg(unix)]
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = 0u8;
+    loop {
+        match unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } {
+            1 => return Ok(Some(byte)),
+            0 => return Ok(None),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn read_byte() -> io::Result<Option<u8>> {
+    use std::io::Read;
+
+    let mut byte = [0u8];
+    match io::stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0]))
+    }
+}
+
+/// Checks whether more input comes within some milliseconds.
+#[cfg(unix)]
+fn input_within(milliseconds: i32) -> bool {
+    let mut poll = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    unsafe { libc::poll(&mut poll, 1, milliseconds) > 0 }
+}
+
+#[cfg(not(unix))]
+fn input_within(_milliseconds: i32) -> bool {
+    false
+}
+
+/// Reads the next key from the terminal, which must be in raw mode.
+/// `None` means the input ended.
+pub fn read_key() -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte()? else {
+        return Ok(None);
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7F | 0x08 => Key::Backspace,
+        0x1B => read_escape()?,
+        0x1F => Key::Ctrl('_'),
+        0x01..=0x1A => Key::Ctrl((b'a' + byte - 1) as char),
+        0x00..=0x1F => Key::Unknown,
+        0x20..=0x7E => Key::Char(byte as char),
+        _ => read_utf8(byte)?
+    };
+
+    Ok(Some(key))
+}
+
+/// Reads what comes after an Escape: a sequence sent by a special key, a key
+/// pressed with Alt, or nothing when Escape was pressed alone.
+fn read_escape() -> io::Result<Key> {
+    if !input_within(ESCAPE_TIMEOUT) {
+        return Ok(Key::Escape);
+    }
+
+    let Some(byte) = read_byte()? else {
+        return Ok(Key::Escape);
+    };
+
+    match byte {
+        b'[' => read_csi(),
+        b'O' => Ok(match read_byte()? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown
+        }),
+        0x7F | 0x08 => Ok(Key::AltBackspace),
+        0x20..=0x7E => Ok(Key::Alt(byte as char)),
+        _ => Ok(Key::Unknown)
+    }
+}
+
+/// Reads a control sequence like `ESC [ 1 ; 5 C`, made of numeric parameters
+/// and a final letter.
+fn read_csi() -> io::Result<Key> {
+    let mut parameters = String::new();
+    let last = loop {
+        match read_byte()? {
+            Some(byte @ 0x40..=0x7E) => break byte,
+            Some(byte) => parameters.push(byte as char),
+            None => return Ok(Key::Unknown)
+        }
+    };
+
+    // A modifier of 3 is Alt and 5 is Control, which move by words with the arrows
+    let modified = parameters.split(';').nth(1).is_some_and(|modifier| modifier == "3" || modifier == "5");
+
+    Ok(match (last, parameters.as_str()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) if modified => Key::WordRight,
+        (b'D', _) if modified => Key::WordLeft,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) => Key::Home,
+        (b'F', _) => Key::End,
+        (b'~', "1" | "7") => Key::Home,
+        (b'~', "4" | "8") => Key::End,
+        (b'~', "3") => Key::Delete,
+        _ => Key::Unknown
+    })
+}
+
+/// Reads the rest of a character encoded in several bytes.
+fn read_utf8(first: u8) -> io::Result<Key> {
+    let length = match first {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return Ok(Key::Unknown)
+    };
+
+    let mut bytes = vec![first];
+    for _ in 1..length {
+        match read_byte()? {
+            Some(byte) => bytes.push(byte),
+            None => return Ok(Key::Unknown)
+        }
+    }
+
+    Ok(match std::str::from_utf8(&bytes).ok().and_then(|text| text.chars().next()) {
+        Some(c) => Key::Char(c),
+        None => Key::Unknown
+    })
+}
+
+/// The amount of columns a character takes in the terminal: none for combining
+/// marks, two for wide characters like the ones of East Asian languages and
+/// most emoji, and one for the rest.
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F | 0x1F900..=0x1F9FF | 0x20000..=0x3FFFD => 2,
+        _ => 1
+    }
+}
+
+/// The characters of some text that are shown in the terminal, leaving out
+/// ANSI escape sequences like the ones that change colors.
+pub fn visible_chars(text: &str) -> Vec<char> {
+    let mut visible = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1B' {
+            visible.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // Control sequences end with a letter, and operating system commands with BEL or ST
+            Some('[') => {
+                while chars.next().is_some_and(|c| !('\x40'..='\x7E').contains(&c)) {}
+            },
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1B' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+
+    visible
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::shell::Shell;
+use crate::signals;
+use crate::streams::Streams;
+
+/// What a trap is set for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    /// The shell exiting, at the end of a script or with `exit`.
+    Exit,
+    /// A signal arriving, by its number.
+    Signal(i32),
+    /// A command failing, outside the conditions of `if`, `while` and `until`.
+    Err,
+}
+
+impl Condition {
+    /// Reads a condition written like `EXIT` (or 0), `ERR`, or a signal like `INT`, `SIGINT` or 2.
+    pub fn parse(spec: &str) -> Option<Condition> {
+        match spec.to_ascii_uppercase().as_str() {
+            "EXIT" | "0" => Some(Condition::Exit),
+            "ERR" => Some(Condition::Err),
+            _ => signals::number(spec).map(Condition::Signal)
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Exit => write!(f, "EXIT"),
+            Condition::Err => write!(f, "ERR"),
+            Condition::Signal(signal) => match signals::name(*signal) {
+                Some(name) => write!(f, "{name}"),
+                None => write!(f, "{signal}")
+            }
+        }
+    }
+}
+
+/// The commands set with `trap`. An empty command makes the shell ignore the signal.
+#[derive(Debug, Default, Clone)]
+pub struct Traps {
+    actions: BTreeMap<Condition, String>,
+    /// Set while a trap runs, so traps don't run inside it.
+    running: bool,
+}
+
+impl Traps {
+    pub fn get(&self, condition: Condition) -> Option<&str> {
+        self.actions.get(&condition).map(String::as_str)
+    }
+
+    /// The conditions with a trap, in the order they're shown.
+    pub fn conditions(&self) -> Vec<Condition> {
+        self.actions.keys().copied().collect()
+    }
+
+    /// Sets the commands run for a condition, catching or ignoring the signal.
+    pub fn set(&mut self, condition: Condition, action: &str) -> Result<(), String> {
+        if let Condition::Signal(signal) = condition {
+            if !signals::can_trap(signal) {
+                return Err(format!("{condition} can't be trapped"));
+            }
+
+            match action.is_empty() {
+                true => signals::ignore(signal),
+                false => signals::catch(signal)
+            }
+        }
+
+        self.actions.insert(condition, action.to_owned());
+        self.update_fatal();
+        Ok(())
+    }
+
+    /// Removes the trap of a condition, giving the signal its usual behavior back.
+    pub fn reset(&mut self, condition: Condition) {
+        self.actions.remove(&condition);
+        if let Condition::Signal(signal) = condition {
+            signals::reset(signal);
+        }
+        self.update_fatal();
+    }
+
+    /// Puts back the traps saved before running some commands whose changes are
+    /// undone, along with what they made the signals do.
+    pub fn restore(&mut self, saved: Traps) {
+        for condition in self.conditions() {
+            if saved.get(condition).is_none() {
+                self.reset(condition);
+            }
+        }
+
+        for (condition, action) in &saved.actions {
+            if self.get(*condition) != Some(action.as_str()) {
+                let _ = self.set(*condition, action);
+            }
+        }
+    }
+
+    /// Catches the signals that would end the shell while there's an EXIT trap,
+    /// so it runs before the shell ends. Interactive shells aren't ended by
+    /// Control-C, and signals ignored since the shell started don't end it either.
+    fn update_fatal(&self) {
+        let exit = self.get(Condition::Exit).is_some_and(|action| !action.is_empty());
+        for signal in signals::FATAL {
+            let ignored = signals::was_ignored(signal) || (signals::is_interactive() && signal == signals::SIGINT);
+            if ignored || self.actions.contains_key(&Condition::Signal(signal)) {
+                continue;
+            }
+
+            match exit {
+                true => signals::catch(signal),
+                false => signals::reset(signal)
+            }
+        }
+    }
+}
+
+impl Shell {
+    /// Runs the traps of the signals that arrived. A signal caught only for the
+    /// EXIT trap runs it and then ends the shell.
+    pub fn run_traps(&mut self, io: &mut Streams) {
+        if self.traps.running {
+            return;
+        }
+
+        while let Some(signal) = signals::take_pending() {
+            match self.traps.get(Condition::Signal(signal)) {
+                Some(action) => {
+                    let action = action.to_owned();
+                    self.run_trap(&action, io);
+                },
+                None => {
+                    self.run_exit_trap(io);
+                    signals::die(signal);
+                }
+            }
+        }
+    }
+
+    /// Runs the ERR trap after a command failed.
+    pub fn run_err_trap(&mut self, io: &mut Streams) {
+        if self.traps.running {
+            return;
+        }
+
+        if let Some(action) = self.traps.get(Condition::Err).filter(|action| !action.is_empty()) {
+            let action = action.to_owned();
+            self.run_trap(&action, io);
+        }
+    }
+
+    /// Runs the EXIT trap, once, when the shell is about to end.
+    pub fn run_exit_trap(&mut self, io: &mut Streams) {
+        let Some(action) = self.traps.actions.remove(&Condition::Exit) else {
+            return;
+        };
+
+        // The trap runs completely, whatever stopped the commands before it
+        self.exiting = false;
+        self.flow = None;
+        signals::take_interrupt();
+        self.run_trap(&action, io);
+    }
+
+    /// Runs the commands of a trap. `$?` is kept as it was, unless they use `exit`.
+    fn run_trap(&mut self, action: &str, io: &mut Streams) {
+        let status = self.status;
+        let list = match self.parse(action) {
+            Ok(list) => list,
+            Err(error) => {
+                errln!(io, "trap: Invalid syntax: {error}.");
+                return;
+            }
+        };
+
+        self.traps.running = true;
+        self.run_list(&list, io);
+        self.traps.running = false;
+
+        if !self.exiting {
+            self.status = status;
+        }
+    }
+}
@@ -0,0 +1,99 @@
+/// Gets the home directory of the user running the shell from the system,
+/// without looking at the HOME variable.
+#[cfg(unix)]
+pub fn current_home() -> Option<String> {
+    let uid = unsafe { libc::getuid() };
+    passwd_field(|entry| entry.pw_dir, |entry, buffer, length, result| unsafe {
+        libc::getpwuid_r(uid, entry, buffer, length, result)
+    })
+}
+
+#[cfg(not(unix))]
+pub fn current_home() -> Option<String> {
+    std::env::var("USERPROFILE").ok()
+}
+
+/// Gets the home directory of a user from the passwd database.
+#[cfg(unix)]
+pub fn user_home(name: &str) -> Option<String> {
+    let name = std::ffi::CString::new(name).ok()?;
+    passwd_field(|entry| entry.pw_dir, |entry, buffer, length, result| unsafe {
+        libc::getpwnam_r(name.as_ptr(), entry, buffer, length, result)
+    })
+}
+
+#[cfg(not(unix))]
+pub fn user_home(_name: &str) -> Option<String> {
+    None
+}
+
+/// Gets the name of the user running the shell from the system.
+#[cfg(unix)]
+pub fn current_user() -> Option<String> {
+    let uid = unsafe { libc::geteuid() };
+    passwd_field(|entry| entry.pw_name, |entry, buffer, length, result| unsafe {
+        libc::getpwuid_r(uid, entry, buffer, length, result)
+    })
+}
+
+#[cfg(not(unix))]
+pub fn current_user() -> Option<String> {
+    std::env::var("USERNAME").ok()
+}
+
+/// Checks whether the shell runs as the superuser.
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_root() -> bool {
+    false
+}
+
+/// Gets the name of the computer.
+#[cfg(unix)]
+pub fn host_name() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return None;
+    }
+
+    let length = buffer.iter().position(|byte| *byte == 0).unwrap_or(buffer.len());
+    String::from_utf8(buffer[..length].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+pub fn host_name() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Runs a passwd lookup, growing its buffer until the entry fits in it, and
+/// takes a field from the entry.
+#[cfg(unix)]
+fn passwd_field(
+    field: impl Fn(&libc::passwd) -> *mut libc::c_char,
+    lookup: impl Fn(*mut libc::passwd, *mut libc::c_char, usize, *mut *mut libc::passwd) -> libc::c_int,
+) -> Option<String> {
+    use std::ffi::CStr;
+
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let error = lookup(&mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result);
+
+        if error == libc::ERANGE && buffer.len() < 1 << 20 {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+
+        if error != 0 || result.is_null() || field(&entry).is_null() {
+            return None;
+        }
+
+        let value = unsafe { CStr::from_ptr(field(&entry)) };
+        return value.to_str().ok().map(str::to_owned);
+    }
+}
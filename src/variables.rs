@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: String,
+    /// Exported variables are passed to the programs started by the shell.
+    pub exported: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    values: BTreeMap<String, Variable>,
+    /// The variables replaced by local ones in each running function, to be
+    /// restored when it returns.
+    scopes: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+impl Variables {
+    /// Creates the variables out of the environment of the shell process, all of them exported.
+    pub fn from_environment() -> Variables {
+        let mut variables = Variables::default();
+        for (name, value) in env::vars_os() {
+            if let (Ok(name), Ok(value)) = (name.into_string(), value.into_string()) {
+                variables.values.insert(name, Variable { value, exported: true });
+            }
+        }
+
+        variables
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|variable| variable.value.as_str())
+    }
+
+    /// Sets the value of a variable, keeping it exported if it already was.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.values.get_mut(name) {
+            Some(variable) => variable.value = value.to_owned(),
+            None => {
+                self.values.insert(name.to_owned(), Variable { value: value.to_owned(), exported: false });
+            }
+        }
+    }
+
+    /// Marks a variable as exported, creating it empty if it doesn't exist.
+    pub fn export(&mut self, name: &str) {
+        self.values.entry(name.to_owned())
+            .or_insert(Variable { value: String::new(), exported: true })
+            .exported = true;
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.values.remove(name);
+    }
+
+    /// Starts the scope of a function call.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    /// Ends the scope of a function call, restoring the variables it made local.
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            self.restore(scope);
+        }
+    }
+
+    /// Sets and exports some variables for a while, like the assignments written
+    /// before a built-in command. Returns what they were, to be given to `restore`.
+    pub fn set_temporarily(&mut self, assignments: &[(String, String)]) -> Vec<(String, Option<Variable>)> {
+        let mut saved = vec![];
+        for (name, value) in assignments {
+            saved.push((name.clone(), self.values.get(name).cloned()));
+            self.values.insert(name.clone(), Variable { value: value.clone(), exported: true });
+        }
+
+        saved
+    }
+
+    /// Puts back the variables saved before changing them.
+    pub fn restore(&mut self, saved: Vec<(String, Option<Variable>)>) {
+        for (name, variable) in saved.into_iter().rev() {
+            match variable {
+                Some(variable) => self.values.insert(name, variable),
+                None => self.values.remove(&name)
+            };
+        }
+    }
+
+    /// Puts back the variables saved by `set_temporarily`, except the ones that
+    /// were changed since then.
+    pub fn restore_unchanged(&mut self, saved: Vec<(String, Option<Variable>)>, assignments: &[(String, String)]) {
+        let assigned = |name: &str| assignments.iter().rev().find(|(assigned, _)| assigned == name).map(|(_, value)| value.as_str());
+        let saved = saved.into_iter()
+            .filter(|(name, _)| self.get(name) == assigned(name))
+            .collect();
+        self.restore(saved);
+    }
+
+    /// Makes a variable local to the current function, starting empty and not
+    /// exported. Returns false when no function is running.
+    pub fn make_local(&mut self, name: &str) -> bool {
+        let Some(scope) = self.scopes.last_mut() else {
+            return false;
+        };
+
+        if !scope.iter().any(|(saved, _)| saved == name) {
+            scope.push((name.to_owned(), self.values.get(name).cloned()));
+        }
+
+        self.values.insert(name.to_owned(), Variable { value: String::new(), exported: false });
+        true
+    }
+
+    /// All the variables, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.values.iter()
+    }
+
+    /// The variables to be passed to a program, sorted by name.
+    pub fn environment(&self) -> Vec<(String, String)> {
+        self.values.iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, variable)| (name.clone(), variable.value.clone()))
+            .collect()
+    }
+}
+
+/// Checks that a name can be used for a variable: letters, digits and underscores,
+/// not starting with a digit.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => chars.all(|c| c == '_' || c.is_ascii_alphanumeric()),
+        _ => false
+    }
+}
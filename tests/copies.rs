@@ -0,0 +1,29 @@
+//! Runs the shell itself, since the commands of a pipeline that the shell runs
+//! by itself and the jobs in the background run in copies of it.
+#![cfg(unix)]
+
+use std::process::Command;
+
+fn run(source: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_shell")).args(["-c", source]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn blocks_and_functions_can_be_piped() {
+    // Blocks can be piped like any other command
+    assert_eq!(run("{ echo b; echo a; } | sort"), "a\nb\n");
+    assert_eq!(run("x='a  b'; f() { echo \"$x|$1\"; }; f 'c  d' | cat"), "a  b|c  d\n");
+    assert_eq!(run("alias hi='echo hi'\nhi there | cat"), "hi there\n");
+    // The copy ends when the next command stops reading
+    assert_eq!(run("while true; do echo y; done | head -2; echo $?"), "y\ny\n0\n");
+}
+
+#[test]
+fn jobs_keep_the_state_of_the_shell() {
+    let output = run("v='a  b'; f() { echo \"$v $1 $$\"; }; f \"${1:-c}\" & wait; echo \"$$ $!\"");
+    let lines: Vec<&str> = output.lines().collect();
+    let (shell, job) = lines[1].split_once(' ').unwrap();
+    assert_eq!(lines[0], format!("a  b c {shell}"));
+    assert_ne!(job, shell);
+}